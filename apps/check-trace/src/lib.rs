@@ -0,0 +1,54 @@
+//! Liquid Nation Check Trace
+//!
+//! A drop-in `check!` for the app contracts that also records which
+//! condition failed, so the spell simulator can report it. Apps only use it
+//! with their `trace` feature; contract builds keep `charms_sdk`'s `check!`
+//! and their verification keys.
+
+use std::cell::RefCell;
+
+thread_local! {
+    static FAILED_CHECKS: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Record a condition that did not hold
+pub fn record(condition: &'static str) {
+    FAILED_CHECKS.with(|checks| checks.borrow_mut().push(condition));
+}
+
+/// Take the conditions recorded on this thread, innermost first
+pub fn take() -> Vec<&'static str> {
+    FAILED_CHECKS.with(|checks| std::mem::take(&mut *checks.borrow_mut()))
+}
+
+/// Same as `charms_sdk::data::check!`, but also records the failed
+/// condition
+#[macro_export]
+macro_rules! check {
+    ($condition:expr) => {
+        if !$condition {
+            $crate::record(stringify!($condition));
+            eprintln!("condition does not hold: {}", stringify!($condition));
+            return false;
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positive(n: i32) -> bool {
+        check!(n > 0);
+        true
+    }
+
+    #[test]
+    fn test_records_failed_checks() {
+        assert!(positive(1));
+        assert!(take().is_empty());
+        assert!(!positive(-1));
+        assert_eq!(take(), vec!["n > 0"]);
+        assert!(take().is_empty());
+    }
+}
@@ -10,13 +10,22 @@
 //! - Refund mechanism for expired/cancelled escrows
 
 use charms_sdk::data::{
-    charm_values, check, sum_token_amount, App, Data, Transaction, B32, TOKEN,
+    charm_values, sum_token_amount, App, Data, Transaction, B32, TOKEN,
 };
 use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
 use sha2::{Digest, Sha256};
 
+#[cfg(not(feature = "trace"))]
+use charms_sdk::data::check;
+#[cfg(feature = "trace")]
+use liquid_check_trace::check;
+
 /// Escrow status
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+///
+/// Encoded as its discriminant (`status: 0`), as in the spell templates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
 pub enum EscrowStatus {
     /// Escrow is active and holding funds
     Active = 0,
@@ -30,8 +39,9 @@ pub enum EscrowStatus {
     Disputed = 4,
 }
 
-/// Escrow type
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// Escrow type, encoded as its discriminant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
 pub enum EscrowType {
     /// Simple 2-party escrow (depositor -> recipient)
     TwoParty = 0,
@@ -162,12 +172,9 @@ fn validate_escrow_creation(app: &App, tx: &Transaction, w: &Data) -> bool {
     check!(!escrow.recipient_pubkey.is_empty());
 
     // Validate escrow type requirements
-    match escrow.escrow_type {
-        EscrowType::TwoOfThree => {
-            check!(escrow.arbiter_pubkey.is_some());
-            check!(!escrow.arbiter_pubkey.as_ref().unwrap().is_empty());
-        }
-        _ => {}
+    if escrow.escrow_type == EscrowType::TwoOfThree {
+        check!(escrow.arbiter_pubkey.is_some());
+        check!(!escrow.arbiter_pubkey.as_ref().unwrap().is_empty());
     }
 
     // Verify the held tokens are actually in the escrow output
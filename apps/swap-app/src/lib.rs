@@ -4,14 +4,23 @@
 //! Enables trustless cross-chain asset swaps without liquidity pools.
 
 use charms_sdk::data::{
-    charm_values, check, sum_token_amount, App, Data, Transaction, UtxoId, B32, TOKEN,
+    charm_values, sum_token_amount, App, Data, Transaction, UtxoId, B32, TOKEN,
 };
 use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
 use sha2::{Digest, Sha256};
 use std::str::FromStr;
 
+#[cfg(not(feature = "trace"))]
+use charms_sdk::data::check;
+#[cfg(feature = "trace")]
+use liquid_check_trace::check;
+
 /// Order status enumeration
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+///
+/// Encoded as its discriminant (`status: 0`), as in the spell templates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
 pub enum OrderStatus {
     Open = 0,
     Filled = 1,
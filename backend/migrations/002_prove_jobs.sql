@@ -0,0 +1,18 @@
+-- Liquid Nation Database Schema
+-- Asynchronous proving jobs
+
+CREATE TABLE IF NOT EXISTS prove_jobs (
+    id VARCHAR(255) PRIMARY KEY,
+    request_hash VARCHAR(64) NOT NULL,
+    request TEXT NOT NULL,
+    status VARCHAR(50) NOT NULL DEFAULT 'queued',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    result TEXT,
+    error TEXT,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_prove_jobs_hash ON prove_jobs(request_hash);
+CREATE INDEX IF NOT EXISTS idx_prove_jobs_due ON prove_jobs(status, next_attempt_at);
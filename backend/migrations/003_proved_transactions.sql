@@ -0,0 +1,6 @@
+-- Liquid Nation Database Schema
+-- Proved transactions are kept per order so signed versions can be checked
+
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS prove_job_id VARCHAR(255);
+
+CREATE INDEX IF NOT EXISTS idx_transactions_prove_job ON transactions(prove_job_id);
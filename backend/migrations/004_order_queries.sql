@@ -0,0 +1,16 @@
+-- Liquid Nation Database Schema
+-- Orders are filtered, sorted and paged in SQL
+
+-- Price in want units per offered unit, for sorting the orderbook
+ALTER TABLE orders ADD COLUMN IF NOT EXISTS price NUMERIC GENERATED ALWAYS AS (
+    CASE WHEN offer_amount ~ '^[0-9]+$' AND want_amount ~ '^[0-9]+$' AND offer_amount::numeric > 0
+        THEN want_amount::numeric / offer_amount::numeric
+        ELSE 0
+    END
+) STORED;
+
+CREATE INDEX IF NOT EXISTS idx_orders_created ON orders(created_at, id);
+CREATE INDEX IF NOT EXISTS idx_orders_status_created ON orders(status, created_at, id);
+CREATE INDEX IF NOT EXISTS idx_orders_pair_price ON orders(offer_token, want_token, price, id);
+CREATE INDEX IF NOT EXISTS idx_orders_chains ON orders(source_chain, dest_chain);
+CREATE INDEX IF NOT EXISTS idx_orders_expiry ON orders(status, expiry_height);
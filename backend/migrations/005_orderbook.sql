@@ -0,0 +1,4 @@
+-- Liquid Nation Database Schema
+-- Pair lookups of the orderbook ignore the case of token symbols
+
+CREATE INDEX IF NOT EXISTS idx_orders_pair_upper ON orders(UPPER(offer_token), UPPER(want_token), status);
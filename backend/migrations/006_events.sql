@@ -0,0 +1,14 @@
+-- Liquid Nation Database Schema
+-- Order and escrow changes, replayed to streaming clients that reconnect
+
+CREATE TABLE IF NOT EXISTS events (
+    id BIGSERIAL PRIMARY KEY,
+    kind VARCHAR(50) NOT NULL,
+    market VARCHAR(210),
+    addresses TEXT[] NOT NULL DEFAULT '{}',
+    data TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_events_market ON events(market, id);
+CREATE INDEX IF NOT EXISTS idx_events_addresses ON events USING GIN (addresses);
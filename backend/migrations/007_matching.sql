@@ -0,0 +1,21 @@
+-- Liquid Nation Database Schema
+-- Opt-in matching of crossing orders
+
+ALTER TABLE orders ADD COLUMN IF NOT EXISTS auto_match BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE IF NOT EXISTS matches (
+    id VARCHAR(255) PRIMARY KEY,
+    market VARCHAR(210) NOT NULL,
+    maker_order_id VARCHAR(255) NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    taker_order_id VARCHAR(255) NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    maker_amount VARCHAR(100) NOT NULL,
+    taker_amount VARCHAR(100) NOT NULL,
+    spell TEXT NOT NULL,
+    status VARCHAR(50) NOT NULL DEFAULT 'proposed',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- One live proposal per pair of orders
+CREATE UNIQUE INDEX IF NOT EXISTS idx_matches_proposed ON matches(maker_order_id, taker_order_id) WHERE status = 'proposed';
+CREATE INDEX IF NOT EXISTS idx_matches_market ON matches(market, status);
@@ -0,0 +1,4 @@
+-- Liquid Nation Database Schema
+-- Minimum partial fill per order
+
+ALTER TABLE orders ADD COLUMN IF NOT EXISTS min_fill_amount VARCHAR(100) NOT NULL DEFAULT '0';
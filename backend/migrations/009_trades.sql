@@ -0,0 +1,25 @@
+-- Liquid Nation Database Schema
+-- Executed fills for trade history and candles
+
+CREATE TABLE IF NOT EXISTS trades (
+    id VARCHAR(255) PRIMARY KEY,
+    order_id VARCHAR(255) NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    market VARCHAR(210) NOT NULL,
+    offer_token VARCHAR(100) NOT NULL,
+    offer_amount VARCHAR(100) NOT NULL,
+    want_token VARCHAR(100) NOT NULL,
+    want_amount VARCHAR(100) NOT NULL,
+    maker_address VARCHAR(255) NOT NULL,
+    taker_address VARCHAR(255) NOT NULL,
+    txid VARCHAR(255),
+    block_height BIGINT,
+    status VARCHAR(50) NOT NULL DEFAULT 'pending',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    executed_at TIMESTAMPTZ,
+    prove_job_id VARCHAR(255)
+);
+
+CREATE INDEX IF NOT EXISTS idx_trades_market ON trades(market, executed_at) WHERE status = 'executed';
+CREATE INDEX IF NOT EXISTS idx_trades_order ON trades(order_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_trades_txid ON trades(txid);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_trades_prove_job ON trades(prove_job_id);
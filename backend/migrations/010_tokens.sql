@@ -0,0 +1,14 @@
+-- Liquid Nation Database Schema
+-- Registry of tradable tokens
+
+CREATE TABLE IF NOT EXISTS tokens (
+    ticker VARCHAR(20) PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    app_id VARCHAR(64) NOT NULL,
+    vk VARCHAR(64) NOT NULL,
+    decimals INTEGER NOT NULL,
+    chain VARCHAR(50) NOT NULL,
+    icon_url TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,19 @@
+-- Liquid Nation Database Schema
+-- Wallet sign-in challenges and sessions
+
+CREATE TABLE IF NOT EXISTS auth_challenges (
+    nonce VARCHAR(64) PRIMARY KEY,
+    address VARCHAR(100) NOT NULL,
+    message TEXT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS sessions (
+    token_hash VARCHAR(64) PRIMARY KEY,
+    address VARCHAR(100) NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_sessions_expires ON sessions(expires_at);
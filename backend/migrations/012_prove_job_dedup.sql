@@ -0,0 +1,13 @@
+-- Liquid Nation Database Schema
+-- One live prove job per request
+
+-- Older live duplicates are failed so the unique index can be built
+UPDATE prove_jobs
+SET status = 'failed', error = 'superseded by a duplicate job', updated_at = NOW()
+WHERE status <> 'failed' AND id NOT IN (
+    SELECT DISTINCT ON (request_hash) id FROM prove_jobs
+    WHERE status <> 'failed'
+    ORDER BY request_hash, created_at DESC
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_prove_jobs_live_hash ON prove_jobs(request_hash) WHERE status <> 'failed';
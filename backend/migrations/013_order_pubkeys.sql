@@ -0,0 +1,4 @@
+-- Liquid Nation Database Schema
+-- Maker public key per order, for follow-up spells
+
+ALTER TABLE orders ADD COLUMN IF NOT EXISTS maker_pubkey VARCHAR(66);
@@ -0,0 +1,202 @@
+//! API error type
+//!
+//! Every handler failure maps to an HTTP status and a stable, machine-readable
+//! code. Bodies look like `{"code": "order_not_found", "message": "..."}`.
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use crate::services::address::AddressError;
+use crate::services::auth::AuthError;
+use crate::services::charms::ProverError;
+use crate::services::psbt::PsbtError;
+use crate::services::tokens::TokenError;
+
+/// Result type for route handlers
+pub type ApiResult<T> = Result<T, ApiError>;
+
+/// Error returned by API handlers
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    /// The request is malformed or missing fields
+    #[error("{0}")]
+    BadRequest(String),
+    /// The built spell does not pass local validation
+    #[error("{0}")]
+    InvalidSpell(String),
+    /// A resource does not exist; the code names the resource
+    #[error("{message}")]
+    NotFound { code: &'static str, message: String },
+    /// The caller has no valid session or its sign-in failed
+    #[error("{0}")]
+    Unauthorized(String),
+    /// The caller may not perform the operation
+    #[error("{0}")]
+    Forbidden(String),
+    /// The resource is not in a state that allows the operation
+    #[error("{0}")]
+    Conflict(String),
+    /// The prover could not be reached or is overloaded
+    #[error("{0}")]
+    ProverUnavailable(String),
+    /// The prover rejected the spell or failed permanently
+    #[error("{0}")]
+    ProverFailed(String),
+    /// A signed transaction or PSBT does not match what was proved
+    #[error("{0}")]
+    InvalidTransaction(String),
+    /// The Bitcoin node could not be reached or refused the request
+    #[error("{0}")]
+    ChainUnavailable(String),
+    /// The Bitcoin node rejected a transaction
+    #[error("{0}")]
+    BroadcastFailed(String),
+    /// Anything else; details are logged, not returned
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+/// JSON body of an error response
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn not_found(code: &'static str, message: impl Into<String>) -> Self {
+        ApiError::NotFound { code, message: message.into() }
+    }
+
+    /// Stable error code
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::InvalidSpell(_) => "invalid_spell",
+            ApiError::NotFound { code, .. } => code,
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::ProverUnavailable(_) => "prover_unavailable",
+            ApiError::ProverFailed(_) => "prover_failed",
+            ApiError::InvalidTransaction(_) => "invalid_transaction",
+            ApiError::ChainUnavailable(_) => "chain_unavailable",
+            ApiError::BroadcastFailed(_) => "broadcast_failed",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    /// HTTP status for the response
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::InvalidSpell(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::NotFound { .. } => StatusCode::NOT_FOUND,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::ProverUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::ProverFailed(_) => StatusCode::BAD_GATEWAY,
+            ApiError::InvalidTransaction(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::ChainUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::BroadcastFailed(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<ProverError> for ApiError {
+    fn from(e: ProverError) -> Self {
+        match e {
+            ProverError::InvalidSpell(msg) => ApiError::InvalidSpell(msg),
+            e if e.is_transient() => ApiError::ProverUnavailable(e.to_string()),
+            e => ApiError::ProverFailed(e.to_string()),
+        }
+    }
+}
+
+impl From<AddressError> for ApiError {
+    fn from(e: AddressError) -> Self {
+        ApiError::BadRequest(e.to_string())
+    }
+}
+
+impl From<AuthError> for ApiError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::InvalidAddress(e) => e.into(),
+            AuthError::UnsupportedAddress(msg) => ApiError::BadRequest(msg),
+            AuthError::InvalidSignature(msg) | AuthError::InvalidChallenge(msg) => ApiError::Unauthorized(msg),
+            AuthError::Internal(e) => ApiError::Internal(e),
+        }
+    }
+}
+
+impl From<PsbtError> for ApiError {
+    fn from(e: PsbtError) -> Self {
+        ApiError::InvalidTransaction(e.to_string())
+    }
+}
+
+impl From<TokenError> for ApiError {
+    fn from(e: TokenError) -> Self {
+        match e {
+            TokenError::InUse(_) => ApiError::Conflict(e.to_string()),
+            TokenError::Internal(e) => ApiError::Internal(e),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let message = match &self {
+            ApiError::Internal(e) => {
+                tracing::error!("Internal error: {:#}", e);
+                "Internal server error".to_string()
+            }
+            other => other.to_string(),
+        };
+        let body = ErrorBody { code: self.code(), message };
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_and_statuses() {
+        let e = ApiError::not_found("order_not_found", "Order x not found");
+        assert_eq!(e.code(), "order_not_found");
+        assert_eq!(e.status(), StatusCode::NOT_FOUND);
+
+        let e: ApiError = ProverError::Api { status: 503, body: "busy".into() }.into();
+        assert_eq!(e.code(), "prover_unavailable");
+
+        let e: ApiError = ProverError::Api { status: 400, body: "bad".into() }.into();
+        assert_eq!(e.status(), StatusCode::BAD_GATEWAY);
+
+        let e: ApiError = ProverError::InvalidSpell("no ins".into()).into();
+        assert_eq!(e.code(), "invalid_spell");
+
+        let e: ApiError = TokenError::InUse("TOAD".into()).into();
+        assert_eq!(e.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_internal_errors_hide_details() {
+        let response = ApiError::Internal(anyhow::anyhow!("db password wrong")).into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(
+            std::str::from_utf8(&body).unwrap(),
+            r#"{"code":"internal_error","message":"Internal server error"}"#
+        );
+    }
+}
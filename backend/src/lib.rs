@@ -0,0 +1,12 @@
+//! Liquid Nation Backend
+//!
+//! Library half of the API server. The binary in `main.rs` wires these
+//! modules into an axum application; keeping them in a library lets other
+//! tools and tests reuse the services without going through HTTP.
+
+pub mod config;
+pub mod db;
+pub mod error;
+pub mod routes;
+pub mod services;
+pub mod state;
@@ -6,11 +6,8 @@
 //! - Escrow management
 //! - Charms protocol integration
 
-mod db;
-mod routes;
-mod services;
-
 use axum::{
+    middleware,
     Router,
     routing::{get, post, delete},
 };
@@ -18,12 +15,11 @@ use tower_http::cors::{CorsLayer, Any};
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use std::net::SocketAddr;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
 
-use routes::{health, orders, wallet, spells, escrow};
-use services::bitcoin::BitcoinService;
-use services::charms::CharmsService;
+use liquid_nation_backend::config::Config;
+use liquid_nation_backend::routes::{health, orders, wallet, spells, escrow, prove_jobs, fees, markets, matches, quote, events, tokens};
+use liquid_nation_backend::state::AppState;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -39,35 +35,35 @@ async fn main() -> anyhow::Result<()> {
 
     tracing::info!("Starting Liquid Nation API Server");
 
-    // Initialize database
-    let db_pool = db::init_db().await?;
-    tracing::info!("Database initialized");
-
-    // Validate environment configuration
-    validate_environment().await;
-
+    // Load and validate configuration; any problem stops the server here
+    let config = Config::load()?;
+    for line in config.summary() {
+        tracing::info!("{}", line);
+    }
+    if config.mock_mode {
+        tracing::warn!("Mock mode is enabled: the prover and node wallet are not called");
+    }
 
-    // Initialize services
-    let bitcoin_rpc = std::env::var("BITCOIN_RPC_URL")
-        .unwrap_or_else(|_| "http://127.0.0.1:48332".to_string());
-    let bitcoin_service = BitcoinService::new(&bitcoin_rpc);
-    let charms_service = CharmsService::new();
+    // Connect services and start the background workers
+    let state = AppState::new(config).await?;
+    state.start();
 
-    // Create shared order state with database
-    let order_state = Arc::new(orders::AppState {
-        charms: charms_service,
-        bitcoin: bitcoin_service,
-        db: db_pool.clone(),
-    });
+    // Order changes, for signed-in wallets
+    let order_actions = Router::new()
+        .route("/api/orders", post(orders::create_order))
+        .route("/api/orders/:id/fill", post(orders::fill_order))
+        .route("/api/orders/:id/cancel", delete(orders::cancel_order))
+        .route("/api/orders/:id/partial-fill", post(orders::partial_fill_order))
+        .route("/api/orders/:id/broadcast", post(orders::broadcast_order))
+        .route("/api/orders/:id/bump", post(orders::bump_order_fee))
+        .route("/api/orders/:id/expire", post(orders::expire_order))
+        .route_layer(middleware::from_fn_with_state(state.clone(), wallet::require_session));
 
-    // Initialize escrow state with cloned services
-    let bitcoin_service_escrow = BitcoinService::new(&bitcoin_rpc);
-    let charms_service_escrow = CharmsService::new();
-    let escrow_state = Arc::new(escrow::EscrowState {
-        charms: Arc::new(charms_service_escrow),
-        bitcoin: Arc::new(bitcoin_service_escrow),
-        escrows: RwLock::new(Vec::new()),
-    });
+    // Proving spends prover time, so only signed-in wallets may ask for it
+    let prove_actions = Router::new()
+        .route("/api/prove-jobs", post(prove_jobs::submit_prove_job))
+        .route("/api/spells/prove", post(spells::prove_spell))
+        .route_layer(middleware::from_fn_with_state(state.clone(), wallet::require_session));
 
     // Build application routes
     let app = Router::new()
@@ -76,29 +72,55 @@ async fn main() -> anyhow::Result<()> {
         .route("/api/health", get(health::health_check))
         .route("/api/health/prover", get(health::check_prover_api))
         
-        // Orders (with state)
+        // Orders
         .route("/api/orders", get(orders::list_orders))
-        .route("/api/orders", post(orders::create_order))
         .route("/api/orders/:id", get(orders::get_order))
-        .route("/api/orders/:id/fill", post(orders::fill_order))
-        .route("/api/orders/:id/cancel", delete(orders::cancel_order))
-        .route("/api/orders/:id/partial-fill", post(orders::partial_fill_order))
-        .route("/api/orders/:id/broadcast", post(orders::broadcast_order))
-        .with_state(order_state)
+        .route("/api/orders/:id/transactions", get(orders::get_order_transactions))
+        .merge(order_actions)
+        
+        // Markets
+        .route("/api/markets/:base/:quote/book", get(markets::get_order_book))
+        .route("/api/markets/:base/:quote/trades", get(markets::get_trades))
+        .route("/api/markets/:base/:quote/candles", get(markets::get_candles))
+        
+        // Matching
+        .route("/api/matches", get(matches::list_matches))
+        .route("/api/matches/:id", get(matches::get_match))
+        
+        // Quotes
+        .route("/api/quote", post(quote::get_quote))
+
+        // Token registry
+        .route("/api/tokens", get(tokens::list_tokens))
+        .route("/api/tokens", post(tokens::register_token))
+        .route("/api/tokens/:ticker", get(tokens::get_token))
+        
+        // Event stream
+        .route("/api/events", get(events::stream_events))
+        
+        // Fees
+        .route("/api/fees", get(fees::get_fee_quote))
+        
+        // Proving jobs
+        .route("/api/prove-jobs/:id", get(prove_jobs::get_prove_job))
+        .merge(prove_actions)
         
         // Wallet
-        .route("/api/wallet/connect", post(wallet::connect_wallet))
         .route("/api/wallet/balance", get(wallet::get_balance))
         .route("/api/wallet/utxos", get(wallet::get_utxos))
         .route("/api/wallet/address", get(wallet::get_address))
-        
-        // Escrow
-        .nest("/api/escrows", escrow::router(escrow_state))
+        .route("/api/wallet/challenge", post(wallet::wallet_challenge))
+        .route("/api/wallet/connect", post(wallet::connect_wallet))
+        .route("/api/wallet/disconnect", post(wallet::disconnect_wallet))
         
         // Spells (Charms protocol)
-        .route("/api/spells/prove", post(spells::prove_spell))
         .route("/api/spells/broadcast", post(spells::broadcast_transaction))
         .route("/api/spells/status/:txid", get(spells::get_transaction_status))
+        .route("/api/spells/validate", post(spells::validate_spell))
+        
+        // Escrow
+        .nest("/api/escrows", escrow::router(state.clone()))
+        .with_state(state.clone())
         
         // CORS
         .layer(CorsLayer::new()
@@ -109,78 +131,59 @@ async fn main() -> anyhow::Result<()> {
         // Tracing
         .layer(TraceLayer::new_for_http());
 
-    // Get port from environment or default
-    let port: u16 = std::env::var("PORT")
-        .ok()
-        .and_then(|p| p.parse().ok())
-        .unwrap_or(3001);
-
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let addr = SocketAddr::from(([0, 0, 0, 0], state.config.port));
     tracing::info!("Listening on {}", addr);
 
+    // On a signal, stop accepting connections and let in-flight requests
+    // finish; cancelling the shutdown token also ends event streams
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    let shutdown = state.tasks.token();
+    let server = axum::serve(listener, app).with_graceful_shutdown(shutdown_signal(shutdown.clone()));
+    tokio::select! {
+        result = server => result?,
+        _ = drain_deadline(shutdown, state.config.shutdown_timeout) => {
+            tracing::warn!("Requests still running after {:?}, stopping anyway", state.config.shutdown_timeout);
+        }
+    }
 
+    state.shutdown().await;
+    tracing::info!("Shutdown complete");
     Ok(())
 }
 
-/// Validate environment configuration on startup
-async fn validate_environment() {
-    tracing::info!("=== Environment Validation ===");
-    
-    // Check Prover API URL
-    let api_url = std::env::var("CHARMS_PROVE_API_URL")
-        .unwrap_or_else(|_| "https://v8.charms.dev/spells/prove".to_string());
-    tracing::info!("✅ Prover API URL: {}", api_url);
-    
-    // Check mock mode
-    let mock_mode = std::env::var("MOCK_MODE")
-        .map(|v| v == "true")
-        .unwrap_or(true);
-    if mock_mode {
-        tracing::warn!("⚠️  Mock mode: ENABLED (Prover API will not be called)");
-    } else {
-        tracing::info!("✅ Mock mode: DISABLED (Real Prover API calls enabled)");
-    }
-    
-    // Check binary path
-    if let Ok(binary_path) = std::env::var("SWAP_APP_BINARY_PATH") {
-        match tokio::fs::metadata(&binary_path).await {
-            Ok(metadata) => {
-                tracing::info!("✅ App binary found: {} ({} bytes)", binary_path, metadata.len());
+/// Wait for Ctrl-C or SIGTERM, then cancel `shutdown`
+async fn shutdown_signal(shutdown: CancellationToken) {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
             }
-            Err(_) => {
-                tracing::warn!("⚠️  App binary not found at: {}", binary_path);
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
             }
         }
-    } else {
-        tracing::warn!("⚠️  SWAP_APP_BINARY_PATH not set (using defaults)");
-    }
-    
-    // Check VK
-    if let Ok(vk) = std::env::var("SWAP_APP_VK") {
-        let preview = if vk.len() > 16 {
-            format!("{}...", &vk[..16])
-        } else {
-            vk.clone()
-        };
-        tracing::info!("✅ App VK configured: {}", preview);
-    } else {
-        tracing::warn!("⚠️  SWAP_APP_VK not set (using default)");
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
     }
-    
-    // Check Bitcoin RPC
-    let bitcoin_rpc = std::env::var("BITCOIN_RPC_URL")
-        .unwrap_or_else(|_| "http://127.0.0.1:48332".to_string());
-    tracing::info!("✅ Bitcoin RPC: {}", bitcoin_rpc);
-    
-    // Check port
-    let port: u16 = std::env::var("PORT")
-        .ok()
-        .and_then(|p| p.parse().ok())
-        .unwrap_or(3001);
-    tracing::info!("✅ Server port: {}", port);
-    
-    tracing::info!("=== Validation Complete ===");
+    tracing::info!("Shutting down");
+    shutdown.cancel();
 }
 
+/// Resolves `timeout` after shutdown starts, bounding the request drain
+async fn drain_deadline(shutdown: CancellationToken, timeout: std::time::Duration) {
+    shutdown.cancelled().await;
+    tokio::time::sleep(timeout).await;
+}
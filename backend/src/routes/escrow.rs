@@ -1,27 +1,31 @@
 //! Escrow API Routes
 //! 
 //! Handles escrow creation, release, refund, and dispute operations
+//!
+//! Changes to an escrow come from a signed-in wallet, which must be the
+//! native segwit or taproot address of one of the escrow's keys.
 
 use axum::{
     extract::{Path, State},
-    http::StatusCode,
+    Extension,
+    middleware,
     response::Json,
     routing::{get, post},
     Router,
 };
+use bitcoin::Network;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
-use crate::services::{BitcoinService, CharmsService};
-
-/// Application state for escrow routes
-pub struct EscrowState {
-    pub charms: Arc<CharmsService>,
-    pub bitcoin: Arc<BitcoinService>,
-    pub escrows: RwLock<Vec<EscrowRecord>>,
-}
+use super::wallet::{require_session, AuthSession};
+use crate::error::{ApiError, ApiResult};
+use crate::services::address;
+use crate::services::chain_watcher::ChainEvent;
+use crate::services::events::EventKind;
+use crate::state::AppState;
 
 /// Escrow status
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -80,7 +84,6 @@ pub struct CreateEscrowRequest {
 pub struct ReleaseEscrowRequest {
     pub preimage: Option<String>,
     pub signature: String,
-    pub signer_pubkey: String,
 }
 
 /// Refund escrow request
@@ -95,7 +98,6 @@ pub struct RefundEscrowRequest {
 pub struct DisputeEscrowRequest {
     pub reason: String,
     pub evidence_hash: Option<String>,
-    pub initiator_pubkey: String,
 }
 
 /// Resolve dispute request
@@ -105,12 +107,19 @@ pub struct ResolveDisputeRequest {
     pub arbiter_signature: String,
 }
 
+/// Role a key plays in an escrow
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Party {
+    Depositor,
+    Recipient,
+    Arbiter,
+}
+
 /// API response wrapper
 #[derive(Debug, Serialize)]
 pub struct EscrowResponse<T> {
     pub success: bool,
     pub data: Option<T>,
-    pub error: Option<String>,
 }
 
 impl<T> EscrowResponse<T> {
@@ -118,36 +127,31 @@ impl<T> EscrowResponse<T> {
         Self {
             success: true,
             data: Some(data),
-            error: None,
-        }
-    }
-
-    pub fn error(msg: impl Into<String>) -> Self {
-        Self {
-            success: false,
-            data: None,
-            error: Some(msg.into()),
         }
     }
 }
 
-/// Create the escrow router
-pub fn router(state: Arc<EscrowState>) -> Router {
+/// Create the escrow router; changes need a signed-in wallet
+pub fn router(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    let actions = Router::new()
+        .route("/", post(create_escrow))
+        .route("/:id/release", post(release_escrow))
+        .route("/:id/refund", post(refund_escrow))
+        .route("/:id/dispute", post(dispute_escrow))
+        .route("/:id/resolve", post(resolve_dispute))
+        .route_layer(middleware::from_fn_with_state(state, require_session));
+
     Router::new()
-        .route("/", get(list_escrows).post(create_escrow))
-        .route("/{id}", get(get_escrow))
-        .route("/{id}/release", post(release_escrow))
-        .route("/{id}/refund", post(refund_escrow))
-        .route("/{id}/dispute", post(dispute_escrow))
-        .route("/{id}/resolve", post(resolve_dispute))
-        .route("/by-depositor/{pubkey}", get(get_escrows_by_depositor))
-        .route("/by-recipient/{pubkey}", get(get_escrows_by_recipient))
-        .with_state(state)
+        .route("/", get(list_escrows))
+        .route("/:id", get(get_escrow))
+        .merge(actions)
+        .route("/by-depositor/:pubkey", get(get_escrows_by_depositor))
+        .route("/by-recipient/:pubkey", get(get_escrows_by_recipient))
 }
 
 /// List all escrows
 async fn list_escrows(
-    State(state): State<Arc<EscrowState>>,
+    State(state): State<Arc<AppState>>,
 ) -> Json<EscrowResponse<Vec<EscrowRecord>>> {
     let escrows = state.escrows.read().await;
     Json(EscrowResponse::success(escrows.clone()))
@@ -155,28 +159,41 @@ async fn list_escrows(
 
 /// Get escrow by ID
 async fn get_escrow(
-    State(state): State<Arc<EscrowState>>,
+    State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
-) -> Result<Json<EscrowResponse<EscrowRecord>>, StatusCode> {
+) -> ApiResult<Json<EscrowResponse<EscrowRecord>>> {
     let escrows = state.escrows.read().await;
     
     if let Some(escrow) = escrows.iter().find(|e| e.id == id) {
         Ok(Json(EscrowResponse::success(escrow.clone())))
     } else {
-        Ok(Json(EscrowResponse::error("Escrow not found")))
+        Err(escrow_not_found(&id))
     }
 }
 
-/// Create a new escrow
+/// Create a new escrow; the signed-in wallet must hold the depositor key
 async fn create_escrow(
-    State(state): State<Arc<EscrowState>>,
+    State(state): State<Arc<AppState>>,
+    Extension(session): Extension<AuthSession>,
     Json(req): Json<CreateEscrowRequest>,
-) -> Result<Json<EscrowResponse<EscrowRecord>>, StatusCode> {
+) -> ApiResult<Json<EscrowResponse<EscrowRecord>>> {
+    let depositor_pubkey = address::parse_pubkey("depositor_pubkey", &req.depositor_pubkey)?;
+    let recipient_pubkey = address::parse_pubkey("recipient_pubkey", &req.recipient_pubkey)?;
+    let arbiter_pubkey = req
+        .arbiter_pubkey
+        .as_deref()
+        .map(|key| address::parse_pubkey("arbiter_pubkey", key))
+        .transpose()?;
+    if !address::is_address_of_pubkey(&depositor_pubkey, &session.address, state.config.network) {
+        return Err(ApiError::Forbidden(format!(
+            "{} is not the address of depositor key {}",
+            session.address, depositor_pubkey
+        )));
+    }
+
     // Validate escrow type requirements
     if req.escrow_type == EscrowType::TwoOfThree && req.arbiter_pubkey.is_none() {
-        return Ok(Json(EscrowResponse::error(
-            "2-of-3 escrow requires arbiter pubkey",
-        )));
+        return Err(ApiError::BadRequest("2-of-3 escrow requires arbiter pubkey".to_string()));
     }
 
     // Generate unique escrow ID
@@ -187,9 +204,9 @@ async fn create_escrow(
     let escrow = EscrowRecord {
         id: id.clone(),
         escrow_id,
-        depositor_pubkey: req.depositor_pubkey,
-        recipient_pubkey: req.recipient_pubkey,
-        arbiter_pubkey: req.arbiter_pubkey,
+        depositor_pubkey,
+        recipient_pubkey,
+        arbiter_pubkey,
         escrow_type: req.escrow_type,
         held_token_id: req.token_id,
         held_amount: req.amount,
@@ -205,6 +222,8 @@ async fn create_escrow(
     // Store escrow
     let mut escrows = state.escrows.write().await;
     escrows.push(escrow.clone());
+    drop(escrows);
+    publish_escrow_event(&state, &escrow).await;
 
     // TODO: Build and broadcast create-escrow spell
     // let spell = state.charms.build_create_escrow_spell(&escrow)?;
@@ -215,135 +234,134 @@ async fn create_escrow(
 
 /// Release escrow to recipient
 async fn release_escrow(
-    State(state): State<Arc<EscrowState>>,
+    State(state): State<Arc<AppState>>,
+    Extension(session): Extension<AuthSession>,
     Path(id): Path<String>,
     Json(req): Json<ReleaseEscrowRequest>,
-) -> Result<Json<EscrowResponse<EscrowRecord>>, StatusCode> {
+) -> ApiResult<Json<EscrowResponse<EscrowRecord>>> {
     let mut escrows = state.escrows.write().await;
     
     if let Some(escrow) = escrows.iter_mut().find(|e| e.id == id) {
         // Validate escrow is active
         if escrow.status != EscrowStatus::Active {
-            return Ok(Json(EscrowResponse::error(
-                "Escrow is not active",
-            )));
+            return Err(ApiError::Conflict("Escrow is not active".to_string()));
         }
 
         // Validate release hash if present
-        if escrow.release_hash.is_some() && req.preimage.is_none() {
-            return Ok(Json(EscrowResponse::error(
-                "Preimage required for hash-locked escrow",
-            )));
+        if let Some(release_hash) = &escrow.release_hash {
+            check_preimage(release_hash, req.preimage.as_deref())?;
         }
 
-        // Validate signer is authorized
-        let is_authorized = req.signer_pubkey == escrow.depositor_pubkey
-            || req.signer_pubkey == escrow.recipient_pubkey
-            || escrow.arbiter_pubkey.as_ref().map(|a| a == &req.signer_pubkey).unwrap_or(false);
-
-        if !is_authorized {
-            return Ok(Json(EscrowResponse::error(
-                "Signer not authorized to release escrow",
-            )));
-        }
+        // The recipient cannot release to itself
+        require_party(
+            state.config.network,
+            escrow,
+            &session,
+            &[Party::Depositor, Party::Arbiter],
+            "release",
+        )?;
 
         // Update escrow status
         escrow.status = EscrowStatus::Released;
 
         // TODO: Build and broadcast release-escrow spell
 
+        publish_escrow_event(&state, escrow).await;
         Ok(Json(EscrowResponse::success(escrow.clone())))
     } else {
-        Ok(Json(EscrowResponse::error("Escrow not found")))
+        Err(escrow_not_found(&id))
     }
 }
 
 /// Refund escrow to depositor
 async fn refund_escrow(
-    State(state): State<Arc<EscrowState>>,
+    State(state): State<Arc<AppState>>,
+    Extension(session): Extension<AuthSession>,
     Path(id): Path<String>,
     Json(_req): Json<RefundEscrowRequest>,
-) -> Result<Json<EscrowResponse<EscrowRecord>>, StatusCode> {
+) -> ApiResult<Json<EscrowResponse<EscrowRecord>>> {
     let mut escrows = state.escrows.write().await;
     
     if let Some(escrow) = escrows.iter_mut().find(|e| e.id == id) {
         // Validate escrow is active or expired
         if escrow.status != EscrowStatus::Active && escrow.status != EscrowStatus::Expired {
-            return Ok(Json(EscrowResponse::error(
-                "Escrow cannot be refunded in current state",
-            )));
+            return Err(ApiError::Conflict("Escrow cannot be refunded in current state".to_string()));
         }
 
+        // Validate signer may refund in this state
+        require_party(state.config.network, escrow, &session, refund_parties(escrow.status), "refund")?;
+
         // Update escrow status
         escrow.status = EscrowStatus::Refunded;
 
         // TODO: Build and broadcast refund-escrow spell
 
+        publish_escrow_event(&state, escrow).await;
         Ok(Json(EscrowResponse::success(escrow.clone())))
     } else {
-        Ok(Json(EscrowResponse::error("Escrow not found")))
+        Err(escrow_not_found(&id))
     }
 }
 
 /// Initiate dispute on escrow
 async fn dispute_escrow(
-    State(state): State<Arc<EscrowState>>,
+    State(state): State<Arc<AppState>>,
+    Extension(session): Extension<AuthSession>,
     Path(id): Path<String>,
-    Json(req): Json<DisputeEscrowRequest>,
-) -> Result<Json<EscrowResponse<EscrowRecord>>, StatusCode> {
+    Json(_req): Json<DisputeEscrowRequest>,
+) -> ApiResult<Json<EscrowResponse<EscrowRecord>>> {
     let mut escrows = state.escrows.write().await;
     
     if let Some(escrow) = escrows.iter_mut().find(|e| e.id == id) {
         // Validate escrow type supports disputes
         if escrow.escrow_type != EscrowType::TwoOfThree {
-            return Ok(Json(EscrowResponse::error(
-                "Only 2-of-3 escrows can be disputed",
-            )));
+            return Err(ApiError::Conflict("Only 2-of-3 escrows can be disputed".to_string()));
         }
 
         // Validate escrow is active
         if escrow.status != EscrowStatus::Active {
-            return Ok(Json(EscrowResponse::error(
-                "Escrow is not active",
-            )));
+            return Err(ApiError::Conflict("Escrow is not active".to_string()));
         }
 
         // Validate initiator is party to escrow
-        if req.initiator_pubkey != escrow.depositor_pubkey
-            && req.initiator_pubkey != escrow.recipient_pubkey
-        {
-            return Ok(Json(EscrowResponse::error(
-                "Only depositor or recipient can initiate dispute",
-            )));
-        }
+        require_party(
+            state.config.network,
+            escrow,
+            &session,
+            &[Party::Depositor, Party::Recipient],
+            "dispute",
+        )?;
 
         // Update escrow status
         escrow.status = EscrowStatus::Disputed;
 
         // TODO: Build and broadcast dispute-escrow spell
 
+        publish_escrow_event(&state, escrow).await;
         Ok(Json(EscrowResponse::success(escrow.clone())))
     } else {
-        Ok(Json(EscrowResponse::error("Escrow not found")))
+        Err(escrow_not_found(&id))
     }
 }
 
 /// Resolve dispute (arbiter only)
 async fn resolve_dispute(
-    State(state): State<Arc<EscrowState>>,
+    State(state): State<Arc<AppState>>,
+    Extension(session): Extension<AuthSession>,
     Path(id): Path<String>,
     Json(req): Json<ResolveDisputeRequest>,
-) -> Result<Json<EscrowResponse<EscrowRecord>>, StatusCode> {
+) -> ApiResult<Json<EscrowResponse<EscrowRecord>>> {
     let mut escrows = state.escrows.write().await;
     
     if let Some(escrow) = escrows.iter_mut().find(|e| e.id == id) {
         // Validate escrow is disputed
         if escrow.status != EscrowStatus::Disputed {
-            return Ok(Json(EscrowResponse::error(
-                "Escrow is not in disputed state",
-            )));
+            return Err(ApiError::Conflict("Escrow is not in disputed state".to_string()));
         }
 
+        // Only the arbiter decides
+        require_party(state.config.network, escrow, &session, &[Party::Arbiter], "resolve")?;
+
         // Determine winner
         let winner = match req.winner.as_str() {
             "depositor" => {
@@ -355,9 +373,7 @@ async fn resolve_dispute(
                 &escrow.recipient_pubkey
             }
             _ => {
-                return Ok(Json(EscrowResponse::error(
-                    "Winner must be 'depositor' or 'recipient'",
-                )));
+                return Err(ApiError::BadRequest("Winner must be 'depositor' or 'recipient'".to_string()));
             }
         };
 
@@ -365,15 +381,76 @@ async fn resolve_dispute(
 
         // TODO: Build and broadcast resolve-dispute spell
 
+        publish_escrow_event(&state, escrow).await;
         Ok(Json(EscrowResponse::success(escrow.clone())))
     } else {
-        Ok(Json(EscrowResponse::error("Escrow not found")))
+        Err(escrow_not_found(&id))
+    }
+}
+
+/// Record an escrow change in the event log; the change already happened,
+/// so a failure is only logged
+async fn publish_escrow_event(state: &AppState, escrow: &EscrowRecord) {
+    let mut parties = vec![escrow.depositor_pubkey.clone(), escrow.recipient_pubkey.clone()];
+    parties.extend(escrow.arbiter_pubkey.clone());
+    if let Err(e) = state.events.publish(EventKind::EscrowUpdated, None, parties, escrow).await {
+        tracing::warn!("Failed to publish event of escrow {}: {}", escrow.id, e);
     }
 }
 
+/// The role the signed-in wallet plays in `escrow`, which must be one of
+/// `allowed`
+fn require_party(
+    network: Network,
+    escrow: &EscrowRecord,
+    session: &AuthSession,
+    allowed: &[Party],
+    action: &str,
+) -> ApiResult<Party> {
+    let keys = [
+        (Party::Depositor, Some(&escrow.depositor_pubkey)),
+        (Party::Recipient, Some(&escrow.recipient_pubkey)),
+        (Party::Arbiter, escrow.arbiter_pubkey.as_ref()),
+    ];
+    keys.into_iter()
+        .filter(|(party, _)| allowed.contains(party))
+        .find(|(_, key)| key.is_some_and(|key| address::is_address_of_pubkey(key, &session.address, network)))
+        .map(|(party, _)| party)
+        .ok_or_else(|| {
+            ApiError::Forbidden(format!(
+                "{} is not a party allowed to {} escrow {}",
+                session.address, action, escrow.id
+            ))
+        })
+}
+
+/// Parties that may refund an escrow: the depositor only once it expired
+fn refund_parties(status: EscrowStatus) -> &'static [Party] {
+    match status {
+        EscrowStatus::Expired => &[Party::Depositor, Party::Recipient, Party::Arbiter],
+        _ => &[Party::Recipient, Party::Arbiter],
+    }
+}
+
+/// Check that the hex `preimage` hashes (SHA-256) to the hex `release_hash`
+fn check_preimage(release_hash: &str, preimage: Option<&str>) -> ApiResult<()> {
+    let preimage = preimage.ok_or_else(|| ApiError::BadRequest("Preimage required for hash-locked escrow".to_string()))?;
+    let bytes = hex::decode(preimage)
+        .map_err(|e| ApiError::BadRequest(format!("Preimage is not hex: {}", e)))?;
+    if !hex::encode(Sha256::digest(&bytes)).eq_ignore_ascii_case(release_hash) {
+        return Err(ApiError::BadRequest("Preimage does not match the release hash".to_string()));
+    }
+    Ok(())
+}
+
+/// Error for an escrow id that does not exist
+fn escrow_not_found(id: &str) -> ApiError {
+    ApiError::not_found("escrow_not_found", format!("Escrow {} not found", id))
+}
+
 /// Get escrows by depositor
 async fn get_escrows_by_depositor(
-    State(state): State<Arc<EscrowState>>,
+    State(state): State<Arc<AppState>>,
     Path(pubkey): Path<String>,
 ) -> Json<EscrowResponse<Vec<EscrowRecord>>> {
     let escrows = state.escrows.read().await;
@@ -387,7 +464,7 @@ async fn get_escrows_by_depositor(
 
 /// Get escrows by recipient
 async fn get_escrows_by_recipient(
-    State(state): State<Arc<EscrowState>>,
+    State(state): State<Arc<AppState>>,
     Path(pubkey): Path<String>,
 ) -> Json<EscrowResponse<Vec<EscrowRecord>>> {
     let escrows = state.escrows.read().await;
@@ -399,3 +476,107 @@ async fn get_escrows_by_recipient(
     Json(EscrowResponse::success(filtered))
 }
 
+/// Expire active escrows as blocks reach their expiry height
+pub async fn expire_escrows(state: Arc<AppState>, mut events: broadcast::Receiver<ChainEvent>) {
+    loop {
+        let height = match state.tasks.recv(&mut events).await {
+            Ok(ChainEvent::NewBlock { height, .. }) => height,
+            Ok(ChainEvent::NewTransaction { .. }) => continue,
+            // Only the latest height matters, so missed blocks are harmless
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+
+        let mut expired = Vec::new();
+        for escrow in state
+            .escrows
+            .write()
+            .await
+            .iter_mut()
+            .filter(|e| e.status == EscrowStatus::Active && e.expiry_height <= height)
+        {
+            tracing::info!("Escrow {} expired at height {}", escrow.id, height);
+            escrow.status = EscrowStatus::Expired;
+            expired.push(escrow.clone());
+        }
+        for escrow in &expired {
+            publish_escrow_event(&state, escrow).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEPOSITOR_PUBKEY: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const DEPOSITOR_ADDRESS: &str = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+    const RECIPIENT_PUBKEY: &str = "02c7f12003196442943d8588e01aee840423cc54fc1521526a3b85c2b0cbd58872";
+    const STRANGER_ADDRESS: &str = "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7";
+
+    fn escrow() -> EscrowRecord {
+        EscrowRecord {
+            id: "e1".to_string(),
+            escrow_id: "escrow_e1".to_string(),
+            depositor_pubkey: DEPOSITOR_PUBKEY.to_string(),
+            recipient_pubkey: RECIPIENT_PUBKEY.to_string(),
+            arbiter_pubkey: None,
+            escrow_type: EscrowType::TwoOfThree,
+            held_token_id: "TOAD".to_string(),
+            held_amount: 100,
+            release_hash: None,
+            expiry_height: 900_000,
+            status: EscrowStatus::Active,
+            created_at: 0,
+            order_id: None,
+            utxo_id: None,
+            tx_id: None,
+        }
+    }
+
+    fn session(address: &str) -> AuthSession {
+        AuthSession { address: address.to_string() }
+    }
+
+    #[test]
+    fn test_parties_are_matched_by_address() {
+        let all = [Party::Depositor, Party::Recipient, Party::Arbiter];
+        let party = require_party(Network::Testnet4, &escrow(), &session(DEPOSITOR_ADDRESS), &all, "release");
+        assert_eq!(party.unwrap(), Party::Depositor);
+
+        // The depositor is not the arbiter
+        let arbiter = require_party(Network::Testnet4, &escrow(), &session(DEPOSITOR_ADDRESS), &[Party::Arbiter], "resolve");
+        assert!(matches!(arbiter, Err(ApiError::Forbidden(_))));
+    }
+
+    #[test]
+    fn test_signed_in_strangers_are_rejected() {
+        let stranger = session(STRANGER_ADDRESS);
+        let all = [Party::Depositor, Party::Recipient, Party::Arbiter];
+        for action in ["release", "refund", "dispute", "resolve"] {
+            let result = require_party(Network::Testnet4, &escrow(), &stranger, &all, action);
+            assert!(matches!(result, Err(ApiError::Forbidden(_))));
+        }
+    }
+
+    #[test]
+    fn test_depositor_refunds_only_expired_escrows() {
+        let depositor = session(DEPOSITOR_ADDRESS);
+        let active = require_party(Network::Testnet4, &escrow(), &depositor, refund_parties(EscrowStatus::Active), "refund");
+        assert!(matches!(active, Err(ApiError::Forbidden(_))));
+
+        let expired = EscrowRecord { status: EscrowStatus::Expired, ..escrow() };
+        let party = require_party(Network::Testnet4, &expired, &depositor, refund_parties(expired.status), "refund");
+        assert_eq!(party.unwrap(), Party::Depositor);
+    }
+
+    #[test]
+    fn test_preimage_must_hash_to_release_hash() {
+        let release_hash = hex::encode(Sha256::digest(b"secret preimage"));
+        assert!(check_preimage(&release_hash, Some(&hex::encode(b"secret preimage"))).is_ok());
+        assert!(check_preimage(&release_hash.to_uppercase(), Some(&hex::encode(b"secret preimage"))).is_ok());
+        for preimage in [None, Some("zz"), Some("00")] {
+            assert!(matches!(check_preimage(&release_hash, preimage), Err(ApiError::BadRequest(_))));
+        }
+    }
+}
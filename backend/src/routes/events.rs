@@ -0,0 +1,148 @@
+//! Real-time event stream
+//!
+//! `GET /api/events` is a Server-Sent Events stream of order and escrow
+//! changes. Each event carries its log id, so a client that reconnects with
+//! `Last-Event-ID` (browsers send it automatically) or `last_event_id`
+//! first gets everything it missed.
+
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+
+use crate::error::{ApiError, ApiResult};
+use crate::services::events::{parse_market, EventFilter, StreamEvent};
+use crate::state::AppState;
+
+/// Events read from the log per query while catching up
+const REPLAY_BATCH: u32 = 500;
+
+/// Query parameters of the event stream
+#[derive(Debug, Deserialize)]
+pub struct EventStreamQuery {
+    /// Comma-separated pairs to follow, e.g. `TOAD/BTC`
+    pub market: Option<String>,
+    /// Comma-separated maker addresses or escrow party pubkeys to follow
+    pub address: Option<String>,
+    /// Resume after this event; `Last-Event-ID` takes precedence
+    pub last_event_id: Option<i64>,
+}
+
+/// Stream order and escrow events for the followed markets and addresses
+/// (everything when none are given)
+pub async fn stream_events(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<EventStreamQuery>,
+) -> ApiResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let filter = EventFilter {
+        markets: split_list(params.market.as_deref())
+            .map(|market| {
+                parse_market(&market)
+                    .ok_or_else(|| ApiError::BadRequest(format!("Invalid market '{}', expected BASE/QUOTE", market)))
+            })
+            .collect::<ApiResult<_>>()?,
+        addresses: split_list(params.address.as_deref()).collect(),
+    };
+    let resume_from = match headers.get("last-event-id") {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|id| id.trim().parse().ok())
+                .ok_or_else(|| ApiError::BadRequest("Last-Event-ID must be an event id".to_string()))?,
+        ),
+        None => params.last_event_id,
+    };
+
+    // Subscribe before reading the log so nothing falls between the two
+    let live = state.events.subscribe();
+    let last_id = match resume_from {
+        Some(id) => id,
+        None => state.events.latest_id().await?,
+    };
+
+    let (tx, rx) = mpsc::channel(64);
+    tokio::spawn(forward_events(state.clone(), filter, last_id, live, tx));
+    Ok(Sse::new(ReceiverStream::new(rx)).keep_alive(KeepAlive::default()))
+}
+
+/// Send a client the stored events after `last_id`, then the live ones
+///
+/// Ends when the client disconnects or the server shuts down. A client that
+/// falls behind the live feed catches up from the log again.
+async fn forward_events(
+    state: Arc<AppState>,
+    filter: EventFilter,
+    mut last_id: i64,
+    mut live: broadcast::Receiver<StreamEvent>,
+    tx: mpsc::Sender<Result<Event, Infallible>>,
+) {
+    let mut catch_up = true;
+    loop {
+        if catch_up {
+            loop {
+                let events = match state.events.since(last_id, &filter, REPLAY_BATCH).await {
+                    Ok(events) => events,
+                    Err(e) => {
+                        tracing::warn!("Failed to replay events after {}: {}", last_id, e);
+                        return;
+                    }
+                };
+                let done = events.len() < REPLAY_BATCH as usize;
+                for event in events {
+                    last_id = event.id;
+                    if !send(&tx, &event).await {
+                        return;
+                    }
+                }
+                if done {
+                    break;
+                }
+            }
+            catch_up = false;
+        }
+
+        match state.tasks.recv(&mut live).await {
+            Ok(event) => {
+                // Already replayed, or not followed
+                if event.id <= last_id || !filter.matches(&event) {
+                    continue;
+                }
+                last_id = event.id;
+                if !send(&tx, &event).await {
+                    return;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(_)) => catch_up = true,
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Send one event; false once the client is gone
+async fn send(tx: &mpsc::Sender<Result<Event, Infallible>>, event: &StreamEvent) -> bool {
+    let sse = match Event::default().id(event.id.to_string()).event(&event.kind).json_data(event) {
+        Ok(sse) => sse,
+        Err(e) => {
+            tracing::warn!("Failed to encode event {}: {}", event.id, e);
+            return true;
+        }
+    };
+    tx.send(Ok(sse)).await.is_ok()
+}
+
+/// Non-empty items of a comma-separated list
+fn split_list(list: Option<&str>) -> impl Iterator<Item = String> + '_ {
+    list.unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+}
@@ -0,0 +1,12 @@
+//! Fee rate endpoints
+
+use axum::{extract::State, Json};
+use std::sync::Arc;
+
+use crate::services::fees::{self, FeeQuote};
+use crate::state::AppState;
+
+/// Current fee rates (sat/vB) for fast, normal and slow confirmation
+pub async fn get_fee_quote(State(state): State<Arc<AppState>>) -> Json<FeeQuote> {
+    Json(fees::quote(state.chain.as_ref(), &state.config.fees).await)
+}
@@ -1,9 +1,12 @@
 //! Health check endpoints
 
-use axum::Json;
+use axum::{extract::State, Json};
 use serde::Serialize;
+use std::sync::Arc;
 use std::time::Instant;
 
+use crate::state::AppState;
+
 #[derive(Serialize)]
 pub struct HealthResponse {
     pub status: String,
@@ -23,15 +26,9 @@ pub struct ProverApiHealth {
 }
 
 /// Overall system health check endpoint
-pub async fn health_check() -> Json<HealthResponse> {
-    let mock_mode = std::env::var("MOCK_MODE")
-        .map(|v| v == "true")
-        .unwrap_or(true);
-
-    let api_url = std::env::var("CHARMS_PROVE_API_URL")
-        .unwrap_or_else(|_| "https://v8.charms.dev/spells/prove".to_string());
-
-    let prover_health = check_prover_api_internal(&api_url).await;
+pub async fn health_check(State(state): State<Arc<AppState>>) -> Json<HealthResponse> {
+    let mock_mode = state.config.mock_mode;
+    let prover_health = check_prover_api_internal(&state.config.prover_api_url).await;
 
     let status = if prover_health.reachable || mock_mode {
         "healthy"
@@ -49,11 +46,8 @@ pub async fn health_check() -> Json<HealthResponse> {
 }
 
 /// Check only the Prover API status
-pub async fn check_prover_api() -> Json<ProverApiHealth> {
-    let api_url = std::env::var("CHARMS_PROVE_API_URL")
-        .unwrap_or_else(|_| "https://v8.charms.dev/spells/prove".to_string());
-
-    Json(check_prover_api_internal(&api_url).await)
+pub async fn check_prover_api(State(state): State<Arc<AppState>>) -> Json<ProverApiHealth> {
+    Json(check_prover_api_internal(&state.config.prover_api_url).await)
 }
 
 /// Internal function to check Prover API reachability
@@ -0,0 +1,125 @@
+//! Market data endpoints
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::db;
+use crate::error::{ApiError, ApiResult};
+use crate::services::events::market_key;
+use crate::services::orderbook::{self, OrderBook, Pair};
+use crate::services::trades::{self, Candle, Trade, INTERVALS};
+use crate::state::AppState;
+
+const DEFAULT_BOOK_DEPTH: usize = 50;
+const MAX_BOOK_DEPTH: usize = 500;
+const DEFAULT_TRADES: u32 = 50;
+const MAX_TRADES: u32 = 500;
+const DEFAULT_CANDLES: i64 = 100;
+const MAX_CANDLES: i64 = 1000;
+
+/// Query parameters of the orderbook
+#[derive(Debug, Deserialize)]
+pub struct BookQuery {
+    /// Price levels per side (default 50)
+    pub depth: Option<usize>,
+}
+
+/// Query parameters of recent trades
+#[derive(Debug, Deserialize)]
+pub struct TradesQuery {
+    /// Trades to return (default 50)
+    pub limit: Option<u32>,
+}
+
+/// Query parameters of candles
+#[derive(Debug, Deserialize)]
+pub struct CandlesQuery {
+    /// `1m`, `5m`, `15m`, `1h` (default), `4h` or `1d`
+    pub interval: Option<String>,
+    /// Intervals to cover (default 100)
+    pub limit: Option<i64>,
+    /// End of the range in seconds since the epoch (default now)
+    pub end: Option<i64>,
+}
+
+/// Bids and asks of a pair, aggregated by price level
+///
+/// Orders of both directions are included, so `TOAD/BTC` and `BTC/TOAD`
+/// are the same book with bids and asks swapped.
+pub async fn get_order_book(
+    State(state): State<Arc<AppState>>,
+    Path((base, quote)): Path<(String, String)>,
+    Query(params): Query<BookQuery>,
+) -> ApiResult<Json<OrderBook>> {
+    let pair = resolve_pair(&state, &base, &quote)?;
+    let depth = params.depth.unwrap_or(DEFAULT_BOOK_DEPTH).clamp(1, MAX_BOOK_DEPTH);
+
+    let orders = db::get_pair_orders(&state.db, &base, &quote).await?;
+    Ok(Json(orderbook::build_book(&pair, &orders, depth)))
+}
+
+/// Executed trades of a pair, newest first
+pub async fn get_trades(
+    State(state): State<Arc<AppState>>,
+    Path((base, quote)): Path<(String, String)>,
+    Query(params): Query<TradesQuery>,
+) -> ApiResult<Json<Vec<Trade>>> {
+    let pair = resolve_pair(&state, &base, &quote)?;
+    let limit = params.limit.unwrap_or(DEFAULT_TRADES).clamp(1, MAX_TRADES);
+
+    let records = db::get_recent_trades(&state.db, &market_key(&base, &quote), limit).await?;
+    Ok(Json(records.iter().filter_map(|trade| trades::view(trade, &pair)).collect()))
+}
+
+/// OHLCV candles of a pair, oldest first
+///
+/// Intervals without trades have no candle.
+pub async fn get_candles(
+    State(state): State<Arc<AppState>>,
+    Path((base, quote)): Path<(String, String)>,
+    Query(params): Query<CandlesQuery>,
+) -> ApiResult<Json<Vec<Candle>>> {
+    let pair = resolve_pair(&state, &base, &quote)?;
+    let interval_name = params.interval.as_deref().unwrap_or("1h");
+    let interval = trades::interval_seconds(interval_name).ok_or_else(|| {
+        let names: Vec<&str> = INTERVALS.iter().map(|(name, _)| *name).collect();
+        ApiError::BadRequest(format!("Unknown interval '{}', expected one of {}", interval_name, names.join(", ")))
+    })?;
+    let limit = params.limit.unwrap_or(DEFAULT_CANDLES).clamp(1, MAX_CANDLES);
+    let end = params.end.unwrap_or_else(|| chrono::Utc::now().timestamp());
+
+    // Whole intervals, the last one holding `end`
+    let to = end.div_euclid(interval) * interval + interval;
+    let from = to - limit * interval;
+    let timestamp = |seconds: i64| {
+        chrono::DateTime::from_timestamp(seconds, 0)
+            .ok_or_else(|| ApiError::BadRequest(format!("end {} is out of range", end)))
+    };
+
+    let records = db::get_trades_between(&state.db, &market_key(&base, &quote), timestamp(from)?, timestamp(to)?).await?;
+    Ok(Json(trades::build_candles(&records, &pair, interval)))
+}
+
+/// A pair of two distinct registered tokens
+fn resolve_pair(state: &AppState, base: &str, quote: &str) -> ApiResult<Pair> {
+    if base.eq_ignore_ascii_case(quote) {
+        return Err(ApiError::BadRequest(format!("{}/{} is not a pair", base, quote)));
+    }
+    let decimals = |ticker: &str| {
+        state
+            .tokens
+            .get(ticker)
+            .map(|token| token.decimals as u32)
+            .ok_or_else(|| ApiError::not_found("token_not_found", format!("Token {} is not registered", ticker)))
+    };
+    Ok(Pair {
+        base: base.to_string(),
+        quote: quote.to_string(),
+        base_decimals: decimals(base)?,
+        quote_decimals: decimals(quote)?,
+    })
+}
@@ -0,0 +1,160 @@
+//! Matching engine endpoints
+//!
+//! The engine re-matches a pair whenever one of its orders changes and
+//! stores the crossing fills it finds as proposals, each with the spell
+//! that carries it out.
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use super::orders::build_match_spell;
+use crate::db::{self, MatchRecord};
+use crate::error::{ApiError, ApiResult};
+use crate::services::events::{market_key, EventKind, StreamEvent};
+use crate::services::matching::{self, Match};
+use crate::state::AppState;
+
+/// Query parameters for listing match proposals
+#[derive(Debug, Deserialize)]
+pub struct ListMatchesQuery {
+    /// Only proposals filling or paid by this order
+    pub order_id: Option<String>,
+    /// `proposed` (default) or `superseded`
+    pub status: Option<String>,
+}
+
+/// List match proposals
+pub async fn list_matches(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ListMatchesQuery>,
+) -> ApiResult<Json<Vec<MatchRecord>>> {
+    let status = params.status.as_deref().unwrap_or("proposed");
+    if !matches!(status, "proposed" | "superseded") {
+        return Err(ApiError::BadRequest(format!(
+            "Unknown match status '{}', expected proposed or superseded",
+            status
+        )));
+    }
+    Ok(Json(db::get_matches(&state.db, status, params.order_id.as_deref()).await?))
+}
+
+/// Get a match proposal by ID
+pub async fn get_match(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<MatchRecord>> {
+    db::get_match(&state.db, &id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("match_not_found", format!("Match {} not found", id)))
+}
+
+/// Match every pair with opted-in orders, then re-match pairs as their
+/// orders change
+pub async fn run_matching(state: Arc<AppState>, mut events: broadcast::Receiver<StreamEvent>) {
+    match_all_markets(&state).await;
+    loop {
+        match state.tasks.recv(&mut events).await {
+            Ok(event) => {
+                let is_order_change = event.kind.starts_with("order_");
+                if let Some(market) = event.market.filter(|_| is_order_change) {
+                    if let Err(e) = match_market(&state, &market).await {
+                        tracing::warn!("Failed to match orders of {}: {}", market, e);
+                    }
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(_)) => match_all_markets(&state).await,
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+async fn match_all_markets(state: &AppState) {
+    let pairs = match db::get_auto_match_pairs(&state.db).await {
+        Ok(pairs) => pairs,
+        Err(e) => {
+            tracing::warn!("Failed to list markets to match: {}", e);
+            return;
+        }
+    };
+    let markets: BTreeSet<String> = pairs.iter().map(|(a, b)| market_key(a, b)).collect();
+    for market in markets {
+        if let Err(e) = match_market(state, &market).await {
+            tracing::warn!("Failed to match orders of {}: {}", market, e);
+        }
+    }
+}
+
+/// Replace the proposals of a market with its current crossing fills
+async fn match_market(state: &AppState, market: &str) -> anyhow::Result<()> {
+    let Some((token_a, token_b)) = market.split_once('/') else {
+        return Ok(());
+    };
+    let orders = db::get_pair_orders(&state.db, token_a, token_b).await?;
+
+    let mut kept = Vec::new();
+    for proposal in matching::find_matches(&orders) {
+        let find = |id: &str| orders.iter().find(|order| order.id == id);
+        let (Some(maker), Some(taker)) = (find(&proposal.maker_order_id), find(&proposal.taker_order_id)) else {
+            continue;
+        };
+        let spell = match build_match_spell(state, &proposal, maker, taker) {
+            Ok(spell) => spell,
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to build the spell matching {} with {}: {}",
+                    proposal.maker_order_id, proposal.taker_order_id, e
+                );
+                continue;
+            }
+        };
+
+        let record = match_record(&proposal, spell);
+        let (id, inserted) = db::upsert_match(&state.db, &record).await?;
+        if inserted {
+            tracing::info!(
+                "Match {}: order {} fills {} of order {}",
+                id, proposal.taker_order_id, proposal.maker_amount, proposal.maker_order_id
+            );
+            let parties = vec![maker.maker_address.clone(), taker.maker_address.clone()];
+            let record = MatchRecord { id: id.clone(), ..record };
+            if let Err(e) = state
+                .events
+                .publish(EventKind::MatchProposed, Some(market.to_string()), parties, &record)
+                .await
+            {
+                tracing::warn!("Failed to publish match {}: {}", id, e);
+            }
+        }
+        kept.push(id);
+    }
+
+    let superseded = db::supersede_matches(&state.db, market, &kept).await?;
+    if superseded > 0 {
+        tracing::info!("{} match proposals of {} no longer apply", superseded, market);
+    }
+    Ok(())
+}
+
+fn match_record(proposal: &Match, spell: String) -> MatchRecord {
+    let now = chrono::Utc::now();
+    MatchRecord {
+        id: Uuid::new_v4().to_string(),
+        market: proposal.market.clone(),
+        maker_order_id: proposal.maker_order_id.clone(),
+        taker_order_id: proposal.taker_order_id.clone(),
+        maker_amount: proposal.maker_amount.to_string(),
+        taker_amount: proposal.taker_amount.to_string(),
+        spell,
+        status: "proposed".to_string(),
+        created_at: now,
+        updated_at: now,
+    }
+}
@@ -4,5 +4,12 @@ pub mod health;
 pub mod orders;
 pub mod wallet;
 pub mod spells;
+pub mod prove_jobs;
+pub mod fees;
+pub mod markets;
+pub mod matches;
+pub mod quote;
+pub mod tokens;
 pub mod escrow;
+pub mod events;
 
@@ -3,23 +3,35 @@
 //! Handles order creation, filling, cancellation with full Charms integration
 
 use axum::{
-    extract::{Path, Query, State},
+    extract::{Extension, Path, Query, State},
     Json,
 };
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::{Network, OutPoint, Transaction, TxOut, Txid};
+use std::str::FromStr;
 use serde::{Deserialize, Serialize};
+use std::collections::hash_map::{Entry, HashMap};
 use std::sync::Arc;
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
-use crate::db::{self, DbPool, OrderRecord};
-use crate::services::charms::{CharmsService, OrderSpellData, FillSpellData, SpellProveRequest};
-use crate::services::bitcoin::BitcoinService;
-
-/// Application state shared across handlers
-pub struct AppState {
-    pub charms: CharmsService,
-    pub bitcoin: BitcoinService,
-    pub db: DbPool,
-}
+use crate::db::{self, OrderCursor, OrderFilter, OrderRecord, OrderSort, TokenRecord, TradeRecord, TransactionRecord};
+use super::wallet::AuthSession;
+use crate::error::{ApiError, ApiResult};
+use crate::services::address;
+use crate::services::charms::{
+    BatchFillOrder, BatchFillSpellData, CancelSpellData, ExpireSpellData, FillSpellData,
+    OrderSpellData, PartialFillSpellData, ProvedTransaction, SpellProveRequest,
+};
+use crate::services::chain_watcher::ChainEvent;
+use crate::services::events::{market_key, EventKind};
+use crate::services::fees::{self, FeeTarget};
+use crate::services::matching::Match;
+use crate::services::prove_queue::JobStatus;
+use crate::services::psbt;
+use crate::services::routing::Route;
+use crate::state::AppState;
 
 /// Order status
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,6 +45,20 @@ pub enum OrderStatus {
     PendingSignature,
 }
 
+impl OrderStatus {
+    /// Parse the status column of an order record
+    pub fn from_db(status: &str) -> Self {
+        match status {
+            "open" => OrderStatus::Open,
+            "filled" => OrderStatus::Filled,
+            "cancelled" => OrderStatus::Cancelled,
+            "expired" => OrderStatus::Expired,
+            "partiallyfilled" => OrderStatus::PartiallyFilled,
+            _ => OrderStatus::PendingSignature,
+        }
+    }
+}
+
 /// Chain identifier - using String for flexibility
 pub type Chain = String;
 
@@ -78,6 +104,32 @@ pub struct Order {
     pub created_at: String,
     pub updated_at: String,
     pub utxo_id: Option<String>,
+    pub auto_match: bool,
+    pub min_fill_amount: String,
+}
+
+impl From<OrderRecord> for Order {
+    fn from(record: OrderRecord) -> Self {
+        Self {
+            id: record.id,
+            maker_address: record.maker_address,
+            offer_token: record.offer_token,
+            offer_amount: record.offer_amount,
+            want_token: record.want_token,
+            want_amount: record.want_amount,
+            source_chain: record.source_chain,
+            dest_chain: record.dest_chain,
+            status: OrderStatus::from_db(&record.status),
+            allow_partial: record.allow_partial,
+            filled_amount: record.filled_amount.unwrap_or_else(|| "0".to_string()),
+            expiry_height: record.expiry_height.unwrap_or(0) as u64,
+            created_at: record.created_at.to_rfc3339(),
+            updated_at: record.updated_at.to_rfc3339(),
+            utxo_id: record.utxo_id,
+            auto_match: record.auto_match,
+            min_fill_amount: record.min_fill_amount,
+        }
+    }
 }
 
 /// Create order request
@@ -97,8 +149,17 @@ pub struct CreateOrderRequest {
     pub funding_utxo: String,
     #[serde(default)]
     pub funding_utxo_value: Option<u64>,
+    /// Fee rate in sat/vB; estimated by the node when absent
+    #[serde(default)]
+    pub fee_rate: Option<f64>,
     #[serde(default)]
     pub dest_address: Option<String>,
+    /// Let the matching engine fill this order against crossing ones
+    #[serde(default)]
+    pub auto_match: bool,
+    /// Smallest partial fill accepted, except for the last remainder
+    #[serde(default)]
+    pub min_fill_amount: Option<String>,
 }
 
 /// Create order response with spell and unsigned transactions
@@ -108,6 +169,9 @@ pub struct CreateOrderResponse {
     pub spell: SpellData,
     pub unsigned_txs: Vec<UnsignedTransaction>,
     pub signing_instructions: SigningInstructions,
+    /// Set while proving is still in progress; poll `/api/prove-jobs/:id`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prove_job_id: Option<String>,
 }
 
 /// Spell data for proving
@@ -115,6 +179,7 @@ pub struct CreateOrderResponse {
 pub struct SpellData {
     pub spell_yaml: String,
     pub spell_yaml_built: String,  // With variables substituted
+    /// Swap app binary, hex encoded; empty in mock mode without a build
     pub app_binary: String,
     pub prev_txs: Vec<String>,
 }
@@ -124,6 +189,9 @@ pub struct SpellData {
 pub struct UnsignedTransaction {
     pub hex: String,
     pub txid: String,
+    /// BIP-174 PSBT (base64) to sign; absent in mock mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub psbt: Option<String>,
     pub inputs_to_sign: Vec<InputToSign>,
 }
 
@@ -152,6 +220,9 @@ pub struct FillOrderRequest {
     pub taker_utxo: String,
     #[serde(default)]
     pub taker_utxo_value: Option<u64>,
+    /// Fee rate in sat/vB; estimated by the node when absent
+    #[serde(default)]
+    pub fee_rate: Option<f64>,
     pub fill_amount: Option<String>,
 }
 
@@ -162,17 +233,57 @@ pub struct FillOrderResponse {
     pub spell: SpellData,
     pub unsigned_txs: Vec<UnsignedTransaction>,
     pub signing_instructions: SigningInstructions,
+    /// Set while proving is still in progress; poll `/api/prove-jobs/:id`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prove_job_id: Option<String>,
+}
+
+/// Cancel order request (the body is optional in mock mode)
+#[derive(Debug, Default, Deserialize)]
+pub struct CancelOrderRequest {
+    /// UTXO paying the cancel transaction fee
+    #[serde(default)]
+    pub funding_utxo: Option<String>,
+    #[serde(default)]
+    pub funding_utxo_value: Option<u64>,
+    /// Fee rate in sat/vB; estimated by the node when absent
+    #[serde(default)]
+    pub fee_rate: Option<f64>,
+}
+
+/// Expire order request, sent by a keeper reclaiming an expired order's
+/// tokens for its maker
+#[derive(Debug, Deserialize)]
+pub struct ExpireOrderRequest {
+    /// Receives the change of the funding UTXO
+    pub keeper_address: String,
+    /// UTXO paying the expire transaction fee
+    #[serde(default)]
+    pub funding_utxo: Option<String>,
+    #[serde(default)]
+    pub funding_utxo_value: Option<u64>,
+    /// Fee rate in sat/vB; estimated by the node when absent
+    #[serde(default)]
+    pub fee_rate: Option<f64>,
 }
 
 /// Query parameters for listing orders
 #[derive(Debug, Deserialize)]
 pub struct ListOrdersQuery {
     pub status: Option<String>,
+    /// List expired orders too; implied by `status=expired`
+    #[serde(default)]
+    pub include_expired: bool,
     pub offer_token: Option<String>,
     pub want_token: Option<String>,
     pub maker_address: Option<String>,
     pub source_chain: Option<String>,
     pub dest_chain: Option<String>,
+    /// `newest` (default), `oldest`, `price_asc` or `price_desc`
+    #[serde(default)]
+    pub sort: OrderSort,
+    /// `next_cursor` of the previous page; not combined with `offset`
+    pub cursor: Option<String>,
     pub limit: Option<u32>,
     pub offset: Option<u32>,
 }
@@ -181,16 +292,73 @@ pub struct ListOrdersQuery {
 #[derive(Debug, Serialize)]
 pub struct ListOrdersResponse {
     pub orders: Vec<Order>,
+    /// Orders matching the filters, across all pages
     pub total: u64,
     pub limit: u32,
     pub offset: u32,
+    /// Pass as `cursor` to get the next page; absent on the last one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
 /// Broadcast request
 #[derive(Debug, Deserialize)]
 pub struct BroadcastRequest {
-    pub signed_tx_hex: String,
-    pub order_id: String,
+    /// Signed PSBTs (base64 or hex), one per transaction to sign
+    #[serde(default)]
+    pub signed_psbts: Vec<String>,
+    /// A single signed transaction or PSBT
+    #[serde(default)]
+    pub signed_tx_hex: Option<String>,
+    /// Operation the transactions were signed for (`create`, `fill`,
+    /// `cancel`, `partial_fill` or `expire`); checked against the latest proved one
+    #[serde(default)]
+    pub operation: Option<String>,
+    #[serde(default)]
+    pub order_id: Option<String>,
+}
+
+/// A signed transaction sent back by the wallet
+enum SignedItem {
+    Psbt(bitcoin::Psbt),
+    Tx(Transaction),
+}
+
+impl SignedItem {
+    fn unsigned(&self) -> &Transaction {
+        match self {
+            SignedItem::Psbt(psbt) => &psbt.unsigned_tx,
+            SignedItem::Tx(tx) => tx,
+        }
+    }
+
+    fn txid(&self) -> Txid {
+        self.unsigned().compute_txid()
+    }
+}
+
+impl BroadcastRequest {
+    /// Decode everything the wallet signed
+    fn signed_items(&self) -> ApiResult<Vec<SignedItem>> {
+        let mut items = Vec::new();
+        for encoded in &self.signed_psbts {
+            items.push(SignedItem::Psbt(psbt::decode(encoded)?));
+        }
+        if let Some(signed) = &self.signed_tx_hex {
+            // Wallets that finalize may hand back either form
+            let item = match psbt::decode(signed) {
+                Ok(signed_psbt) => SignedItem::Psbt(signed_psbt),
+                Err(_) => SignedItem::Tx(bitcoin::consensus::encode::deserialize_hex(signed.trim()).map_err(|e| {
+                    ApiError::BadRequest(format!("signed_tx_hex is neither a PSBT nor a transaction: {}", e))
+                })?),
+            };
+            items.push(item);
+        }
+        if items.is_empty() {
+            return Err(ApiError::BadRequest("No signed transactions were sent".to_string()));
+        }
+        Ok(items)
+    }
 }
 
 /// Broadcast response
@@ -201,16 +369,62 @@ pub struct BroadcastResponse {
     pub message: String,
 }
 
+/// How to bump an order's fee
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BumpMethod {
+    /// Replace the transactions with ones paying a higher rate
+    Rbf,
+    /// Add a child paying for the unconfirmed package
+    Cpfp,
+}
+
+/// Fee bump request
+#[derive(Debug, Deserialize)]
+pub struct BumpFeeRequest {
+    pub method: BumpMethod,
+    /// Target rate in sat/vB; the fast estimate when absent
+    #[serde(default)]
+    pub fee_rate: Option<f64>,
+}
+
+/// Fee bump response
+#[derive(Debug, Serialize)]
+pub struct BumpFeeResponse {
+    pub method: BumpMethod,
+    /// Rate the new transactions pay, in sat/vB
+    pub fee_rate: f64,
+    pub unsigned_txs: Vec<UnsignedTransaction>,
+    /// Set while the replacement is still being proved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prove_job_id: Option<String>,
+}
+
 // ============ App Configuration ============
-// Built with: charms app build && charms app vk
+// App VKs come from the binaries loaded into `AppState::apps`
 
 const DEFAULT_APP_ID: &str = "liquid-swap";
-const DEFAULT_APP_VK: &str = "857ee181813511526321296bb0183b7496e1cdc0801552495464e9ec44c37718";
-const DEFAULT_TOKEN_ID: &str = "toad-token";
-const DEFAULT_TOKEN_VK: &str = "857ee181813511526321296bb0183b7496e1cdc0801552495464e9ec44c37718";
 
-// Path to the compiled WASM binary
-const APP_WASM_PATH: &str = "target/wasm32-wasip1/release/liquid-swap-app.wasm";
+// ============ Transaction Records ============
+// `tx_type` of a transaction row names the order operation it was proved for
+
+const OP_CREATE: &str = "create";
+const OP_FILL: &str = "fill";
+const OP_CANCEL: &str = "cancel";
+const OP_PARTIAL_FILL: &str = "partial_fill";
+const OP_EXPIRE: &str = "expire";
+/// Child paying for an unconfirmed order transaction
+const OP_CPFP: &str = "cpfp";
+
+const TX_PROVING: &str = "proving";
+const TX_PROVED: &str = "proved";
+const TX_BROADCAST: &str = "broadcast";
+
+// ============ Listing ============
+
+const ORDER_STATUSES: &[&str] = &["open", "filled", "cancelled", "expired", "partiallyfilled", "pendingsignature"];
+const DEFAULT_PAGE_SIZE: u32 = 20;
+const MAX_PAGE_SIZE: u32 = 100;
 
 // ============ Spell Templates ============
 
@@ -218,125 +432,111 @@ const CREATE_ORDER_SPELL: &str = include_str!("../../../apps/swap-app/spells/cre
 const FILL_ORDER_SPELL: &str = include_str!("../../../apps/swap-app/spells/fill-order.yaml");
 const CANCEL_ORDER_SPELL: &str = include_str!("../../../apps/swap-app/spells/cancel-order.yaml");
 const PARTIAL_FILL_SPELL: &str = include_str!("../../../apps/swap-app/spells/partial-fill.yaml");
+const EXPIRE_ORDER_SPELL: &str = include_str!("../../../apps/swap-app/spells/expire-order.yaml");
+const BATCH_FILL_SPELL: &str = include_str!("../../../apps/swap-app/spells/batch-fill.yaml");
+
 
 // ============ Route Handlers ============
 
-/// List all orders with optional filters
+/// List orders matching the query filters, a page at a time
 pub async fn list_orders(
     State(state): State<Arc<AppState>>,
     Query(params): Query<ListOrdersQuery>,
-) -> Json<ListOrdersResponse> {
-    // Fetch orders from database
-    let db_orders = match db::get_all_orders(&state.db).await {
-        Ok(orders) => orders,
-        Err(e) => {
-            tracing::error!("Failed to fetch orders: {}", e);
-            Vec::new()
+) -> ApiResult<Json<ListOrdersResponse>> {
+    if let Some(status) = params.status.as_deref() {
+        if !ORDER_STATUSES.contains(&status) {
+            return Err(ApiError::BadRequest(format!(
+                "Unknown order status '{}', expected one of {}",
+                status,
+                ORDER_STATUSES.join(", ")
+            )));
         }
-    };
+    }
+    if params.cursor.is_some() && params.offset.is_some() {
+        return Err(ApiError::BadRequest("Pass either cursor or offset, not both".to_string()));
+    }
+    let after = params.cursor.as_deref().map(|cursor| decode_cursor(cursor, params.sort)).transpose()?;
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let offset = params.offset.unwrap_or(0);
 
-    // Convert database records to API response format
-    let orders: Vec<Order> = db_orders
-        .into_iter()
-        .map(|record| Order {
-            id: record.id,
-            maker_address: record.maker_address,
-            offer_token: record.offer_token,
-            offer_amount: record.offer_amount,
-            want_token: record.want_token,
-            want_amount: record.want_amount,
-            source_chain: record.source_chain,
-            dest_chain: record.dest_chain,
-            status: match record.status.as_str() {
-                "open" => OrderStatus::Open,
-                "filled" => OrderStatus::Filled,
-                "cancelled" => OrderStatus::Cancelled,
-                "expired" => OrderStatus::Expired,
-                "partiallyfilled" => OrderStatus::PartiallyFilled,
-                _ => OrderStatus::PendingSignature,
-            },
-            allow_partial: record.allow_partial,
-            filled_amount: record.filled_amount.unwrap_or_else(|| "0".to_string()),
-            expiry_height: record.expiry_height.unwrap_or(0) as u64,
-            created_at: record.created_at.to_rfc3339(),
-            updated_at: record.updated_at.to_rfc3339(),
-            utxo_id: record.utxo_id,
-        })
-        .collect();
+    let filter = OrderFilter {
+        status: params.status,
+        // Expired orders are hidden unless asked for
+        exclude_expired: !params.include_expired,
+        offer_token: params.offer_token,
+        want_token: params.want_token,
+        maker_address: params.maker_address,
+        // Chains are stored normalized
+        source_chain: params.source_chain.as_deref().map(normalize_chain),
+        dest_chain: params.dest_chain.as_deref().map(normalize_chain),
+    };
 
-    let total = orders.len() as u64;
-    let limit = params.limit.unwrap_or(20);
-    let offset = params.offset.unwrap_or(0);
+    // One extra row tells whether there is a next page
+    let mut records = db::list_orders(&state.db, &filter, params.sort, after.as_ref(), limit + 1, offset).await?;
+    let next_cursor = if records.len() > limit as usize {
+        records.truncate(limit as usize);
+        records.last().map(|record| encode_cursor(&OrderCursor::after(params.sort, record)))
+    } else {
+        None
+    };
+    let total = db::count_orders(&state.db, &filter).await?;
 
-    Json(ListOrdersResponse {
+    Ok(Json(ListOrdersResponse {
+        orders: records.into_iter().map(|record| Order::from(record.order)).collect(),
         total,
-        orders,
         limit,
         offset,
-    })
+        next_cursor,
+    }))
 }
 
 /// Get a specific order by ID
 pub async fn get_order(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
-) -> Json<Option<Order>> {
-    // Fetch from database
-    match db::get_order_by_id(&state.db, &id).await {
-        Ok(Some(record)) => {
-            Json(Some(Order {
-                id: record.id,
-                maker_address: record.maker_address,
-                offer_token: record.offer_token,
-                offer_amount: record.offer_amount,
-                want_token: record.want_token,
-                want_amount: record.want_amount,
-                source_chain: record.source_chain,
-                dest_chain: record.dest_chain,
-                status: match record.status.as_str() {
-                    "open" => OrderStatus::Open,
-                    "filled" => OrderStatus::Filled,
-                    "cancelled" => OrderStatus::Cancelled,
-                    "expired" => OrderStatus::Expired,
-                    "partiallyfilled" => OrderStatus::PartiallyFilled,
-                    _ => OrderStatus::PendingSignature,
-                },
-                allow_partial: record.allow_partial,
-                filled_amount: record.filled_amount.unwrap_or_else(|| "0".to_string()),
-                expiry_height: record.expiry_height.unwrap_or(0) as u64,
-                created_at: record.created_at.to_rfc3339(),
-                updated_at: record.updated_at.to_rfc3339(),
-                utxo_id: record.utxo_id,
-            }))
-        }
-        Ok(None) => Json(None),
-        Err(e) => {
-            tracing::error!("Failed to fetch order {}: {}", id, e);
-            Json(None)
-        }
-    }
+) -> ApiResult<Json<Order>> {
+    let record = load_order(&state, &id).await?;
+    Ok(Json(record.into()))
 }
 
 /// Create a new order - builds spell and calls prover
 pub async fn create_order(
     State(state): State<Arc<AppState>>,
+    Extension(session): Extension<AuthSession>,
     Json(req): Json<CreateOrderRequest>,
-) -> Json<CreateOrderResponse> {
+) -> ApiResult<Json<CreateOrderResponse>> {
+    require_address(&session, "maker_address", &req.maker_address)?;
+    let maker_pubkey = check_party(state.config.network, "maker_address", &req.maker_address, "maker_pubkey", req.maker_pubkey.as_deref())?;
+    if let Some(dest_address) = req.dest_address.as_deref().filter(|_| normalize_chain(&req.dest_chain) == "bitcoin") {
+        address::parse_address("dest_address", dest_address, state.config.network)?;
+    }
     let order_id = Uuid::new_v4().to_string();
     let now = chrono::Utc::now();
     
     // Validate funding UTXO
-    if req.funding_utxo.is_empty() || req.funding_utxo == "pending" {
-        tracing::warn!("Invalid funding UTXO: {}. Using mock mode.", req.funding_utxo);
-        // In real mode, we need a valid UTXO. For now, fall back to mock mode
-        // TODO: Get actual UTXO from wallet
+    if !state.charms.is_mock_mode() && (req.funding_utxo.is_empty() || req.funding_utxo == "pending") {
+        return Err(ApiError::BadRequest(format!("Invalid funding UTXO: '{}'", req.funding_utxo)));
     }
-    
-    // Get current block height for expiry calculation
-    let current_height = match state.bitcoin.get_blockchain_info().await {
-        Ok(info) => info.blocks,
-        Err(_) => 850000, // Fallback
+    let offer_amount = parse_amount("offer_amount", &req.offer_amount)?;
+    parse_amount("want_amount", &req.want_amount)?;
+    let min_fill_amount = match req.min_fill_amount.as_deref() {
+        Some(value) => parse_amount("min_fill_amount", value)?,
+        None => 0,
     };
+    if min_fill_amount > offer_amount {
+        return Err(ApiError::BadRequest(format!(
+            "min_fill_amount {} exceeds the offer_amount {}",
+            min_fill_amount, offer_amount
+        )));
+    }
+    
+    // Expiry is counted from the real tip; an order cannot be priced in
+    // blocks without one
+    let current_height = state
+        .chain
+        .tip_height()
+        .await
+        .map_err(|e| ApiError::ChainUnavailable(format!("Failed to get the chain tip: {}", e)))?;
     
     let expiry_height = current_height + req.expiry_blocks;
     
@@ -344,22 +544,25 @@ pub async fn create_order(
     let source_chain = normalize_chain(&req.source_chain);
     let dest_chain = normalize_chain(&req.dest_chain);
     
-    // Generate escrow address (in production, this would be derived from the contract)
-    let escrow_address = format!("tb1q_escrow_{}", &order_id[..8]);
+    // Tokens are offered on the source chain and paid on the destination
+    let offer_token = resolve_token(&state, &req.offer_token, &source_chain)?;
+    let want_token = resolve_token(&state, &req.want_token, &dest_chain)?;
     
     // Prepare spell data
     let order_spell_data = OrderSpellData {
         maker_address: req.maker_address.clone(),
-        maker_pubkey: req.maker_pubkey.clone().unwrap_or_else(|| req.maker_address.clone()),
-        offer_token_id: DEFAULT_TOKEN_ID.to_string(),
-        offer_token_vk: DEFAULT_TOKEN_VK.to_string(),
+        maker_pubkey: maker_pubkey.clone().unwrap_or_else(|| req.maker_address.clone()),
+        offer_token_id: offer_token.app_id.clone(),
+        offer_token_vk: offer_token.vk.clone(),
         offer_amount: req.offer_amount.clone(),
-        want_token_id: req.want_token.clone().to_lowercase(),
+        want_token_id: want_token.app_id.clone(),
+        want_token_vk: want_token.vk.clone(),
         want_amount: req.want_amount.clone(),
         expiry_height,
         allow_partial: req.allow_partial,
+        min_fill_amount: min_fill_amount.to_string(),
         funding_utxo: req.funding_utxo.clone(),
-        escrow_address: escrow_address.clone(),
+        escrow_address: escrow_address(&order_id),
         dest_chain: chain_to_id(&dest_chain),
         dest_address: req.dest_address.clone().unwrap_or_else(|| req.maker_address.clone()),
     };
@@ -369,95 +572,32 @@ pub async fn create_order(
         CREATE_ORDER_SPELL,
         &order_spell_data,
         DEFAULT_APP_ID,
-        DEFAULT_APP_VK,
-    ).unwrap_or_else(|e| {
-        tracing::error!("Failed to build spell: {}", e);
-        CREATE_ORDER_SPELL.to_string()
-    });
-    
-    // Validate the spell
-    if let Err(e) = state.charms.validate_spell(&spell_built) {
-        tracing::warn!("Spell validation warning: {}", e);
-    }
-    
-    // Call the Charms Prover API
-    let proved_txs = if !state.charms.is_mock_mode() {
-        // Load app binary if path is set
-        let mut binaries = std::collections::BTreeMap::new();
-        if let Ok(binary_path) = std::env::var("SWAP_APP_BINARY_PATH") {
-            if let Ok(binary_data) = tokio::fs::read(&binary_path).await {
-                let app_vk = std::env::var("SWAP_APP_VK")
-                    .unwrap_or_else(|_| DEFAULT_APP_VK.to_string());
-                binaries.insert(app_vk, binary_data);
-                tracing::info!("Loaded app binary from: {}", binary_path);
-            } else {
-                tracing::warn!("Failed to load app binary from: {}", binary_path);
-            }
-        }
-        
-        let prove_request = SpellProveRequest {
-            spell: spell_built.clone(),
-            binaries,
-            prev_txs: vec![],
-            funding_utxo: req.funding_utxo.clone(),
-            funding_utxo_value: req.funding_utxo_value.unwrap_or(10000),
-            change_address: req.maker_address.clone(),
-            fee_rate: 10.0,
-            chain: "testnet4".to_string(),
-        };
-        
-        match state.charms.prove_spell(prove_request).await {
-            Ok(txs) => {
-                if txs.is_empty() {
-                    tracing::warn!("Prover API returned empty transactions, falling back to mock");
-                    // Fallback to mock transaction
-                    vec![crate::services::charms::ProvedTransaction {
-                        hex: format!("0200000001...mock_fallback_{}...", order_id),
-                        txid: format!("mock_fallback_{}", order_id),
-                    }]
-                } else {
-                    txs
-                }
-            }
-            Err(e) => {
-                tracing::error!("Prover API error: {}. Falling back to mock transaction.", e);
-                // Fallback to mock transaction when API fails
-                vec![crate::services::charms::ProvedTransaction {
-                    hex: format!("0200000001...mock_fallback_{}...", order_id),
-                    txid: format!("mock_fallback_{}", order_id),
-                }]
-            }
-        }
-    } else {
-        // Mock mode - generate mock transaction
-        vec![crate::services::charms::ProvedTransaction {
-            hex: "0200000001...mock...".to_string(),
-            txid: format!("mock_{}", order_id),
-        }]
-    };
+        &state.apps.swap.vk,
+    )?;
     
-    // Create unsigned transactions for signing
-    let unsigned_txs: Vec<UnsignedTransaction> = proved_txs.iter().map(|tx| {
-        UnsignedTransaction {
-            hex: tx.hex.clone(),
-            txid: tx.txid.clone(),
-            inputs_to_sign: vec![
-                InputToSign {
-                    index: 0,
-                    address: req.maker_address.clone(),
-                    sighash_type: "SIGHASH_DEFAULT".to_string(),
-                }
-            ],
-        }
-    }).collect();
+    let proved = prove_transactions(
+        &state,
+        &spell_built,
+        &req.funding_utxo,
+        req.funding_utxo_value,
+        req.fee_rate,
+        &req.maker_address,
+        &order_id,
+    ).await?;
+    let unsigned_txs = unsigned_transactions(
+        &state,
+        &proved.transactions,
+        &req.maker_address,
+        maker_pubkey.as_deref(),
+    ).await?;
     
     // Create the order record
     let order = Order {
         id: order_id.clone(),
         maker_address: req.maker_address.clone(),
-        offer_token: req.offer_token.clone(),
+        offer_token: offer_token.ticker.clone(),
         offer_amount: req.offer_amount.clone(),
-        want_token: req.want_token.clone(),
+        want_token: want_token.ticker.clone(),
         want_amount: req.want_amount.clone(),
         source_chain: source_chain.clone(),
         dest_chain: dest_chain.clone(),
@@ -468,15 +608,17 @@ pub async fn create_order(
         created_at: now.to_rfc3339(),
         updated_at: now.to_rfc3339(),
         utxo_id: Some(req.funding_utxo.clone()),
+        auto_match: req.auto_match,
+        min_fill_amount: min_fill_amount.to_string(),
     };
 
     // Store order in database
     let db_record = OrderRecord {
         id: order_id.clone(),
         maker_address: req.maker_address.clone(),
-        offer_token: req.offer_token.clone(),
+        offer_token: offer_token.ticker,
         offer_amount: req.offer_amount.clone(),
-        want_token: req.want_token,
+        want_token: want_token.ticker,
         want_amount: req.want_amount,
         source_chain,
         dest_chain,
@@ -488,20 +630,22 @@ pub async fn create_order(
         tx_id: None,
         created_at: now,
         updated_at: now,
+        auto_match: req.auto_match,
+        min_fill_amount: min_fill_amount.to_string(),
+        maker_pubkey,
     };
 
-    if let Err(e) = db::insert_order(&state.db, &db_record).await {
-        tracing::error!("Failed to insert order into database: {}", e);
-    } else {
-        tracing::info!("Order {} saved to database", order_id);
-    }
+    db::insert_order(&state.db, &db_record).await?;
+    record_transactions(&state, &order_id, OP_CREATE, &proved).await?;
+    publish_order_event(&state, EventKind::OrderCreated, &order_id).await;
+    tracing::info!("Order {} saved to database", order_id);
     
-    Json(CreateOrderResponse {
+    Ok(Json(CreateOrderResponse {
         order,
         spell: SpellData {
             spell_yaml: CREATE_ORDER_SPELL.to_string(),
             spell_yaml_built: spell_built,
-            app_binary: "".to_string(), // TODO: Load compiled app binary
+            app_binary: hex::encode(&state.apps.swap.binary),
             prev_txs: vec![],
         },
         unsigned_txs,
@@ -514,61 +658,37 @@ pub async fn create_order(
             ],
             broadcast_endpoint: format!("/api/orders/{}/broadcast", order_id),
         },
-    })
+        prove_job_id: proved.pending_job_id(),
+    }))
 }
 
 /// Fill an order (atomic swap)
 pub async fn fill_order(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
+    Extension(session): Extension<AuthSession>,
     Json(req): Json<FillOrderRequest>,
-) -> Json<FillOrderResponse> {
+) -> ApiResult<Json<FillOrderResponse>> {
+    require_address(&session, "taker_address", &req.taker_address)?;
+    let taker_pubkey = check_party(state.config.network, "taker_address", &req.taker_address, "taker_pubkey", req.taker_pubkey.as_deref())?;
     let now = chrono::Utc::now();
     
-    // TODO: Lookup order from database
-    let existing_order = Order {
-        id: id.clone(),
-        maker_address: "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
-        offer_token: "TOAD".to_string(),
-        offer_amount: "1000".to_string(),
-        want_token: "BTC".to_string(),
-        want_amount: "10000".to_string(),
-        source_chain: "bitcoin".to_string(),
-        dest_chain: "bitcoin".to_string(),
-        status: OrderStatus::Open,
-        allow_partial: true,
-        filled_amount: "0".to_string(),
-        expiry_height: 850000,
-        created_at: now.to_rfc3339(),
-        updated_at: now.to_rfc3339(),
-        utxo_id: Some("abc123:0".to_string()),
-    };
+    let record = load_order(&state, &id).await?;
+    if record.status != "open" {
+        return Err(ApiError::Conflict(format!("Order {} is {}, not open", id, record.status)));
+    }
+    let order_utxo = order_utxo(&record)?;
     
     // Prepare fill spell data
-    let order_spell_data = OrderSpellData {
-        maker_address: existing_order.maker_address.clone(),
-        maker_pubkey: existing_order.maker_address.clone(),
-        offer_token_id: DEFAULT_TOKEN_ID.to_string(),
-        offer_token_vk: DEFAULT_TOKEN_VK.to_string(),
-        offer_amount: existing_order.offer_amount.clone(),
-        want_token_id: existing_order.want_token.clone().to_lowercase(),
-        want_amount: existing_order.want_amount.clone(),
-        expiry_height: existing_order.expiry_height,
-        allow_partial: existing_order.allow_partial,
-        funding_utxo: existing_order.utxo_id.clone().unwrap_or_default(),
-        escrow_address: "".to_string(),
-        dest_chain: chain_to_id(&existing_order.dest_chain),
-        dest_address: existing_order.maker_address.clone(),
-    };
-    
+    let order_spell_data = order_spell_data(&state, &record)?;
     let fill_spell_data = FillSpellData {
-        order_utxo: existing_order.utxo_id.clone().unwrap_or_default(),
+        order_utxo,
         taker_utxo: req.taker_utxo.clone(),
-        taker_pubkey: req.taker_pubkey.clone().unwrap_or_else(|| req.taker_address.clone()),
+        taker_pubkey: taker_pubkey.clone().unwrap_or_else(|| req.taker_address.clone()),
         taker_address: req.taker_address.clone(),
-        maker_address: existing_order.maker_address.clone(),
-        offer_amount: existing_order.offer_amount.clone(),
-        want_amount: existing_order.want_amount.clone(),
+        maker_address: record.maker_address.clone(),
+        offer_amount: record.offer_amount.clone(),
+        want_amount: record.want_amount.clone(),
         fill_amount: req.fill_amount.clone(),
     };
     
@@ -578,51 +698,38 @@ pub async fn fill_order(
         &fill_spell_data,
         &order_spell_data,
         DEFAULT_APP_ID,
-        DEFAULT_APP_VK,
-    ).unwrap_or_else(|e| {
-        tracing::error!("Failed to build fill spell: {}", e);
-        FILL_ORDER_SPELL.to_string()
-    });
-    
-    // Call prover (mock for now)
-    let unsigned_txs = vec![
-        UnsignedTransaction {
-            hex: "0200000001...mock_fill...".to_string(),
-            txid: format!("mock_fill_{}", id),
-            inputs_to_sign: vec![
-                InputToSign {
-                    index: 0,
-                    address: req.taker_address.clone(),
-                    sighash_type: "SIGHASH_DEFAULT".to_string(),
-                }
-            ],
-        }
-    ];
+        &state.apps.swap.vk,
+    )?;
     
-    let order = Order {
-        id: id.clone(),
-        maker_address: existing_order.maker_address,
-        offer_token: existing_order.offer_token,
-        offer_amount: existing_order.offer_amount.clone(),
-        want_token: existing_order.want_token,
-        want_amount: existing_order.want_amount,
-        source_chain: existing_order.source_chain,
-        dest_chain: existing_order.dest_chain,
-        status: OrderStatus::PendingSignature,
-        allow_partial: existing_order.allow_partial,
-        filled_amount: existing_order.offer_amount, // Full fill
-        expiry_height: existing_order.expiry_height,
-        created_at: existing_order.created_at,
-        updated_at: now.to_rfc3339(),
-        utxo_id: None,
-    };
+    let proved = prove_transactions(
+        &state,
+        &spell_built,
+        &req.taker_utxo,
+        req.taker_utxo_value,
+        req.fee_rate,
+        &req.taker_address,
+        &id,
+    ).await?;
+    let unsigned_txs = unsigned_transactions(
+        &state,
+        &proved.transactions,
+        &req.taker_address,
+        taker_pubkey.as_deref(),
+    ).await?;
+    record_transactions(&state, &id, OP_FILL, &proved).await?;
+    record_pending_trade(&state, &record, &proved, &req.taker_address, &record.offer_amount, &record.want_amount).await?;
+    
+    let mut order = Order::from(record);
+    order.status = OrderStatus::PendingSignature;
+    order.filled_amount = order.offer_amount.clone(); // Full fill
+    order.updated_at = now.to_rfc3339();
 
-    Json(FillOrderResponse {
+    Ok(Json(FillOrderResponse {
         order,
         spell: SpellData {
             spell_yaml: FILL_ORDER_SPELL.to_string(),
             spell_yaml_built: spell_built,
-            app_binary: "".to_string(),
+            app_binary: hex::encode(&state.apps.swap.binary),
             prev_txs: vec![],
         },
         unsigned_txs,
@@ -635,60 +742,87 @@ pub async fn fill_order(
             ],
             broadcast_endpoint: format!("/api/orders/{}/broadcast", id),
         },
-    })
+        prove_job_id: proved.pending_job_id(),
+    }))
 }
 
 /// Cancel an order
 pub async fn cancel_order(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
-) -> Json<FillOrderResponse> {
+    Extension(session): Extension<AuthSession>,
+    body: Option<Json<CancelOrderRequest>>,
+) -> ApiResult<Json<FillOrderResponse>> {
     let now = chrono::Utc::now();
+    let req = body.map(|Json(req)| req).unwrap_or_default();
     
-    // Update order status to cancelled in database
-    if let Err(e) = db::update_order_status(&state.db, &id, "cancelled").await {
-        tracing::error!("Failed to update order status: {}", e);
+    let record = load_order(&state, &id).await?;
+    if record.maker_address != session.address {
+        return Err(ApiError::Forbidden(format!("Only the maker of order {} can cancel it", id)));
+    }
+    if !matches!(record.status.as_str(), "open" | "partiallyfilled" | "pendingsignature") {
+        return Err(ApiError::Conflict(format!("Order {} is {} and cannot be cancelled", id, record.status)));
     }
     
-    // Build cancel spell
-    let spell_built = CANCEL_ORDER_SPELL.to_string();
+    // An order that never reached the chain has nothing to unlock
+    let (spell_built, proved) = if record.tx_id.is_none() {
+        (String::new(), ProveOutcome::default())
+    } else {
+        let (offer_amount, _, filled_amount) = order_amounts(&record)?;
+        let cancel_data = CancelSpellData {
+            order_utxo: order_utxo(&record)?,
+            maker_address: record.maker_address.clone(),
+            filled_amount,
+            remaining_amount: offer_amount.saturating_sub(filled_amount),
+        };
+        let spell_built = state.charms.build_cancel_order_spell(
+            CANCEL_ORDER_SPELL,
+            &cancel_data,
+            &order_spell_data(&state, &record)?,
+            DEFAULT_APP_ID,
+            &state.apps.swap.vk,
+        )?;
+        
+        let funding_utxo = match req.funding_utxo {
+            Some(utxo) => utxo,
+            None if state.charms.is_mock_mode() => String::new(),
+            None => {
+                return Err(ApiError::BadRequest(
+                    "funding_utxo is required to pay for the cancel transaction".to_string(),
+                ))
+            }
+        };
+        let proved = prove_transactions(
+            &state,
+            &spell_built,
+            &funding_utxo,
+            req.funding_utxo_value,
+            req.fee_rate,
+            &record.maker_address,
+            &id,
+        ).await?;
+        record_transactions(&state, &id, OP_CANCEL, &proved).await?;
+        (spell_built, proved)
+    };
+    let unsigned_txs = unsigned_transactions(&state, &proved.transactions, &record.maker_address, None).await?;
+    
+    // An order on chain stays live until its cancel transaction confirms;
+    // one that never got there, or a simulated one, is cancelled now
+    let on_chain = record.tx_id.is_some() && !state.charms.is_mock_mode();
+    let mut order = Order::from(record);
+    if !on_chain {
+        db::update_order_status(&state.db, &id, "cancelled").await?;
+        publish_order_event(&state, EventKind::OrderCancelled, &id).await;
+        order.status = OrderStatus::Cancelled;
+        order.updated_at = now.to_rfc3339();
+    }
     
-    let unsigned_txs = vec![
-        UnsignedTransaction {
-            hex: "0200000001...mock_cancel...".to_string(),
-            txid: format!("mock_cancel_{}", id),
-            inputs_to_sign: vec![
-                InputToSign {
-                    index: 0,
-                    address: "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
-                    sighash_type: "SIGHASH_DEFAULT".to_string(),
-                }
-            ],
-        }
-    ];
-    
-    Json(FillOrderResponse {
-        order: Order {
-            id: id.clone(),
-            maker_address: "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
-            offer_token: "TOAD".to_string(),
-            offer_amount: "1000".to_string(),
-            want_token: "BTC".to_string(),
-            want_amount: "10000".to_string(),
-            source_chain: "bitcoin".to_string(),
-            dest_chain: "bitcoin".to_string(),
-            status: OrderStatus::PendingSignature,
-            allow_partial: true,
-            filled_amount: "0".to_string(),
-            expiry_height: 850000,
-            created_at: now.to_rfc3339(),
-            updated_at: now.to_rfc3339(),
-            utxo_id: None,
-        },
+    Ok(Json(FillOrderResponse {
+        order,
         spell: SpellData {
             spell_yaml: CANCEL_ORDER_SPELL.to_string(),
             spell_yaml_built: spell_built,
-            app_binary: "".to_string(),
+            app_binary: hex::encode(&state.apps.swap.binary),
             prev_txs: vec![],
         },
         unsigned_txs,
@@ -697,60 +831,116 @@ pub async fn cancel_order(
             steps: vec![
                 "1. Your escrowed tokens will be returned".to_string(),
                 "2. Sign the transaction to cancel".to_string(),
+                "3. The order is cancelled once the transaction confirms".to_string(),
             ],
             broadcast_endpoint: format!("/api/orders/{}/broadcast", id),
         },
-    })
+        prove_job_id: proved.pending_job_id(),
+    }))
 }
 
 /// Partially fill an order
 pub async fn partial_fill_order(
-    State(_state): State<Arc<AppState>>,
+    State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
+    Extension(session): Extension<AuthSession>,
     Json(req): Json<FillOrderRequest>,
-) -> Json<FillOrderResponse> {
-    let fill_amount = req.fill_amount.clone().unwrap_or("500".to_string());
+) -> ApiResult<Json<FillOrderResponse>> {
+    require_address(&session, "taker_address", &req.taker_address)?;
+    let taker_pubkey = check_party(state.config.network, "taker_address", &req.taker_address, "taker_pubkey", req.taker_pubkey.as_deref())?;
     let now = chrono::Utc::now();
     
-    // Build partial fill spell
-    let spell_built = PARTIAL_FILL_SPELL.to_string();
+    let record = load_order(&state, &id).await?;
+    if !record.allow_partial {
+        return Err(ApiError::Conflict(format!("Order {} does not allow partial fills", id)));
+    }
+    if !matches!(record.status.as_str(), "open" | "partiallyfilled") {
+        return Err(ApiError::Conflict(format!("Order {} is {}, not open", id, record.status)));
+    }
     
-    let unsigned_txs = vec![
-        UnsignedTransaction {
-            hex: "0200000001...mock_partial...".to_string(),
-            txid: format!("mock_partial_{}", id),
-            inputs_to_sign: vec![
-                InputToSign {
-                    index: 0,
-                    address: req.taker_address.clone(),
-                    sighash_type: "SIGHASH_DEFAULT".to_string(),
-                }
-            ],
-        }
-    ];
-    
-    Json(FillOrderResponse {
-        order: Order {
-            id: id.clone(),
-            maker_address: "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
-            offer_token: "TOAD".to_string(),
-            offer_amount: "1000".to_string(),
-            want_token: "BTC".to_string(),
-            want_amount: "10000".to_string(),
-            source_chain: "bitcoin".to_string(),
-            dest_chain: "bitcoin".to_string(),
-            status: OrderStatus::PartiallyFilled,
-            allow_partial: true,
-            filled_amount: fill_amount,
-            expiry_height: 850000,
-            created_at: now.to_rfc3339(),
-            updated_at: now.to_rfc3339(),
-            utxo_id: Some("abc123:1".to_string()),
-        },
+    let fill_amount = parse_amount(
+        "fill_amount",
+        req.fill_amount.as_deref().ok_or_else(|| ApiError::BadRequest("fill_amount is required".to_string()))?,
+    )?;
+    let (offer_amount, want_amount, filled_amount) = order_amounts(&record)?;
+    let remaining = offer_amount.saturating_sub(filled_amount);
+    if fill_amount > remaining {
+        return Err(ApiError::BadRequest(format!(
+            "fill_amount {} exceeds the remaining {}",
+            fill_amount, remaining
+        )));
+    }
+    let min_fill_amount = order_min_fill(&record)?;
+    if fill_amount < min_fill_amount && fill_amount < remaining {
+        return Err(ApiError::BadRequest(format!(
+            "fill_amount {} is below the order's min_fill_amount {}",
+            fill_amount, min_fill_amount
+        )));
+    }
+    
+    // Proportional pricing: fill_want_amount = fill_amount * want_amount / offer_amount
+    let fill_want_amount = (fill_amount as u128 * want_amount as u128 / offer_amount as u128) as u64;
+    
+    let partial_data = PartialFillSpellData {
+        order_utxo: order_utxo(&record)?,
+        taker_utxo: req.taker_utxo.clone(),
+        taker_pubkey: taker_pubkey.clone().unwrap_or_else(|| req.taker_address.clone()),
+        taker_address: req.taker_address.clone(),
+        maker_address: record.maker_address.clone(),
+        escrow_address: escrow_address(&record.id),
+        fill_amount,
+        fill_want_amount,
+        current_filled: filled_amount,
+        current_remaining: remaining,
+    };
+    let spell_built = state.charms.build_partial_fill_spell(
+        PARTIAL_FILL_SPELL,
+        &partial_data,
+        &order_spell_data(&state, &record)?,
+        DEFAULT_APP_ID,
+        &state.apps.swap.vk,
+    )?;
+    
+    let proved = prove_transactions(
+        &state,
+        &spell_built,
+        &req.taker_utxo,
+        req.taker_utxo_value,
+        req.fee_rate,
+        &req.taker_address,
+        &id,
+    ).await?;
+    let unsigned_txs = unsigned_transactions(
+        &state,
+        &proved.transactions,
+        &req.taker_address,
+        taker_pubkey.as_deref(),
+    ).await?;
+    record_transactions(&state, &id, OP_PARTIAL_FILL, &proved).await?;
+    record_pending_trade(
+        &state,
+        &record,
+        &proved,
+        &req.taker_address,
+        &fill_amount.to_string(),
+        &fill_want_amount.to_string(),
+    ).await?;
+    
+    let mut order = Order::from(record);
+    order.status = if fill_amount == remaining {
+        OrderStatus::Filled
+    } else {
+        OrderStatus::PartiallyFilled
+    };
+    order.filled_amount = (filled_amount + fill_amount).to_string();
+    order.updated_at = now.to_rfc3339();
+    
+    Ok(Json(FillOrderResponse {
+        order,
         spell: SpellData {
             spell_yaml: PARTIAL_FILL_SPELL.to_string(),
             spell_yaml_built: spell_built,
-            app_binary: "".to_string(),
+            app_binary: hex::encode(&state.apps.swap.binary),
             prev_txs: vec![],
         },
         unsigned_txs,
@@ -763,69 +953,1171 @@ pub async fn partial_fill_order(
             ],
             broadcast_endpoint: format!("/api/orders/{}/broadcast", id),
         },
-    })
+        prove_job_id: proved.pending_job_id(),
+    }))
+}
+
+/// Reclaim an expired order's tokens for its maker
+///
+/// Anyone may do this once the tip is past the order's expiry height; the
+/// keeper pays the fee and the remaining tokens go back to the maker.
+pub async fn expire_order(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(req): Json<ExpireOrderRequest>,
+) -> ApiResult<Json<FillOrderResponse>> {
+    address::parse_address("keeper_address", &req.keeper_address, state.config.network)?;
+    let now = chrono::Utc::now();
+    
+    let record = load_order(&state, &id).await?;
+    if !matches!(record.status.as_str(), "open" | "partiallyfilled" | "expired") {
+        return Err(ApiError::Conflict(format!("Order {} is {} and has nothing to reclaim", id, record.status)));
+    }
+    let current_height = state
+        .chain
+        .tip_height()
+        .await
+        .map_err(|e| ApiError::ChainUnavailable(format!("Failed to get the chain tip: {}", e)))?;
+    let expiry_height = record.expiry_height.unwrap_or(0) as u64;
+    if current_height <= expiry_height {
+        return Err(ApiError::Conflict(format!(
+            "Order {} expires after block {}; the tip is at {}",
+            id, expiry_height, current_height
+        )));
+    }
+    
+    let (offer_amount, _, filled_amount) = order_amounts(&record)?;
+    let expire_data = ExpireSpellData {
+        order_utxo: order_utxo(&record)?,
+        maker_address: record.maker_address.clone(),
+        filled_amount,
+        remaining_amount: offer_amount.saturating_sub(filled_amount),
+        current_height,
+    };
+    let spell_built = state.charms.build_expire_order_spell(
+        EXPIRE_ORDER_SPELL,
+        &expire_data,
+        &order_spell_data(&state, &record)?,
+        DEFAULT_APP_ID,
+        &state.apps.swap.vk,
+    )?;
+    
+    let funding_utxo = match req.funding_utxo {
+        Some(utxo) => utxo,
+        None if state.charms.is_mock_mode() => String::new(),
+        None => {
+            return Err(ApiError::BadRequest(
+                "funding_utxo is required to pay for the expire transaction".to_string(),
+            ))
+        }
+    };
+    let proved = prove_transactions(
+        &state,
+        &spell_built,
+        &funding_utxo,
+        req.funding_utxo_value,
+        req.fee_rate,
+        &req.keeper_address,
+        &id,
+    ).await?;
+    record_transactions(&state, &id, OP_EXPIRE, &proved).await?;
+    let unsigned_txs = unsigned_transactions(&state, &proved.transactions, &req.keeper_address, None).await?;
+    
+    // The sweeper may not have seen the block that expired it yet
+    if record.status != "expired" {
+        db::update_order_status(&state.db, &id, "expired").await?;
+        publish_order_event(&state, EventKind::OrderExpired, &id).await;
+    }
+    
+    let mut order = Order::from(record);
+    order.status = OrderStatus::Expired;
+    order.updated_at = now.to_rfc3339();
+    
+    Ok(Json(FillOrderResponse {
+        order,
+        spell: SpellData {
+            spell_yaml: EXPIRE_ORDER_SPELL.to_string(),
+            spell_yaml_built: spell_built,
+            app_binary: hex::encode(&state.apps.swap.binary),
+            prev_txs: vec![],
+        },
+        unsigned_txs,
+        signing_instructions: SigningInstructions {
+            message: "Sign to return the expired order's tokens to its maker".to_string(),
+            steps: vec![
+                "1. The remaining tokens go back to the maker".to_string(),
+                "2. Sign the funding input to pay the fee".to_string(),
+            ],
+            broadcast_endpoint: format!("/api/orders/{}/broadcast", id),
+        },
+        prove_job_id: proved.pending_job_id(),
+    }))
+}
+
+/// Unsigned transactions of an order's latest proved operation
+///
+/// Lets clients that were handed a `prove_job_id` fetch the PSBTs once
+/// proving has finished.
+pub async fn get_order_transactions(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<Vec<UnsignedTransaction>>> {
+    let record = load_order(&state, &id).await?;
+    if state.charms.is_mock_mode() {
+        return Ok(Json(vec![]));
+    }
+
+    let proved: Vec<ProvedTransaction> = proved_transactions(&state, &id)
+        .await?
+        .into_iter()
+        .filter_map(|row| Some(ProvedTransaction { hex: row.tx_hex?, txid: row.txid? }))
+        .collect();
+    let unsigned_txs = unsigned_transactions(&state, &proved, &record.maker_address, None).await?;
+    Ok(Json(unsigned_txs))
 }
 
-/// Broadcast a signed transaction
+/// Broadcast signed transactions
+///
+/// The signed PSBTs (or raw transactions) must be for the transactions
+/// proved for the order's latest operation, with valid signatures on every
+/// input the wallet was asked to sign. Proved transactions that need no
+/// wallet signature are broadcast as they are. The package has to pass
+/// `testmempoolaccept` before anything is sent.
 pub async fn broadcast_order(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
     Json(req): Json<BroadcastRequest>,
-) -> Json<BroadcastResponse> {
+) -> ApiResult<Json<BroadcastResponse>> {
     tracing::info!("Broadcasting transaction for order {}", id);
+    let record = load_order(&state, &id).await?;
     
-    // Check if we're in mock mode (transaction hex starts with mock indicator)
-    let is_mock = req.signed_tx_hex.contains("mock") 
-        || req.signed_tx_hex.len() < 100 
-        || state.charms.is_mock_mode();
-    
-    if is_mock {
+    if state.charms.is_mock_mode() {
         // In mock mode, simulate successful broadcast
         let mock_txid = format!("mock_broadcast_{}", uuid::Uuid::new_v4());
         tracing::info!("Mock mode: simulating broadcast with txid {}", mock_txid);
         
-        // Update order status in database
-        if let Err(e) = db::update_order_status(&state.db, &id, "open").await {
-            tracing::error!("Failed to update order status: {}", e);
-        }
-        if let Err(e) = db::update_order_tx_id(&state.db, &id, &mock_txid).await {
-            tracing::error!("Failed to update order tx_id: {}", e);
+        // A simulated cancel has already taken effect
+        if record.status == "pendingsignature" {
+            db::update_order_status(&state.db, &id, "open").await?;
         }
+        db::update_order_tx_id(&state.db, &id, &mock_txid).await?;
+        publish_order_event(&state, EventKind::OrderUpdated, &id).await;
         
-        return Json(BroadcastResponse {
+        return Ok(Json(BroadcastResponse {
             txid: mock_txid,
             status: "confirmed".to_string(),
             message: "Transaction simulated successfully (mock mode). In production, tokens would be locked in escrow.".to_string(),
-        });
+        }));
     }
     
-    // Send to Bitcoin network (real mode)
-    match state.bitcoin.send_raw_transaction(&req.signed_tx_hex).await {
-        Ok(txid) => {
-            tracing::info!("Transaction broadcast successful: {}", txid);
-            
-            // Update order status in database
-            if let Err(e) = db::update_order_status(&state.db, &id, "open").await {
-                tracing::error!("Failed to update order status: {}", e);
+    let rows = proved_transactions(&state, &id).await?;
+    let proved: Vec<ProvedTransaction> = rows
+        .iter()
+        .filter_map(|row| Some(ProvedTransaction { hex: row.tx_hex.clone()?, txid: row.txid.clone()? }))
+        .collect();
+    let operation = rows.first().map(|row| row.tx_type.as_str()).unwrap_or_default();
+    if let Some(expected) = req.operation.as_deref() {
+        if expected != operation {
+            return Err(ApiError::Conflict(format!(
+                "The latest proved operation of order {} is {}, not {}",
+                id, operation, expected
+            )));
+        }
+    }
+    let package = decode_transactions(&proved)?;
+    let signed = req.signed_items()?;
+    
+    // Pair every proved transaction with its signed version: by txid, or
+    // failing that by a shared input so the mismatch can be reported
+    let mut used = vec![false; signed.len()];
+    let mut finals = Vec::with_capacity(package.len());
+    for tx in &package {
+        let prevouts = spent_outputs(&state, tx, &package).await?;
+        let txid = tx.compute_txid();
+        let position = signed
+            .iter()
+            .enumerate()
+            .position(|(i, item)| !used[i] && item.txid() == txid)
+            .or_else(|| {
+                signed.iter().enumerate().position(|(i, item)| {
+                    !used[i]
+                        && item.unsigned().input.iter().any(|signed_in| {
+                            tx.input.iter().any(|proved_in| proved_in.previous_output == signed_in.previous_output)
+                        })
+                })
+            });
+        let final_tx = match position {
+            Some(i) => {
+                used[i] = true;
+                match &signed[i] {
+                    SignedItem::Psbt(signed_psbt) => psbt::finalize(signed_psbt, tx, &prevouts)?,
+                    SignedItem::Tx(signed_tx) => {
+                        psbt::check_signed_transaction(signed_tx, tx)?;
+                        psbt::verify_signatures(signed_tx, tx, &prevouts)?;
+                        signed_tx.clone()
+                    }
+                }
             }
-            if let Err(e) = db::update_order_tx_id(&state.db, &id, &txid).await {
-                tracing::error!("Failed to update order tx_id: {}", e);
+            None if psbt::build_psbt(tx, &prevouts, None).map(|p| psbt::inputs_to_sign(&p).is_empty())? => tx.clone(),
+            None => {
+                return Err(ApiError::InvalidTransaction(format!("Transaction {} is not signed", txid)));
             }
-            
-            Json(BroadcastResponse {
-                txid,
-                status: "confirmed".to_string(),
-                message: "Transaction broadcast successfully. Tokens are now locked in escrow.".to_string(),
-            })
+        };
+        finals.push(final_tx);
+    }
+    if let Some(i) = used.iter().position(|used| !used) {
+        return Err(ApiError::InvalidTransaction(format!(
+            "Signed transaction {} was not proved for order {}",
+            signed[i].txid(),
+            id
+        )));
+    }
+    
+    // Nothing is sent unless the node would take the whole package
+    let hexes: Vec<String> = finals.iter().map(bitcoin::consensus::encode::serialize_hex).collect();
+    let results = state
+        .chain
+        .test_mempool_accept(&hexes)
+        .await
+        .map_err(|e| ApiError::ChainUnavailable(format!("testmempoolaccept failed: {}", e)))?;
+    match results {
+        Some(results) => {
+            if let Some(rejected) = results.iter().find(|result| !result.allowed) {
+                return Err(ApiError::InvalidTransaction(format!(
+                    "Transaction {} would be rejected by the node: {}",
+                    rejected.txid,
+                    rejected.reject_reason.as_deref().unwrap_or("not checked")
+                )));
+            }
+        }
+        None => tracing::debug!("{} backend cannot test mempool acceptance", state.chain.name()),
+    }
+    
+    // Sent in order, so a spell tx follows the commit tx it spends
+    let mut txid = String::new();
+    for (row, tx_hex) in rows.iter().zip(&hexes) {
+        txid = state
+            .chain
+            .broadcast(tx_hex)
+            .await
+            .map_err(|e| ApiError::BroadcastFailed(format!("Failed to broadcast: {}", e)))?;
+        tracing::info!("Transaction broadcast successful: {}", txid);
+        if let Err(e) = db::update_transaction_status(&state.db, &row.id, TX_BROADCAST, Some(&txid)).await {
+            tracing::error!("Failed to update transaction {}: {}", row.id, e);
+        }
+    }
+    
+    // The transaction is out; a failed bookkeeping update must not hide the txid
+    if operation == OP_CREATE {
+        if let Err(e) = db::update_order_status(&state.db, &id, "open").await {
+            tracing::error!("Failed to update order status: {}", e);
+        }
+    }
+    // The order UTXO stays with its spell transaction, not a fee-bump child
+    if operation != OP_CPFP {
+        if let Err(e) = db::update_order_tx_id(&state.db, &id, &txid).await {
+            tracing::error!("Failed to update order tx_id: {}", e);
+        }
+    }
+    if operation == OP_FILL || operation == OP_PARTIAL_FILL {
+        if let Some(job_id) = rows.last().and_then(|row| row.prove_job_id.as_deref()) {
+            if let Err(e) = db::attach_trade_txid(&state.db, &id, job_id, &txid).await {
+                tracing::error!("Failed to attach txid {} to the trade of order {}: {}", txid, id, e);
+            }
+        }
+    }
+    publish_order_event(&state, EventKind::OrderUpdated, &id).await;
+    
+    Ok(Json(BroadcastResponse {
+        txid,
+        status: "broadcast".to_string(),
+        message: "Transaction broadcast successfully. Tokens will be locked in escrow once it confirms.".to_string(),
+    }))
+}
+
+/// Bump the fee of an order's unconfirmed transactions
+///
+/// `rbf` proves the latest operation again at a higher fee rate and returns
+/// replacement PSBTs; `cpfp` returns a child spending the change output
+/// that pays for the whole package. Either is signed and sent through
+/// `/api/orders/:id/broadcast` like any other order transaction. Only the
+/// maker, or the taker of a pending fill, may bump.
+pub async fn bump_order_fee(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Extension(session): Extension<AuthSession>,
+    Json(req): Json<BumpFeeRequest>,
+) -> ApiResult<Json<BumpFeeResponse>> {
+    let record = load_order(&state, &id).await?;
+    if state.charms.is_mock_mode() {
+        return Err(ApiError::Conflict("Fees cannot be bumped in mock mode".to_string()));
+    }
+
+    let rows = proved_transactions(&state, &id).await?;
+    let last = rows
+        .iter()
+        .rev()
+        .find(|row| row.status == TX_BROADCAST)
+        .ok_or_else(|| ApiError::Conflict(format!("Order {} has no broadcast transactions to bump", id)))?;
+    if record.maker_address != session.address {
+        let taker = match &last.prove_job_id {
+            Some(job_id) => db::get_pending_trade_by_prove_job(&state.db, job_id).await?.map(|trade| trade.taker_address),
+            None => None,
+        };
+        if taker.as_deref() != Some(session.address.as_str()) {
+            return Err(ApiError::Forbidden(format!(
+                "Only the maker of order {} or the taker of its pending fill can bump its fee",
+                id
+            )));
+        }
+    }
+    let last_txid = last.txid.clone().unwrap_or_default();
+    let entry = state
+        .bitcoin
+        .get_mempool_entry(&last_txid)
+        .await
+        .map_err(|e| ApiError::ChainUnavailable(e.to_string()))?
+        .ok_or_else(|| ApiError::Conflict(format!("Transaction {} is no longer in the mempool", last_txid)))?;
+
+    let target_rate = match req.fee_rate {
+        Some(rate) => state.config.fees.clamp(rate),
+        None => fees::quote(state.chain.as_ref(), &state.config.fees).await.fast,
+    };
+    let request = match &last.prove_job_id {
+        Some(job_id) => db::get_prove_job(&state.db, job_id)
+            .await?
+            .and_then(|job| serde_json::from_str::<SpellProveRequest>(&job.request).ok()),
+        None => None,
+    }
+    .ok_or_else(|| ApiError::Conflict(format!("Transaction {} was not proved by the backend", last_txid)))?;
+
+    match req.method {
+        BumpMethod::Rbf => {
+            let mut request = request;
+            let fee_rate = fees::rbf_fee_rate(request.fee_rate, target_rate);
+            if fee_rate > state.config.fees.max_rate {
+                return Err(ApiError::BadRequest(format!(
+                    "A replacement needs {} sat/vB, above the {} sat/vB limit",
+                    fee_rate, state.config.fees.max_rate
+                )));
+            }
+            request.fee_rate = fee_rate;
+            tracing::info!("Replacing transactions of order {} at {} sat/vB", id, request.fee_rate);
+
+            let proved = submit_prove_request(&state, &request, &id).await?;
+            let unsigned_txs = unsigned_transactions(&state, &proved.transactions, &request.change_address, None).await?;
+            record_transactions(&state, &id, &last.tx_type, &proved).await?;
+            // The replacement carries the same fill
+            if let (Some(from), Some(to)) = (&last.prove_job_id, &proved.job_id) {
+                db::move_trade_to_job(&state.db, from, to).await?;
+            }
+            Ok(Json(BumpFeeResponse {
+                method: BumpMethod::Rbf,
+                fee_rate: request.fee_rate,
+                unsigned_txs,
+                prove_job_id: proved.pending_job_id(),
+            }))
         }
+        BumpMethod::Cpfp => {
+            let change_script = bitcoin::Address::from_str(&request.change_address)
+                .and_then(|address| address.require_network(state.config.network))
+                .map_err(|e| ApiError::Conflict(format!("Change address cannot be spent: {}", e)))?
+                .script_pubkey();
+            let parent = decode_transactions(&[ProvedTransaction {
+                hex: last.tx_hex.clone().unwrap_or_default(),
+                txid: last_txid.clone(),
+            }])?
+            .remove(0);
+            let (vout, prevout) = parent
+                .output
+                .iter()
+                .enumerate()
+                .find(|(_, output)| output.script_pubkey == change_script)
+                .ok_or_else(|| ApiError::Conflict(format!("Transaction {} has no change output to spend", last_txid)))?;
+
+            let child_vsize = fees::cpfp_child_vsize(prevout);
+            let fee = fees::cpfp_child_fee(entry.ancestor_size, entry.ancestor_fee_sats(), child_vsize, target_rate);
+            let child = fees::cpfp_child(OutPoint::new(parent.compute_txid(), vout as u32), prevout, fee)
+                .ok_or_else(|| ApiError::Conflict(format!("The change output cannot pay a {} sat fee", fee)))?;
+            tracing::info!("Child {} pays {} sats for order {}", child.compute_txid(), fee, id);
+
+            let child = ProvedTransaction {
+                hex: bitcoin::consensus::encode::serialize_hex(&child),
+                txid: child.compute_txid().to_string(),
+            };
+            let unsigned_txs = unsigned_transactions(&state, std::slice::from_ref(&child), &request.change_address, None).await?;
+            // Not proved, so it stands alone rather than joining a job's rows
+            let record = transaction_records(&id, OP_CPFP, None, std::slice::from_ref(&child)).remove(0);
+            db::insert_transaction(&state.db, &record).await?;
+
+            Ok(Json(BumpFeeResponse {
+                method: BumpMethod::Cpfp,
+                fee_rate: target_rate,
+                unsigned_txs,
+                prove_job_id: None,
+            }))
+        }
+    }
+}
+
+/// Mark broadcast order transactions confirmed as blocks come in
+///
+/// A confirmed fill also marks its order filled.
+pub async fn track_confirmations(state: Arc<AppState>, mut events: broadcast::Receiver<ChainEvent>) {
+    loop {
+        match state.tasks.recv(&mut events).await {
+            Ok(ChainEvent::NewBlock { .. }) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Ok(ChainEvent::NewTransaction { .. }) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+        if let Err(e) = confirm_broadcast_transactions(&state).await {
+            tracing::warn!("Failed to check order transactions for confirmations: {}", e);
+        }
+    }
+}
+
+async fn confirm_broadcast_transactions(state: &AppState) -> anyhow::Result<()> {
+    for row in db::get_transactions_by_status(&state.db, TX_BROADCAST).await? {
+        let Some(txid) = row.txid.as_deref() else { continue };
+        let Some(status) = state.chain.tx_status(txid).await?.filter(|status| status.confirmed) else {
+            continue;
+        };
+        db::confirm_transaction(&state.db, &row.id).await?;
+        tracing::info!("Transaction {} of order {} confirmed", txid, row.order_id);
+        if row.tx_type == OP_FILL || row.tx_type == OP_PARTIAL_FILL {
+            settle_fill(state, &row, txid, status.block_height).await?;
+        } else if row.tx_type == OP_CANCEL {
+            settle_cancel(state, &row, txid).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Mark an order cancelled once the transaction spending it back to its
+/// maker confirms
+///
+/// Only the spell transaction, which the order's `tx_id` points at after
+/// broadcast, counts; its commit transaction confirming changes nothing.
+async fn settle_cancel(state: &AppState, row: &TransactionRecord, txid: &str) -> anyhow::Result<()> {
+    let Some(record) = db::get_order_by_id(&state.db, &row.order_id).await? else {
+        return Ok(());
+    };
+    if record.tx_id.as_deref() != Some(txid) || matches!(record.status.as_str(), "cancelled" | "filled") {
+        return Ok(());
+    }
+    db::update_order_status(&state.db, &row.order_id, "cancelled").await?;
+    tracing::info!("Order {} cancelled in {}", row.order_id, txid);
+    publish_order_event(state, EventKind::OrderCancelled, &row.order_id).await;
+    Ok(())
+}
+
+/// Apply a confirmed fill to its order and execute its trade
+///
+/// Only the spell transaction counts: the one its trade was attached to, or
+/// the order's `tx_id`. Its commit transaction confirming changes nothing.
+/// A spell fill without a recorded trade empties the order; a partial fill
+/// without one changes nothing.
+async fn settle_fill(
+    state: &AppState,
+    row: &TransactionRecord,
+    txid: &str,
+    block_height: Option<u64>,
+) -> anyhow::Result<()> {
+    let trade = db::execute_trade(&state.db, txid, block_height.map(|height| height as i64)).await?;
+    let Some(record) = db::get_order_by_id(&state.db, &row.order_id).await? else {
+        return Ok(());
+    };
+    if trade.is_none() && record.tx_id.as_deref() != Some(txid) {
+        return Ok(());
+    }
+    let (offer_amount, _, filled_amount) = order_amounts(&record)?;
+    let filled_amount = match &trade {
+        Some(trade) => filled_amount
+            .saturating_add(trade.offer_amount.trim().parse().unwrap_or(0))
+            .min(offer_amount),
+        None if row.tx_type == OP_FILL => offer_amount,
+        None => return Ok(()),
+    };
+
+    if filled_amount == offer_amount {
+        db::update_order_fill(&state.db, &row.order_id, &filled_amount.to_string(), "filled").await?;
+        publish_order_event(state, EventKind::OrderFilled, &row.order_id).await;
+    } else {
+        db::update_order_fill(&state.db, &row.order_id, &filled_amount.to_string(), "partiallyfilled").await?;
+        publish_order_event(state, EventKind::OrderUpdated, &row.order_id).await;
+    }
+
+    if let Some(trade) = trade {
+        tracing::info!("Trade {} of order {} executed in {}", trade.id, trade.order_id, txid);
+        let addresses = vec![trade.maker_address.clone(), trade.taker_address.clone()];
+        if let Err(e) = state
+            .events
+            .publish(EventKind::TradeExecuted, Some(trade.market.clone()), addresses, &trade)
+            .await
+        {
+            tracing::warn!("Failed to publish trade {}: {}", trade.id, e);
+        }
+    }
+    Ok(())
+}
+
+/// Spell for a proposed match: the maker order is filled with the taker
+/// order's locked tokens, at the maker's price
+pub(crate) fn build_match_spell(
+    state: &AppState,
+    proposal: &Match,
+    maker: &OrderRecord,
+    taker: &OrderRecord,
+) -> ApiResult<String> {
+    let (offer_amount, _, filled_amount) = order_amounts(maker)?;
+    let taker_utxo = order_utxo(taker)?;
+    let spell = if maker.allow_partial {
+        let partial_data = PartialFillSpellData {
+            order_utxo: order_utxo(maker)?,
+            taker_utxo,
+            taker_pubkey: maker_key(taker),
+            taker_address: taker.maker_address.clone(),
+            maker_address: maker.maker_address.clone(),
+            escrow_address: escrow_address(&maker.id),
+            fill_amount: proposal.maker_amount,
+            fill_want_amount: proposal.taker_amount,
+            current_filled: filled_amount,
+            current_remaining: offer_amount.saturating_sub(filled_amount),
+        };
+        state.charms.build_partial_fill_spell(
+            PARTIAL_FILL_SPELL,
+            &partial_data,
+            &order_spell_data(state, maker)?,
+            DEFAULT_APP_ID,
+            &state.apps.swap.vk,
+        )?
+    } else {
+        let fill_data = FillSpellData {
+            order_utxo: order_utxo(maker)?,
+            taker_utxo,
+            taker_pubkey: maker_key(taker),
+            taker_address: taker.maker_address.clone(),
+            maker_address: maker.maker_address.clone(),
+            offer_amount: maker.offer_amount.clone(),
+            want_amount: maker.want_amount.clone(),
+            fill_amount: None,
+        };
+        state.charms.build_fill_order_spell(
+            FILL_ORDER_SPELL,
+            &fill_data,
+            &order_spell_data(state, maker)?,
+            DEFAULT_APP_ID,
+            &state.apps.swap.vk,
+        )?
+    };
+    Ok(spell)
+}
+
+/// Batch-fill spell buying a route's fills with the taker's tokens
+pub(crate) fn build_route_spell(
+    state: &AppState,
+    route: &Route,
+    orders: &[OrderRecord],
+    taker_utxo: &str,
+    taker_address: &str,
+    taker_pubkey: Option<&str>,
+) -> ApiResult<SpellData> {
+    let mut batch_orders = Vec::new();
+    for fill in &route.fills {
+        let record = orders
+            .iter()
+            .find(|order| order.id == fill.order_id)
+            .ok_or_else(|| ApiError::Internal(anyhow::anyhow!("Routed order {} is missing", fill.order_id)))?;
+        let (_, _, filled_amount) = order_amounts(record)?;
+        batch_orders.push(BatchFillOrder {
+            order_utxo: order_utxo(record)?,
+            order_data: order_spell_data(state, record)?,
+            fill_amount: fill.fill_amount,
+            fill_want_amount: fill.cost,
+            current_filled: filled_amount,
+            current_remaining: fill.remaining,
+        });
+    }
+    let batch_data = BatchFillSpellData {
+        taker_utxo: taker_utxo.to_string(),
+        taker_pubkey: taker_pubkey.unwrap_or(taker_address).to_string(),
+        taker_address: taker_address.to_string(),
+        orders: batch_orders,
+    };
+    let spell_built = state.charms.build_batch_fill_spell(
+        BATCH_FILL_SPELL,
+        &batch_data,
+        DEFAULT_APP_ID,
+        &state.apps.swap.vk,
+    )?;
+
+    Ok(SpellData {
+        spell_yaml: BATCH_FILL_SPELL.to_string(),
+        spell_yaml_built: spell_built,
+        app_binary: hex::encode(&state.apps.swap.binary),
+        prev_txs: vec![],
+    })
+}
+
+/// Mark orders expired once the tip passes their expiry height
+///
+/// Their tokens stay locked until someone builds the expire spell through
+/// [`expire_order`].
+pub async fn expire_orders(state: Arc<AppState>, mut events: broadcast::Receiver<ChainEvent>) {
+    loop {
+        let height = match state.tasks.recv(&mut events).await {
+            Ok(ChainEvent::NewBlock { height, .. }) => height,
+            Ok(ChainEvent::NewTransaction { .. }) => continue,
+            Err(broadcast::error::RecvError::Lagged(_)) => match state.chain.tip_height().await {
+                Ok(height) => height,
+                Err(e) => {
+                    tracing::warn!("Failed to get the chain tip for order expiry: {}", e);
+                    continue;
+                }
+            },
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        match db::expire_orders(&state.db, height).await {
+            Ok(ids) => {
+                for id in ids {
+                    tracing::info!("Order {} expired at height {}", id, height);
+                    publish_order_event(&state, EventKind::OrderExpired, &id).await;
+                }
+            }
+            Err(e) => tracing::warn!("Failed to expire orders at height {}: {}", height, e),
+        }
+    }
+}
+
+// ============ Helpers ============
+
+/// Load an order or fail with `order_not_found`
+async fn load_order(state: &AppState, id: &str) -> ApiResult<OrderRecord> {
+    db::get_order_by_id(&state.db, id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("order_not_found", format!("Order {} not found", id)))
+}
+
+/// Record an order change in the event log
+///
+/// The order is reloaded so the event carries its state after the change.
+/// The change itself already happened, so a failure is only logged.
+async fn publish_order_event(state: &AppState, kind: EventKind, id: &str) {
+    let record = match db::get_order_by_id(&state.db, id).await {
+        Ok(Some(record)) => record,
+        Ok(None) => return,
         Err(e) => {
-            tracing::error!("Broadcast failed: {}", e);
-            
-            Json(BroadcastResponse {
-                txid: "".to_string(),
-                status: "failed".to_string(),
-                message: format!("Failed to broadcast: {}", e),
+            tracing::warn!("Failed to load order {} for its {} event: {}", id, kind.as_str(), e);
+            return;
+        }
+    };
+    let market = Some(market_key(&record.offer_token, &record.want_token));
+    let addresses = vec![record.maker_address.clone()];
+    if let Err(e) = state.events.publish(kind, market, addresses, &Order::from(record)).await {
+        tracing::warn!("Failed to publish {} event of order {}: {}", kind.as_str(), id, e);
+    }
+}
+
+/// Record a fill as a pending trade of its prove job, executed once its
+/// transaction confirms
+///
+/// Mock fills have no job and are never confirmed, so they record nothing.
+async fn record_pending_trade(
+    state: &AppState,
+    record: &OrderRecord,
+    proved: &ProveOutcome,
+    taker_address: &str,
+    offer_amount: &str,
+    want_amount: &str,
+) -> ApiResult<()> {
+    let Some(job_id) = &proved.job_id else {
+        return Ok(());
+    };
+    let trade = TradeRecord {
+        id: Uuid::new_v4().to_string(),
+        order_id: record.id.clone(),
+        market: market_key(&record.offer_token, &record.want_token),
+        offer_token: record.offer_token.clone(),
+        offer_amount: offer_amount.to_string(),
+        want_token: record.want_token.clone(),
+        want_amount: want_amount.to_string(),
+        maker_address: record.maker_address.clone(),
+        taker_address: taker_address.to_string(),
+        txid: None,
+        block_height: None,
+        status: "pending".to_string(),
+        created_at: chrono::Utc::now(),
+        executed_at: None,
+        prove_job_id: Some(job_id.clone()),
+    };
+    db::insert_trade(&state.db, &trade).await?;
+    Ok(())
+}
+
+/// Opaque page cursor holding the sort key and ID of the last order of a page
+fn encode_cursor(cursor: &OrderCursor) -> String {
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(cursor).unwrap_or_default())
+}
+
+/// A cursor made by [`encode_cursor`] for the same `sort`
+fn decode_cursor(cursor: &str, sort: OrderSort) -> ApiResult<OrderCursor> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<OrderCursor>(&bytes).ok())
+        .filter(|decoded| decoded.sort == sort && decoded.is_valid())
+        .ok_or_else(|| ApiError::BadRequest(format!("Invalid cursor '{}' for sort {:?}", cursor, sort)))
+}
+
+/// Parse a positive token amount from a request
+fn parse_amount(field: &str, value: &str) -> ApiResult<u64> {
+    match value.trim().parse::<u64>() {
+        Ok(amount) if amount > 0 => Ok(amount),
+        _ => Err(ApiError::BadRequest(format!("{} must be a positive integer, got '{}'", field, value))),
+    }
+}
+
+/// Offer, want and filled amounts of a stored order
+fn order_amounts(record: &OrderRecord) -> ApiResult<(u64, u64, u64)> {
+    let parse = |field: &str, value: &str| {
+        value.trim().parse::<u64>().map_err(|_| {
+            ApiError::Internal(anyhow::anyhow!("Order {} has a malformed {}: '{}'", record.id, field, value))
+        })
+    };
+    Ok((
+        parse("offer_amount", &record.offer_amount)?,
+        parse("want_amount", &record.want_amount)?,
+        parse("filled_amount", record.filled_amount.as_deref().unwrap_or("0"))?,
+    ))
+}
+
+/// Smallest partial fill of a stored order
+fn order_min_fill(record: &OrderRecord) -> ApiResult<u64> {
+    record.min_fill_amount.trim().parse().map_err(|_| {
+        ApiError::Internal(anyhow::anyhow!(
+            "Order {} has a malformed min_fill_amount: '{}'",
+            record.id, record.min_fill_amount
+        ))
+    })
+}
+
+/// The order NFT is the first output of the order's spell transaction
+fn order_utxo(record: &OrderRecord) -> ApiResult<String> {
+    record
+        .tx_id
+        .as_ref()
+        .map(|txid| format!("{}:0", txid))
+        .ok_or_else(|| ApiError::Conflict(format!("Order {} has not been broadcast yet", record.id)))
+}
+
+/// Escrow address holding an order's tokens
+fn escrow_address(order_id: &str) -> String {
+    // In production, this would be derived from the contract
+    format!("tb1q_escrow_{}", &order_id[..8])
+}
+
+/// Check a party's address against the network and its optional public key,
+/// returned normalized
+pub(crate) fn check_party(
+    network: Network,
+    address_field: &str,
+    address: &str,
+    pubkey_field: &str,
+    pubkey: Option<&str>,
+) -> ApiResult<Option<String>> {
+    address::parse_address(address_field, address, network)?;
+    Ok(pubkey.map(|key| address::parse_pubkey(pubkey_field, key)).transpose()?)
+}
+
+/// Reject requests acting for an address other than the signed-in one
+fn require_address(session: &AuthSession, field: &str, address: &str) -> ApiResult<()> {
+    if address.trim() != session.address {
+        return Err(ApiError::Forbidden(format!(
+            "{} {} is not the signed-in wallet {}",
+            field, address, session.address
+        )));
+    }
+    Ok(())
+}
+
+/// A registered token living on `chain`
+fn resolve_token(state: &AppState, ticker: &str, chain: &str) -> ApiResult<TokenRecord> {
+    let token = state
+        .tokens
+        .get(ticker)
+        .ok_or_else(|| ApiError::BadRequest(format!("Unknown token '{}'; see /api/tokens", ticker)))?;
+    if token.chain != chain {
+        return Err(ApiError::BadRequest(format!(
+            "{} is a {} token, not a {} one",
+            token.ticker, token.chain, chain
+        )));
+    }
+    Ok(token)
+}
+
+/// Spell data describing a stored order
+fn order_spell_data(state: &AppState, record: &OrderRecord) -> ApiResult<OrderSpellData> {
+    let token = |ticker: &str| {
+        state.tokens.get(ticker).ok_or_else(|| {
+            ApiError::Conflict(format!("Token {} of order {} is not registered", ticker, record.id))
+        })
+    };
+    let (offer_token, want_token) = (token(&record.offer_token)?, token(&record.want_token)?);
+    Ok(OrderSpellData {
+        maker_address: record.maker_address.clone(),
+        maker_pubkey: maker_key(record),
+        offer_token_id: offer_token.app_id,
+        offer_token_vk: offer_token.vk,
+        offer_amount: record.offer_amount.clone(),
+        want_token_id: want_token.app_id,
+        want_token_vk: want_token.vk,
+        want_amount: record.want_amount.clone(),
+        expiry_height: record.expiry_height.unwrap_or(0) as u64,
+        allow_partial: record.allow_partial,
+        min_fill_amount: record.min_fill_amount.clone(),
+        funding_utxo: record.utxo_id.clone().unwrap_or_default(),
+        escrow_address: escrow_address(&record.id),
+        dest_chain: chain_to_id(&record.dest_chain),
+        dest_address: record.maker_address.clone(),
+    })
+}
+
+/// Key an order's maker signs spells with; the address stands in for
+/// orders created without a public key
+fn maker_key(record: &OrderRecord) -> String {
+    record.maker_pubkey.clone().unwrap_or_else(|| record.maker_address.clone())
+}
+
+/// Transactions for the wallet to sign
+///
+/// Each proved transaction comes with a PSBT listing the outputs its inputs
+/// spend. Mock transactions cannot be decoded and are returned as before,
+/// with input 0 signed by `signer_address`.
+async fn unsigned_transactions(
+    state: &AppState,
+    txs: &[ProvedTransaction],
+    signer_address: &str,
+    signer_pubkey: Option<&str>,
+) -> ApiResult<Vec<UnsignedTransaction>> {
+    if state.charms.is_mock_mode() {
+        return Ok(txs
+            .iter()
+            .map(|tx| UnsignedTransaction {
+                hex: tx.hex.clone(),
+                txid: tx.txid.clone(),
+                psbt: None,
+                inputs_to_sign: vec![InputToSign {
+                    index: 0,
+                    address: signer_address.to_string(),
+                    sighash_type: "SIGHASH_DEFAULT".to_string(),
+                }],
+            })
+            .collect());
+    }
+
+    let package = decode_transactions(txs)?;
+    let signer = signer_pubkey.and_then(x_only_key);
+    let network = state.config.network;
+
+    let mut unsigned = Vec::with_capacity(package.len());
+    for (proved, tx) in txs.iter().zip(&package) {
+        let prevouts = spent_outputs(state, tx, &package).await?;
+        let psbt = psbt::build_psbt(tx, &prevouts, signer)?;
+        let inputs_to_sign = psbt::inputs_to_sign(&psbt)
+            .into_iter()
+            .map(|input| InputToSign {
+                index: input.index as u32,
+                address: bitcoin::Address::from_script(&prevouts[input.index].script_pubkey, network)
+                    .map(|address| address.to_string())
+                    .unwrap_or_else(|_| signer_address.to_string()),
+                sighash_type: input.sighash_type,
             })
+            .collect();
+        unsigned.push(UnsignedTransaction {
+            hex: proved.hex.clone(),
+            txid: proved.txid.clone(),
+            psbt: Some(psbt::encode(&psbt)),
+            inputs_to_sign,
+        });
+    }
+    Ok(unsigned)
+}
+
+/// Outcome of proving an order operation
+#[derive(Debug, Default)]
+struct ProveOutcome {
+    /// Job that proved the spell; none in mock mode
+    job_id: Option<String>,
+    /// Empty while the job is still running
+    transactions: Vec<ProvedTransaction>,
+}
+
+impl ProveOutcome {
+    /// The job to poll, if proving has not finished yet
+    fn pending_job_id(&self) -> Option<String> {
+        if self.transactions.is_empty() {
+            self.job_id.clone()
+        } else {
+            None
+        }
+    }
+}
+
+/// Prove a spell through the job queue
+///
+/// Returns the proved transactions, or none and the job id when proving
+/// outlasts the wait window. Mock mode returns a placeholder transaction
+/// instead; outside mock mode a failed proof is always an error.
+async fn prove_transactions(
+    state: &AppState,
+    spell: &str,
+    funding_utxo: &str,
+    funding_utxo_value: Option<u64>,
+    fee_rate: Option<f64>,
+    change_address: &str,
+    id: &str,
+) -> ApiResult<ProveOutcome> {
+    if state.charms.is_mock_mode() {
+        return Ok(ProveOutcome {
+            job_id: None,
+            transactions: vec![ProvedTransaction {
+                hex: "0200000001...mock...".to_string(),
+                txid: format!("mock_{}", id),
+            }],
+        });
+    }
+
+    let report = state.charms.check_spell(spell);
+    if !report.valid {
+        return Err(ApiError::InvalidSpell(format!("Spell failed validation: {}", report.error_summary())));
+    }
+    let funding_utxo_value = funding_utxo_value
+        .ok_or_else(|| ApiError::BadRequest("The funding UTXO value is required".to_string()))?;
+
+    let request = SpellProveRequest {
+        spell: spell.to_string(),
+        // The queue attaches the app binaries when it proves
+        binaries: std::collections::BTreeMap::new(),
+        prev_txs: vec![],
+        funding_utxo: funding_utxo.to_string(),
+        funding_utxo_value,
+        change_address: change_address.to_string(),
+        fee_rate: fees::select_fee_rate(state.chain.as_ref(), &state.config.fees, fee_rate, FeeTarget::Normal).await,
+        chain: address::network_name(state.config.network).to_string(),
+    };
+    submit_prove_request(state, &request, id).await
+}
+
+/// Queue a prove request and wait briefly for it
+async fn submit_prove_request(state: &AppState, request: &SpellProveRequest, id: &str) -> ApiResult<ProveOutcome> {
+    // Proving runs in the background; wait briefly so fast proofs
+    // still come back in this response
+    let job = state.prove_queue.submit(request).await?;
+    let job = state
+        .prove_queue
+        .wait(&job.id, state.config.prove_wait)
+        .await?
+        .unwrap_or(job);
+
+    match job.status {
+        JobStatus::Succeeded => match job.transactions {
+            Some(txs) if !txs.is_empty() => Ok(ProveOutcome { job_id: Some(job.id), transactions: txs }),
+            _ => Err(ApiError::ProverFailed("Prover returned no transactions".to_string())),
+        },
+        JobStatus::Failed => Err(ApiError::ProverFailed(
+            job.error.unwrap_or_else(|| "Proving failed".to_string()),
+        )),
+        JobStatus::Queued | JobStatus::Running => {
+            tracing::info!("{} is waiting on prove job {}", id, job.id);
+            Ok(ProveOutcome { job_id: Some(job.id), transactions: vec![] })
+        }
+    }
+}
+
+/// Store the transactions proved for an order operation
+///
+/// A job that is still running gets a placeholder row, replaced by the
+/// transactions once they are first asked for.
+async fn record_transactions(
+    state: &AppState,
+    order_id: &str,
+    operation: &str,
+    proved: &ProveOutcome,
+) -> ApiResult<()> {
+    let Some(job_id) = &proved.job_id else {
+        return Ok(());
+    };
+
+    if proved.transactions.is_empty() {
+        let placeholder = TransactionRecord {
+            id: Uuid::new_v4().to_string(),
+            order_id: order_id.to_string(),
+            tx_type: operation.to_string(),
+            tx_hex: None,
+            txid: None,
+            status: TX_PROVING.to_string(),
+            signed_at: None,
+            broadcast_at: None,
+            confirmed_at: None,
+            created_at: chrono::Utc::now(),
+            prove_job_id: Some(job_id.clone()),
+        };
+        db::insert_transaction(&state.db, &placeholder).await?;
+    } else {
+        let records = transaction_records(order_id, operation, Some(job_id), &proved.transactions);
+        db::replace_proving_transactions(&state.db, job_id, &records).await?;
+    }
+    Ok(())
+}
+
+/// Rows for the transactions of a finished prove job, in proving order
+fn transaction_records(
+    order_id: &str,
+    operation: &str,
+    job_id: Option<&str>,
+    txs: &[ProvedTransaction],
+) -> Vec<TransactionRecord> {
+    let now = chrono::Utc::now();
+    txs.iter()
+        .enumerate()
+        .map(|(i, tx)| TransactionRecord {
+            id: Uuid::new_v4().to_string(),
+            order_id: order_id.to_string(),
+            tx_type: operation.to_string(),
+            tx_hex: Some(tx.hex.clone()),
+            txid: Some(tx.txid.clone()),
+            status: TX_PROVED.to_string(),
+            signed_at: None,
+            broadcast_at: None,
+            confirmed_at: None,
+            // Keeps the commit transaction ahead of the spell transaction
+            created_at: now + chrono::Duration::microseconds(i as i64),
+            prove_job_id: job_id.map(str::to_string),
+        })
+        .collect()
+}
+
+/// The most recently proved transactions of an order
+///
+/// Resolves a placeholder left by a job that outlasted its request.
+async fn proved_transactions(state: &AppState, order_id: &str) -> ApiResult<Vec<TransactionRecord>> {
+    let rows = db::get_transactions_by_order(&state.db, order_id).await?;
+    let latest = rows
+        .first()
+        .ok_or_else(|| ApiError::Conflict(format!("Order {} has no proved transactions", order_id)))?;
+    // Fee-bump children are built by the backend, not proved, and stand alone
+    let Some(job_id) = latest.prove_job_id.clone() else {
+        return Ok(vec![latest.clone()]);
+    };
+
+    if latest.status != TX_PROVING {
+        return Ok(db::get_transactions_by_prove_job(&state.db, &job_id).await?);
+    }
+
+    let job = state
+        .prove_queue
+        .get(&job_id)
+        .await?
+        .ok_or_else(|| ApiError::Internal(anyhow::anyhow!("Prove job {} is missing", job_id)))?;
+    match job.status {
+        JobStatus::Succeeded => {
+            let txs = job.transactions.unwrap_or_default();
+            let records = transaction_records(order_id, &latest.tx_type, Some(&job_id), &txs);
+            db::replace_proving_transactions(&state.db, &job_id, &records).await?;
+            Ok(records)
+        }
+        JobStatus::Failed => Err(ApiError::ProverFailed(
+            job.error.unwrap_or_else(|| "Proving failed".to_string()),
+        )),
+        JobStatus::Queued | JobStatus::Running => Err(ApiError::Conflict(format!(
+            "Order {} is still being proved by job {}",
+            order_id, job_id
+        ))),
+    }
+}
+
+/// Decode proved transactions
+fn decode_transactions(txs: &[ProvedTransaction]) -> ApiResult<Vec<Transaction>> {
+    txs.iter()
+        .map(|tx| {
+            bitcoin::consensus::encode::deserialize_hex(&tx.hex).map_err(|e| {
+                ApiError::Internal(anyhow::anyhow!("Proved transaction {} does not decode: {}", tx.txid, e))
+            })
+        })
+        .collect()
+}
+
+/// Outputs spent by each input of `tx`, from `package` or the chain
+async fn spent_outputs(state: &AppState, tx: &Transaction, package: &[Transaction]) -> ApiResult<Vec<TxOut>> {
+    let mut fetched: HashMap<Txid, Transaction> = HashMap::new();
+    let mut prevouts = Vec::with_capacity(tx.input.len());
+
+    for input in &tx.input {
+        let outpoint = input.previous_output;
+        let parent = match package.iter().find(|p| p.compute_txid() == outpoint.txid) {
+            Some(parent) => parent,
+            None => {
+                if let Entry::Vacant(entry) = fetched.entry(outpoint.txid) {
+                    let parent = state
+                        .chain
+                        .get_transaction(&outpoint.txid.to_string())
+                        .await
+                        .map_err(|e| ApiError::ChainUnavailable(format!("Failed to look up {}: {}", outpoint.txid, e)))?
+                        .ok_or_else(|| {
+                            ApiError::InvalidTransaction(format!("Input spends unknown transaction {}", outpoint.txid))
+                        })?;
+                    entry.insert(parent);
+                }
+                &fetched[&outpoint.txid]
+            }
+        };
+        let prevout = parent.output.get(outpoint.vout as usize).cloned().ok_or_else(|| {
+            ApiError::InvalidTransaction(format!("Input spends missing output {}", outpoint))
+        })?;
+        prevouts.push(prevout);
+    }
+    Ok(prevouts)
+}
+
+/// X-only key of a hex public key (32 or 33 bytes)
+fn x_only_key(pubkey: &str) -> Option<XOnlyPublicKey> {
+    let bytes = hex::decode(pubkey).ok()?;
+    match bytes.len() {
+        32 => XOnlyPublicKey::from_slice(&bytes).ok(),
+        33 => bitcoin::secp256k1::PublicKey::from_slice(&bytes)
+            .ok()
+            .map(|key| key.x_only_public_key().0),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursors_carry_their_sort_key() {
+        let cursor = OrderCursor { sort: OrderSort::PriceAsc, key: "0.5".to_string(), id: "o1".to_string() };
+        let encoded = encode_cursor(&cursor);
+        assert_eq!(decode_cursor(&encoded, OrderSort::PriceAsc).unwrap(), cursor);
+        // A cursor only continues the listing it came from
+        assert!(decode_cursor(&encoded, OrderSort::Newest).is_err());
+
+        let cursor = OrderCursor {
+            sort: OrderSort::Newest,
+            key: "2026-10-19T12:00:00.123456Z".to_string(),
+            id: "o2".to_string(),
+        };
+        assert_eq!(decode_cursor(&encode_cursor(&cursor), OrderSort::Newest).unwrap(), cursor);
+    }
+
+    #[test]
+    fn test_malformed_cursors_are_rejected() {
+        assert!(decode_cursor("not base64!", OrderSort::Newest).is_err());
+        assert!(decode_cursor(&URL_SAFE_NO_PAD.encode("o1"), OrderSort::Newest).is_err());
+        for (sort, key) in [(OrderSort::PriceDesc, "1e5"), (OrderSort::PriceDesc, "1."), (OrderSort::Oldest, "yesterday")] {
+            let cursor = OrderCursor { sort, key: key.to_string(), id: "o1".to_string() };
+            assert!(decode_cursor(&encode_cursor(&cursor), sort).is_err());
         }
     }
 }
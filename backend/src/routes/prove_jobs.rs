@@ -0,0 +1,84 @@
+//! Proving job endpoints
+//!
+//! Spells are proved in the background; clients submit a job and poll it.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::error::{ApiError, ApiResult};
+use crate::services::address;
+use crate::services::charms::SpellProveRequest;
+use crate::services::fees::{self, FeeTarget};
+use crate::services::prove_queue::ProveJob;
+use crate::state::AppState;
+
+/// Submit prove job request
+#[derive(Debug, Deserialize)]
+pub struct SubmitProveJobRequest {
+    pub spell_yaml: String,
+    /// App binaries by VK, hex encoded; registered apps are attached automatically
+    #[serde(default)]
+    pub binaries: BTreeMap<String, String>,
+    #[serde(default)]
+    pub prev_txs: Vec<String>,
+    pub funding_utxo: String,
+    pub funding_utxo_value: u64,
+    pub change_address: String,
+    /// Fee rate in sat/vB; the node's estimate when absent
+    #[serde(default)]
+    pub fee_rate: Option<f64>,
+}
+
+/// Queue a spell for proving
+pub async fn submit_prove_job(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<SubmitProveJobRequest>,
+) -> ApiResult<(StatusCode, Json<ProveJob>)> {
+    let report = state.charms.check_spell(&req.spell_yaml);
+    if !report.valid {
+        return Err(ApiError::InvalidSpell(format!(
+            "Spell failed validation: {}",
+            report.error_summary()
+        )));
+    }
+
+    let mut binaries = BTreeMap::new();
+    for (vk, binary) in req.binaries {
+        let bytes = hex::decode(&binary)
+            .map_err(|e| ApiError::BadRequest(format!("Binary for {} is not hex: {}", vk, e)))?;
+        binaries.insert(vk, bytes);
+    }
+
+    let request = SpellProveRequest {
+        spell: req.spell_yaml,
+        binaries,
+        prev_txs: req.prev_txs,
+        funding_utxo: req.funding_utxo,
+        funding_utxo_value: req.funding_utxo_value,
+        change_address: req.change_address,
+        fee_rate: fees::select_fee_rate(state.chain.as_ref(), &state.config.fees, req.fee_rate, FeeTarget::Normal).await,
+        chain: address::network_name(state.config.network).to_string(),
+    };
+
+    let job = state.prove_queue.submit(&request).await?;
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// Get the state of a prove job
+pub async fn get_prove_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<ProveJob>> {
+    state
+        .prove_queue
+        .get(&id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("prove_job_not_found", format!("Prove job {} not found", id)))
+}
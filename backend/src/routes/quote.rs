@@ -0,0 +1,138 @@
+//! Best-execution quotes
+//!
+//! A taker names what to buy and how much; the quote routes the amount
+//! across the cheapest live orders and, given the taker's UTXO, returns the
+//! batch-fill spell that takes them all in one transaction.
+
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::orders::{build_route_spell, check_party, SpellData};
+use crate::db;
+use crate::error::{ApiError, ApiResult};
+use crate::services::routing;
+use crate::state::AppState;
+
+/// Quote request
+#[derive(Debug, Deserialize)]
+pub struct QuoteRequest {
+    /// Token to buy
+    pub buy_token: String,
+    /// Token to pay with
+    pub pay_token: String,
+    /// Amount of `buy_token` to buy, in smallest units
+    pub amount: String,
+    /// Taker's destination; with `taker_utxo`, the batch-fill spell is built
+    #[serde(default)]
+    pub taker_address: Option<String>,
+    #[serde(default)]
+    pub taker_pubkey: Option<String>,
+    /// UTXO holding the tokens to pay with
+    #[serde(default)]
+    pub taker_utxo: Option<String>,
+}
+
+/// Part of a quote taken from one order
+#[derive(Debug, Serialize)]
+pub struct QuoteFill {
+    pub order_id: String,
+    /// `buy_token` bought from the order
+    pub fill_amount: String,
+    /// `pay_token` paid to its maker
+    pub cost: String,
+    /// Whether the fill takes the rest of the order
+    pub fills_order: bool,
+}
+
+/// Quote response
+#[derive(Debug, Serialize)]
+pub struct QuoteResponse {
+    pub buy_token: String,
+    pub pay_token: String,
+    pub requested_amount: String,
+    /// What the orders can supply, at most the requested amount
+    pub filled_amount: String,
+    pub total_cost: String,
+    /// Whether the whole requested amount is covered
+    pub complete: bool,
+    /// Cheapest first
+    pub fills: Vec<QuoteFill>,
+    /// Tip the quote was priced at; orders expiring by then are left out
+    pub tip_height: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spell: Option<SpellData>,
+}
+
+/// Route a purchase across the cheapest live orders
+pub async fn get_quote(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<QuoteRequest>,
+) -> ApiResult<Json<QuoteResponse>> {
+    if req.buy_token.eq_ignore_ascii_case(&req.pay_token) {
+        return Err(ApiError::BadRequest(format!("{}/{} is not a pair", req.buy_token, req.pay_token)));
+    }
+    let amount = match req.amount.trim().parse::<u64>() {
+        Ok(amount) if amount > 0 => amount,
+        _ => return Err(ApiError::BadRequest(format!("amount must be a positive integer, got '{}'", req.amount))),
+    };
+    let taker = match (req.taker_address.as_deref(), req.taker_utxo.as_deref()) {
+        (Some(address), Some(utxo)) => {
+            let pubkey = check_party(state.config.network, "taker_address", address, "taker_pubkey", req.taker_pubkey.as_deref())?;
+            Some((address, utxo, pubkey))
+        }
+        (None, None) => None,
+        _ => {
+            return Err(ApiError::BadRequest(
+                "taker_address and taker_utxo are needed together to build the spell".to_string(),
+            ))
+        }
+    };
+
+    let tip_height = state
+        .chain
+        .tip_height()
+        .await
+        .map_err(|e| ApiError::ChainUnavailable(format!("Failed to get the chain tip: {}", e)))?;
+    let orders = db::get_pair_orders(&state.db, &req.buy_token, &req.pay_token).await?;
+    let route = routing::route(&orders, &req.buy_token, &req.pay_token, amount, tip_height);
+
+    let spell = match taker {
+        Some(_) if route.fills.is_empty() => {
+            return Err(ApiError::Conflict(format!(
+                "No live order sells {} for {}",
+                req.buy_token, req.pay_token
+            )))
+        }
+        Some((address, utxo, pubkey)) => Some(build_route_spell(
+            &state,
+            &route,
+            &orders,
+            utxo,
+            address,
+            pubkey.as_deref(),
+        )?),
+        None => None,
+    };
+
+    Ok(Json(QuoteResponse {
+        buy_token: req.buy_token,
+        pay_token: req.pay_token,
+        requested_amount: amount.to_string(),
+        filled_amount: route.filled.to_string(),
+        total_cost: route.cost.to_string(),
+        complete: route.filled == amount,
+        fills: route
+            .fills
+            .iter()
+            .map(|fill| QuoteFill {
+                order_id: fill.order_id.clone(),
+                fill_amount: fill.fill_amount.to_string(),
+                cost: fill.cost.to_string(),
+                fills_order: fill.fill_amount == fill.remaining,
+            })
+            .collect(),
+        tip_height,
+        spell,
+    }))
+}
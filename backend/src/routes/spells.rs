@@ -1,21 +1,35 @@
 //! Charms spell and transaction endpoints
 
 use axum::{
-    extract::Path,
+    extract::{Path, State},
     Json,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::services::{address, app_registry};
+use crate::error::{ApiError, ApiResult};
+use crate::services::charms::SpellProveRequest;
+use crate::services::fees::{self, FeeTarget};
+use crate::services::prove_queue::JobStatus;
+use crate::services::spell_validator::{self, SpellReport};
+use crate::state::AppState;
 
 /// Prove spell request
 #[derive(Debug, Deserialize)]
 pub struct ProveSpellRequest {
     pub spell_yaml: String,
+    /// Hex encoded app binary; registered apps are attached automatically
+    #[serde(default)]
     pub app_binary: String,
     pub prev_txs: Vec<String>,
     pub funding_utxo: String,
     pub funding_utxo_value: u64,
     pub change_address: String,
-    pub fee_rate: f64,
+    /// Fee rate in sat/vB; the node's estimate when absent
+    #[serde(default)]
+    pub fee_rate: Option<f64>,
 }
 
 /// Prove spell response
@@ -23,8 +37,11 @@ pub struct ProveSpellRequest {
 pub struct ProveSpellResponse {
     pub success: bool,
     pub transactions: Vec<TransactionData>,
-    pub total_fee: u64,
-    pub error: Option<String>,
+    /// Only known for mock transactions
+    pub total_fee: Option<u64>,
+    /// Set while proving is still in progress; poll `/api/prove-jobs/:id`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prove_job_id: Option<String>,
 }
 
 /// Transaction data
@@ -43,6 +60,12 @@ pub struct InputToSign {
     pub script_pubkey: String,
 }
 
+/// Validate spell request
+#[derive(Debug, Deserialize)]
+pub struct ValidateSpellRequest {
+    pub spell_yaml: String,
+}
+
 /// Broadcast transaction request
 #[derive(Debug, Deserialize)]
 pub struct BroadcastRequest {
@@ -54,7 +77,6 @@ pub struct BroadcastRequest {
 pub struct BroadcastResponse {
     pub success: bool,
     pub txids: Vec<String>,
-    pub error: Option<String>,
 }
 
 /// Transaction status
@@ -69,21 +91,12 @@ pub struct TransactionStatus {
 
 /// Prove a spell and generate transactions
 pub async fn prove_spell(
+    State(state): State<Arc<AppState>>,
     Json(req): Json<ProveSpellRequest>,
-) -> Json<ProveSpellResponse> {
-    // TODO: Call Charms prover API
-    // For mock mode, use local prover
-    
-    let charms_api_url = std::env::var("CHARMS_PROVE_API_URL")
-        .unwrap_or_else(|_| "https://v8.charms.dev/spells/prove".to_string());
-    
-    let mock_mode = std::env::var("MOCK_MODE")
-        .map(|v| v == "true")
-        .unwrap_or(true);  // Default to mock for development
-
-    if mock_mode {
+) -> ApiResult<Json<ProveSpellResponse>> {
+    if state.charms.is_mock_mode() {
         // Mock response for development
-        return Json(ProveSpellResponse {
+        return Ok(Json(ProveSpellResponse {
             success: true,
             transactions: vec![
                 TransactionData {
@@ -103,97 +116,149 @@ pub async fn prove_spell(
                     inputs_to_sign: vec![],  // Spell tx uses commit output
                 },
             ],
-            total_fee: 500,
-            error: None,
-        });
+            total_fee: Some(500),
+            prove_job_id: None,
+        }));
     }
 
-    // Call Charms prover API
-    let client = reqwest::Client::new();
-    
-    match client
-        .post(&charms_api_url)
-        .json(&serde_json::json!({
-            "spell": req.spell_yaml,
-            "binaries": {
-                // TODO: Include app binary with correct VK
-            },
-            "prev_txs": req.prev_txs,
-            "funding_utxo": req.funding_utxo,
-            "funding_utxo_value": req.funding_utxo_value,
-            "change_address": req.change_address,
-            "fee_rate": req.fee_rate,
-            "chain": "Bitcoin"
-        }))
-        .send()
-        .await
-    {
-        Ok(response) => {
-            if response.status().is_success() {
-                // TODO: Parse response and extract transactions
-                Json(ProveSpellResponse {
-                    success: true,
-                    transactions: vec![],
-                    total_fee: 0,
-                    error: None,
-                })
-            } else {
-                let error = response.text().await.unwrap_or_default();
-                Json(ProveSpellResponse {
-                    success: false,
-                    transactions: vec![],
-                    total_fee: 0,
-                    error: Some(error),
+    // Fail fast on spells the prover would reject
+    let report = spell_validator::validate(&req.spell_yaml);
+    if !report.valid {
+        return Err(ApiError::InvalidSpell(format!("Spell failed validation: {}", report.error_summary())));
+    }
+
+    let mut binaries = BTreeMap::new();
+    if !req.app_binary.is_empty() {
+        let binary = hex::decode(&req.app_binary)
+            .map_err(|e| ApiError::BadRequest(format!("app_binary is not hex: {}", e)))?;
+        binaries.insert(app_registry::compute_vk(&binary), binary);
+    }
+    state.apps.attach_binaries(&req.spell_yaml, &mut binaries);
+
+    let request = SpellProveRequest {
+        spell: req.spell_yaml,
+        binaries,
+        prev_txs: req.prev_txs,
+        funding_utxo: req.funding_utxo,
+        funding_utxo_value: req.funding_utxo_value,
+        change_address: req.change_address,
+        fee_rate: fees::select_fee_rate(state.chain.as_ref(), &state.config.fees, req.fee_rate, FeeTarget::Normal).await,
+        chain: address::network_name(state.config.network).to_string(),
+    };
+
+    let job = state.prove_queue.submit(&request).await?;
+    let job = state
+        .prove_queue
+        .wait(&job.id, state.config.prove_wait)
+        .await?
+        .unwrap_or(job);
+
+    match job.status {
+        JobStatus::Succeeded => Ok(Json(ProveSpellResponse {
+            success: true,
+            transactions: job
+                .transactions
+                .unwrap_or_default()
+                .into_iter()
+                .map(|tx| TransactionData {
+                    txid: tx.txid,
+                    hex: tx.hex,
+                    inputs_to_sign: vec![],
                 })
-            }
-        }
-        Err(e) => Json(ProveSpellResponse {
-            success: false,
+                .collect(),
+            total_fee: None,
+            prove_job_id: None,
+        })),
+        JobStatus::Failed => Err(ApiError::ProverFailed(
+            job.error.unwrap_or_else(|| "Proving failed".to_string()),
+        )),
+        JobStatus::Queued | JobStatus::Running => Ok(Json(ProveSpellResponse {
+            success: true,
             transactions: vec![],
-            total_fee: 0,
-            error: Some(e.to_string()),
-        }),
+            total_fee: None,
+            prove_job_id: Some(job.id),
+        })),
     }
 }
 
+/// Validate a spell locally and return every finding
+pub async fn validate_spell(
+    Json(req): Json<ValidateSpellRequest>,
+) -> Json<SpellReport> {
+    Json(spell_validator::validate(&req.spell_yaml))
+}
+
 /// Broadcast signed transactions
 pub async fn broadcast_transaction(
+    State(state): State<Arc<AppState>>,
     Json(req): Json<BroadcastRequest>,
-) -> Json<BroadcastResponse> {
-    // TODO: Broadcast via Bitcoin Core RPC
-    
-    let mock_mode = std::env::var("MOCK_MODE")
-        .map(|v| v == "true")
-        .unwrap_or(true);
-
-    if mock_mode {
-        return Json(BroadcastResponse {
+) -> ApiResult<Json<BroadcastResponse>> {
+    if state.charms.is_mock_mode() {
+        return Ok(Json(BroadcastResponse {
             success: true,
             txids: req.signed_txs.iter().map(|_| format!("mock_txid_{}", uuid::Uuid::new_v4())).collect(),
-            error: None,
-        });
+        }));
     }
 
-    // TODO: Call Bitcoin Core sendrawtransaction
-    Json(BroadcastResponse {
-        success: false,
-        txids: vec![],
-        error: Some("Not implemented".to_string()),
-    })
+    // Sent in order, so a spell tx follows the commit tx it spends
+    let mut txids = Vec::with_capacity(req.signed_txs.len());
+    for (i, tx_hex) in req.signed_txs.iter().enumerate() {
+        let txid = state.chain.broadcast(tx_hex).await.map_err(|e| {
+            ApiError::BroadcastFailed(format!(
+                "Transaction {} was rejected after {} broadcast: {}",
+                i,
+                txids.len(),
+                e
+            ))
+        })?;
+        txids.push(txid);
+    }
+
+    Ok(Json(BroadcastResponse {
+        success: true,
+        txids,
+    }))
 }
 
 /// Get transaction status
 pub async fn get_transaction_status(
+    State(state): State<Arc<AppState>>,
     Path(txid): Path<String>,
-) -> Json<TransactionStatus> {
-    // TODO: Query Bitcoin Core for transaction status
-    
-    Json(TransactionStatus {
+) -> ApiResult<Json<TransactionStatus>> {
+    if state.charms.is_mock_mode() {
+        return Ok(Json(TransactionStatus {
+            txid,
+            confirmed: false,
+            confirmations: 0,
+            block_height: None,
+            block_hash: None,
+        }));
+    }
+
+    let status = state
+        .chain
+        .tx_status(&txid)
+        .await
+        .map_err(|e| ApiError::ChainUnavailable(e.to_string()))?
+        .ok_or_else(|| ApiError::not_found("transaction_not_found", format!("Transaction {} not found", txid)))?;
+
+    let confirmations = match status.block_height {
+        Some(height) if status.confirmed => {
+            let tip = state
+                .chain
+                .tip_height()
+                .await
+                .map_err(|e| ApiError::ChainUnavailable(e.to_string()))?;
+            (tip + 1).saturating_sub(height) as u32
+        }
+        _ => 0,
+    };
+
+    Ok(Json(TransactionStatus {
         txid,
-        confirmed: false,
-        confirmations: 0,
-        block_height: None,
-        block_hash: None,
-    })
+        confirmed: confirmations > 0,
+        confirmations,
+        block_height: status.block_height,
+        block_hash: status.block_hash,
+    }))
 }
-
@@ -0,0 +1,60 @@
+//! Token registry endpoints
+//!
+//! Anyone can read the registry. Registering or replacing a token changes
+//! what every new order's spell commits to, so it takes the admin key set in
+//! `TOKEN_ADMIN_KEY`, sent as `x-admin-key`; without one set, the registry
+//! is read-only. Orders store only tickers, so a ticker that live orders
+//! use cannot move to another app id or VK.
+
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    Json,
+};
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+
+use super::orders::normalize_chain;
+use crate::db::TokenRecord;
+use crate::error::{ApiError, ApiResult};
+use crate::services::tokens::NewToken;
+use crate::state::AppState;
+
+/// All registered tokens, by ticker
+pub async fn list_tokens(State(state): State<Arc<AppState>>) -> Json<Vec<TokenRecord>> {
+    Json(state.tokens.list())
+}
+
+/// A registered token
+pub async fn get_token(
+    State(state): State<Arc<AppState>>,
+    Path(ticker): Path<String>,
+) -> ApiResult<Json<TokenRecord>> {
+    state
+        .tokens
+        .get(&ticker)
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("token_not_found", format!("Token {} is not registered", ticker)))
+}
+
+/// Register a token, or replace the one with its ticker
+pub async fn register_token(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(token): Json<NewToken>,
+) -> ApiResult<Json<TokenRecord>> {
+    let Some(admin_key) = state.config.token_admin_key.as_deref() else {
+        return Err(ApiError::Forbidden("Token registration is disabled; set TOKEN_ADMIN_KEY".to_string()));
+    };
+    let given = headers.get("x-admin-key").map(|value| value.as_bytes()).unwrap_or_default();
+    if !bool::from(given.ct_eq(admin_key.as_bytes())) {
+        return Err(ApiError::Forbidden("Invalid or missing x-admin-key".to_string()));
+    }
+
+    let token = NewToken { chain: normalize_chain(&token.chain), ..token }
+        .validate()
+        .map_err(ApiError::BadRequest)?;
+    let record = state.tokens.register(token).await?;
+    tracing::info!("Registered token {} ({}) on {}", record.ticker, record.app_id, record.chain);
+    Ok(Json(record))
+}
@@ -1,7 +1,24 @@
 //! Wallet management endpoints
+//!
+//! Signing in: `POST /api/wallet/challenge` issues a message for the address,
+//! the wallet signs it with BIP-322 and `POST /api/wallet/connect` returns a
+//! session token. Routes behind [`require_session`] take it as
+//! `Authorization: Bearer <token>`.
 
-use axum::Json;
+use axum::{
+    extract::{Query, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+    Json,
+};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::error::{ApiError, ApiResult};
+use crate::services::address;
+use crate::services::bitcoin::RpcError;
+use crate::state::AppState;
 
 /// UTXO representation
 #[derive(Debug, Serialize, Deserialize)]
@@ -37,11 +54,36 @@ pub struct TokenBalance {
     pub amount: String,
 }
 
+/// Query parameters for listing UTXOs
+#[derive(Debug, Deserialize)]
+pub struct UtxoQuery {
+    /// Look up this address through the chain backend instead of listing
+    /// the node wallet
+    pub address: Option<String>,
+}
+
+/// Sign-in challenge request
+#[derive(Debug, Deserialize)]
+pub struct ChallengeRequest {
+    pub address: String,
+}
+
+/// Sign-in challenge response
+#[derive(Debug, Serialize)]
+pub struct ChallengeResponse {
+    pub address: String,
+    /// Message to sign with BIP-322
+    pub message: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
 /// Connect wallet request
 #[derive(Debug, Deserialize)]
 pub struct ConnectWalletRequest {
     pub address: String,
+    /// Base64 BIP-322 signature, simple or full
     pub signature: Option<String>,
+    /// The challenge message, as issued
     pub message: Option<String>,
 }
 
@@ -51,65 +93,213 @@ pub struct ConnectWalletResponse {
     pub connected: bool,
     pub address: String,
     pub network: String,
+    /// Bearer token for the order and escrow routes
+    pub session_token: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// The session a request was authorized with, set by [`require_session`]
+#[derive(Debug, Clone)]
+pub struct AuthSession {
+    pub address: String,
+}
+
+/// Issue a sign-in challenge for an address
+pub async fn wallet_challenge(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<ChallengeRequest>,
+) -> ApiResult<Json<ChallengeResponse>> {
+    let challenge = state.sessions.challenge(req.address.trim()).await?;
+    Ok(Json(ChallengeResponse {
+        address: challenge.address,
+        message: challenge.message,
+        expires_at: challenge.expires_at,
+    }))
 }
 
-/// Connect wallet endpoint
+/// Connect wallet endpoint: verify a signed challenge and open a session
 pub async fn connect_wallet(
+    State(state): State<Arc<AppState>>,
     Json(req): Json<ConnectWalletRequest>,
-) -> Json<ConnectWalletResponse> {
-    // TODO: Verify signature if provided
-    // TODO: Determine network from address
-    
-    Json(ConnectWalletResponse {
+) -> ApiResult<Json<ConnectWalletResponse>> {
+    let (Some(signature), Some(message)) = (req.signature.as_deref(), req.message.as_deref()) else {
+        return Err(ApiError::BadRequest(
+            "signature and message are required; get a message from /api/wallet/challenge".to_string(),
+        ));
+    };
+    let address = req.address.trim();
+    let (session_token, session) = state.sessions.sign_in(address, message, signature).await?;
+    tracing::info!("Wallet {} signed in", session.address);
+
+    Ok(Json(ConnectWalletResponse {
         connected: true,
-        address: req.address,
-        network: "testnet4".to_string(),
-    })
+        address: session.address,
+        network: address::network_name(state.config.network).to_string(),
+        session_token,
+        expires_at: session.expires_at,
+    }))
+}
+
+/// End the session of the request's bearer token
+pub async fn disconnect_wallet(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> ApiResult<Json<serde_json::Value>> {
+    let token = bearer_token(&headers)?;
+    state.sessions.sign_out(token).await?;
+    Ok(Json(serde_json::json!({ "connected": false })))
+}
+
+/// Middleware rejecting requests without a live session
+///
+/// Handlers read the signed-in address from the [`AuthSession`] extension.
+pub async fn require_session(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> ApiResult<Response> {
+    let token = bearer_token(request.headers())?;
+    let session = state
+        .sessions
+        .session(token)
+        .await?
+        .ok_or_else(|| ApiError::Unauthorized("Session is unknown or expired; connect the wallet again".to_string()))?;
+    request.extensions_mut().insert(AuthSession { address: session.address });
+    Ok(next.run(request).await)
+}
+
+fn bearer_token(headers: &HeaderMap) -> ApiResult<&str> {
+    headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| ApiError::Unauthorized("Missing bearer session token; connect the wallet first".to_string()))
 }
 
 /// Get wallet balance
-pub async fn get_balance() -> Json<WalletBalance> {
-    // TODO: Query Bitcoin Core for balance
+pub async fn get_balance(
+    State(state): State<Arc<AppState>>,
+) -> ApiResult<Json<WalletBalance>> {
+    if state.charms.is_mock_mode() {
+        return Ok(Json(WalletBalance {
+            btc_balance: 50000,  // sats
+            tokens: vec![
+                TokenBalance {
+                    token_id: "abc123".to_string(),
+                    ticker: "TOAD".to_string(),
+                    amount: "10000".to_string(),
+                },
+            ],
+        }));
+    }
+
     // TODO: Parse UTXOs for charm tokens
-    
-    Json(WalletBalance {
-        btc_balance: 50000,  // sats
-        tokens: vec![
-            TokenBalance {
-                token_id: "abc123".to_string(),
-                ticker: "TOAD".to_string(),
-                amount: "10000".to_string(),
-            },
-        ],
-    })
+    let btc = state.bitcoin.get_balance().await.map_err(chain_error)?;
+
+    Ok(Json(WalletBalance {
+        btc_balance: btc_to_sats(btc),
+        tokens: vec![],
+    }))
 }
 
 /// Get wallet UTXOs
-pub async fn get_utxos() -> Json<Vec<Utxo>> {
-    // TODO: Query Bitcoin Core for UTXOs
+pub async fn get_utxos(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<UtxoQuery>,
+) -> ApiResult<Json<Vec<Utxo>>> {
+    if state.charms.is_mock_mode() {
+        return Ok(Json(vec![
+            Utxo {
+                txid: "abc123def456".to_string(),
+                vout: 0,
+                value: 10000,
+                script_pubkey: "0014...".to_string(),
+                confirmations: 6,
+                charms: Some(vec![
+                    CharmData {
+                        app_id: "toad_token".to_string(),
+                        app_tag: "token".to_string(),
+                        data: serde_json::json!({ "amount": 5000 }),
+                    },
+                ]),
+            },
+        ]));
+    }
+
+    if let Some(address) = query.address {
+        return address_utxos(&state, &address).await.map(Json);
+    }
+
     // TODO: Parse charm data from UTXOs
-    
-    Json(vec![
-        Utxo {
-            txid: "abc123def456".to_string(),
-            vout: 0,
-            value: 10000,
-            script_pubkey: "0014...".to_string(),
-            confirmations: 6,
-            charms: Some(vec![
-                CharmData {
-                    app_id: "toad_token".to_string(),
-                    app_tag: "token".to_string(),
-                    data: serde_json::json!({ "amount": 5000 }),
-                },
-            ]),
-        },
-    ])
+    let unspent = state.bitcoin.list_unspent(None, None).await.map_err(chain_error)?;
+
+    Ok(Json(
+        unspent
+            .into_iter()
+            .map(|u| Utxo {
+                txid: u.txid,
+                vout: u.vout,
+                value: btc_to_sats(u.amount),
+                script_pubkey: u.script_pub_key,
+                confirmations: u.confirmations,
+                charms: None,
+            })
+            .collect(),
+    ))
 }
 
 /// Get new wallet address
-pub async fn get_address() -> Json<String> {
-    // TODO: Call Bitcoin Core getnewaddress
-    Json("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string())
+pub async fn get_address(
+    State(state): State<Arc<AppState>>,
+) -> ApiResult<Json<String>> {
+    if state.charms.is_mock_mode() {
+        return Ok(Json("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string()));
+    }
+
+    let address = state.bitcoin.get_new_address(None).await.map_err(chain_error)?;
+    Ok(Json(address))
+}
+
+/// UTXOs of any address, from the chain backend
+async fn address_utxos(state: &AppState, address: &str) -> ApiResult<Vec<Utxo>> {
+    let script_pubkey = address::parse_address("address", address, state.config.network)?
+        .script_pubkey()
+        .to_hex_string();
+
+    let utxos = state
+        .chain
+        .address_utxos(address)
+        .await
+        .map_err(|e| ApiError::ChainUnavailable(format!("UTXO lookup failed: {}", e)))?;
+    let tip = if utxos.iter().any(|utxo| utxo.height.is_some()) {
+        state
+            .chain
+            .tip_height()
+            .await
+            .map_err(|e| ApiError::ChainUnavailable(format!("UTXO lookup failed: {}", e)))?
+    } else {
+        0
+    };
+
+    Ok(utxos
+        .into_iter()
+        .map(|utxo| Utxo {
+            txid: utxo.txid,
+            vout: utxo.vout,
+            value: utxo.value,
+            script_pubkey: script_pubkey.clone(),
+            confirmations: utxo.height.map_or(0, |height| (tip + 1).saturating_sub(height) as u32),
+            charms: None,
+        })
+        .collect())
 }
 
+fn btc_to_sats(btc: f64) -> u64 {
+    (btc * 100_000_000.0).round() as u64
+}
+
+fn chain_error(e: RpcError) -> ApiError {
+    ApiError::ChainUnavailable(format!("Bitcoin node request failed: {}", e))
+}
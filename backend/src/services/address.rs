@@ -0,0 +1,164 @@
+//! Address and public key validation
+//!
+//! Addresses coming from clients are parsed with the `bitcoin` crate and
+//! checked against the network the server runs on (`BITCOIN_NETWORK`:
+//! `mainnet`, `testnet4`, `testnet`, `signet` or `regtest`, default
+//! `testnet4`). Testnets and signet share address prefixes, so an address
+//! valid on one of them passes on the others. Public keys are accepted as
+//! hex, either 33-byte compressed or 32-byte x-only.
+
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, XOnlyPublicKey};
+use bitcoin::{Address, CompressedPublicKey, Network};
+
+/// Why an address or key was rejected
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AddressError {
+    #[error("{field} '{value}' is not a Bitcoin address: {reason}")]
+    Invalid { field: String, value: String, reason: String },
+    #[error("{field} {value} is not a {network} address")]
+    WrongNetwork { field: String, value: String, network: &'static str },
+    #[error("{field} '{value}' is not a public key: {reason}")]
+    InvalidPubkey { field: String, value: String, reason: &'static str },
+}
+
+/// Network by name; Bitcoin Core's `-chain` names are accepted too
+pub fn parse_network(name: &str) -> Option<Network> {
+    match name.trim().to_lowercase().as_str() {
+        "mainnet" | "main" | "bitcoin" => Some(Network::Bitcoin),
+        "testnet4" => Some(Network::Testnet4),
+        "testnet" | "testnet3" | "test" => Some(Network::Testnet),
+        "signet" => Some(Network::Signet),
+        "regtest" => Some(Network::Regtest),
+        _ => None,
+    }
+}
+
+/// Name clients see for a network
+pub fn network_name(network: Network) -> &'static str {
+    match network {
+        Network::Bitcoin => "mainnet",
+        Network::Testnet => "testnet",
+        Network::Testnet4 => "testnet4",
+        Network::Signet => "signet",
+        Network::Regtest => "regtest",
+    }
+}
+
+/// Parse the address in `field`, which must be for `network`
+pub fn parse_address(field: &str, value: &str, network: Network) -> Result<Address, AddressError> {
+    let address = value.trim().parse::<Address<NetworkUnchecked>>().map_err(|e| AddressError::Invalid {
+        field: field.to_string(),
+        value: value.to_string(),
+        reason: e.to_string(),
+    })?;
+    if !address.is_valid_for_network(network) {
+        return Err(AddressError::WrongNetwork {
+            field: field.to_string(),
+            value: value.to_string(),
+            network: network_name(network),
+        });
+    }
+    Ok(address.assume_checked())
+}
+
+/// Check the hex public key in `field` and return it in lower case
+pub fn parse_pubkey(field: &str, value: &str) -> Result<String, AddressError> {
+    let invalid = |reason| AddressError::InvalidPubkey { field: field.to_string(), value: value.to_string(), reason };
+    let bytes = hex::decode(value.trim()).map_err(|_| invalid("not hex"))?;
+    let on_curve = match bytes.len() {
+        33 => PublicKey::from_slice(&bytes).is_ok(),
+        32 => XOnlyPublicKey::from_slice(&bytes).is_ok(),
+        _ => return Err(invalid("expected 33 bytes compressed or 32 bytes x-only")),
+    };
+    if !on_curve {
+        return Err(invalid("not a valid point"));
+    }
+    Ok(hex::encode(bytes))
+}
+
+/// Whether `address` is the native segwit or key-path taproot address of
+/// the hex public key `pubkey` on `network`
+pub fn is_address_of_pubkey(pubkey: &str, address: &str, network: Network) -> bool {
+    let Ok(bytes) = hex::decode(pubkey.trim()) else { return false };
+    let secp = Secp256k1::verification_only();
+    let candidates = match bytes.len() {
+        33 => match CompressedPublicKey::from_slice(&bytes) {
+            Ok(key) => vec![
+                Address::p2wpkh(&key, network),
+                Address::p2tr(&secp, key.0.x_only_public_key().0, None, network),
+            ],
+            Err(_) => return false,
+        },
+        32 => match XOnlyPublicKey::from_slice(&bytes) {
+            Ok(key) => vec![Address::p2tr(&secp, key, None, network)],
+            Err(_) => return false,
+        },
+        _ => return false,
+    };
+    candidates.iter().any(|candidate| candidate.to_string().eq_ignore_ascii_case(address.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TESTNET_ADDRESS: &str = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+    const MAINNET_ADDRESS: &str = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+
+    #[test]
+    fn test_addresses_are_checked_against_the_network() {
+        assert!(parse_address("maker_address", TESTNET_ADDRESS, Network::Testnet4).is_ok());
+        assert!(parse_address("maker_address", MAINNET_ADDRESS, Network::Bitcoin).is_ok());
+        assert_eq!(
+            parse_address("maker_address", MAINNET_ADDRESS, Network::Testnet4).unwrap_err(),
+            AddressError::WrongNetwork {
+                field: "maker_address".to_string(),
+                value: MAINNET_ADDRESS.to_string(),
+                network: "testnet4",
+            }
+        );
+        // Regtest has its own bech32 prefix
+        assert!(parse_address("taker_address", TESTNET_ADDRESS, Network::Regtest).is_err());
+        assert!(matches!(
+            parse_address("taker_address", "not-an-address", Network::Testnet4),
+            Err(AddressError::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn test_pubkeys_must_be_compressed_or_x_only() {
+        let compressed = "02c7f12003196442943d8588e01aee840423cc54fc1521526a3b85c2b0cbd58872";
+        assert_eq!(parse_pubkey("maker_pubkey", &compressed.to_uppercase()).unwrap(), compressed);
+        assert!(parse_pubkey("maker_pubkey", &compressed[2..]).is_ok());
+        // Uncompressed keys, bad prefixes and non-hex are rejected
+        assert!(parse_pubkey("maker_pubkey", &format!("04{}", "11".repeat(64))).is_err());
+        assert!(parse_pubkey("maker_pubkey", &format!("05{}", &compressed[2..])).is_err());
+        assert!(parse_pubkey("maker_pubkey", "tb1qmaker").is_err());
+    }
+
+    #[test]
+    fn test_addresses_of_pubkeys() {
+        let compressed = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        assert!(is_address_of_pubkey(compressed, TESTNET_ADDRESS, Network::Testnet4));
+        assert!(is_address_of_pubkey(compressed, MAINNET_ADDRESS, Network::Bitcoin));
+        assert!(!is_address_of_pubkey(compressed, MAINNET_ADDRESS, Network::Testnet4));
+
+        let secp = Secp256k1::verification_only();
+        let x_only = XOnlyPublicKey::from_slice(&hex::decode(&compressed[2..]).unwrap()).unwrap();
+        let taproot = Address::p2tr(&secp, x_only, None, Network::Testnet4).to_string();
+        assert!(is_address_of_pubkey(&compressed[2..], &taproot, Network::Testnet4));
+        assert!(is_address_of_pubkey(compressed, &taproot, Network::Testnet4));
+        assert!(!is_address_of_pubkey(&compressed[2..], TESTNET_ADDRESS, Network::Testnet4));
+        assert!(!is_address_of_pubkey("tb1qmaker", TESTNET_ADDRESS, Network::Testnet4));
+    }
+
+    #[test]
+    fn test_network_names() {
+        assert_eq!(parse_network("mainnet"), Some(Network::Bitcoin));
+        assert_eq!(parse_network("Testnet4"), Some(Network::Testnet4));
+        assert_eq!(parse_network("main"), Some(Network::Bitcoin));
+        assert_eq!(parse_network("liquid"), None);
+        assert_eq!(network_name(Network::Bitcoin), "mainnet");
+    }
+}
@@ -0,0 +1,222 @@
+//! Registry of the Charms apps this backend proves spells for
+//!
+//! The compiled swap and escrow WASM binaries are loaded once at startup and
+//! their verification keys derived from them, so the VKs written into spells
+//! always match the binaries sent to the prover. A configured VK that
+//! disagrees with its binary stops the server from starting.
+
+use anyhow::{bail, Context, Result};
+use serde_yaml::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Default location of the swap app binary (`charms app build`)
+pub const SWAP_APP_WASM_PATH: &str = "target/wasm32-wasip1/release/liquid-swap-app.wasm";
+/// Default location of the escrow app binary
+pub const ESCROW_APP_WASM_PATH: &str = "target/wasm32-wasip1/release/liquid-escrow-app.wasm";
+
+/// VK used in mock mode when no binary has been built
+const PLACEHOLDER_VK: &str = "857ee181813511526321296bb0183b7496e1cdc0801552495464e9ec44c37718";
+
+/// Where the app binaries are and the VKs they are expected to have
+#[derive(Debug, Clone)]
+pub struct AppsConfig {
+    /// Swap app binary (`SWAP_APP_BINARY_PATH`, or `APP_WASM_PATH`)
+    pub swap_binary: PathBuf,
+    /// Escrow app binary (`ESCROW_APP_BINARY_PATH`)
+    pub escrow_binary: PathBuf,
+    /// Expected swap app VK (`SWAP_APP_VK`)
+    pub swap_vk: Option<String>,
+    /// Expected escrow app VK (`ESCROW_APP_VK`)
+    pub escrow_vk: Option<String>,
+}
+
+/// A compiled app and its verification key
+#[derive(Debug, Clone)]
+pub struct AppBinary {
+    pub name: &'static str,
+    pub path: PathBuf,
+    pub vk: String,
+    /// Empty when running in mock mode without a built binary
+    pub binary: Vec<u8>,
+}
+
+impl AppBinary {
+    /// Load a binary and check it against the configured VK, if any
+    pub fn load(name: &'static str, path: PathBuf, configured_vk: Option<&str>) -> Result<Self> {
+        let binary = std::fs::read(&path)
+            .with_context(|| format!("failed to read {} app binary at {}", name, path.display()))?;
+        let vk = compute_vk(&binary);
+
+        if let Some(expected) = configured_vk {
+            if !expected.eq_ignore_ascii_case(&vk) {
+                bail!(
+                    "{} app VK mismatch: configured {} but {} hashes to {}; rebuild the app or update the VK",
+                    name,
+                    expected,
+                    path.display(),
+                    vk
+                );
+            }
+        }
+
+        Ok(Self { name, path, vk, binary })
+    }
+
+    /// Stand-in used in mock mode when the binary has not been built
+    fn placeholder(name: &'static str, path: PathBuf, configured_vk: Option<&str>) -> Self {
+        Self {
+            name,
+            path,
+            vk: configured_vk.unwrap_or(PLACEHOLDER_VK).to_lowercase(),
+            binary: Vec::new(),
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        !self.binary.is_empty()
+    }
+}
+
+/// The apps known to the backend
+#[derive(Debug, Clone)]
+pub struct AppRegistry {
+    pub swap: AppBinary,
+    pub escrow: AppBinary,
+}
+
+impl AppRegistry {
+    /// Load the configured apps
+    ///
+    /// Missing binaries are an error unless `allow_missing` is set, as it is
+    /// in mock mode.
+    pub fn load(config: &AppsConfig, allow_missing: bool) -> Result<Self> {
+        Ok(Self {
+            swap: load_app("swap", config.swap_binary.clone(), config.swap_vk.as_deref(), allow_missing)?,
+            escrow: load_app("escrow", config.escrow_binary.clone(), config.escrow_vk.as_deref(), allow_missing)?,
+        })
+    }
+
+    pub fn apps(&self) -> [&AppBinary; 2] {
+        [&self.swap, &self.escrow]
+    }
+
+    /// Loaded binaries keyed by VK
+    pub fn binaries(&self) -> BTreeMap<String, Vec<u8>> {
+        self.apps()
+            .into_iter()
+            .filter(|app| app.is_loaded())
+            .map(|app| (app.vk.clone(), app.binary.clone()))
+            .collect()
+    }
+
+    /// Add the binaries of every registered app the spell uses
+    ///
+    /// Binaries already present in `binaries` are left alone.
+    pub fn attach_binaries(&self, spell_yaml: &str, binaries: &mut BTreeMap<String, Vec<u8>>) {
+        let used = spell_app_vks(spell_yaml);
+        for app in self.apps() {
+            if app.is_loaded() && used.iter().any(|vk| vk.eq_ignore_ascii_case(&app.vk)) {
+                binaries
+                    .entry(app.vk.clone())
+                    .or_insert_with(|| app.binary.clone());
+            }
+        }
+    }
+}
+
+fn load_app(
+    name: &'static str,
+    path: PathBuf,
+    configured_vk: Option<&str>,
+    allow_missing: bool,
+) -> Result<AppBinary> {
+    if allow_missing && !path.exists() {
+        tracing::warn!("{} app binary not found at {}, using placeholder VK", name, path.display());
+        return Ok(AppBinary::placeholder(name, path, configured_vk));
+    }
+    let app = AppBinary::load(name, path, configured_vk)?;
+    tracing::info!("Loaded {} app from {} (VK {})", name, app.path.display(), app.vk);
+    Ok(app)
+}
+
+/// Verification key of an app binary: the hex SHA-256 of the WASM
+pub fn compute_vk(binary: &[u8]) -> String {
+    hex::encode(Sha256::digest(binary))
+}
+
+/// VKs referenced by a spell's `apps` section (`tag/identity/vk`)
+fn spell_app_vks(spell_yaml: &str) -> Vec<String> {
+    let Ok(spell) = serde_yaml::from_str::<Value>(spell_yaml) else {
+        return Vec::new();
+    };
+    let Some(apps) = spell.get("apps").and_then(Value::as_mapping) else {
+        return Vec::new();
+    };
+    apps.values()
+        .filter_map(Value::as_str)
+        .filter_map(|spec| spec.splitn(3, '/').nth(2))
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_binary(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.wasm", name, uuid::Uuid::new_v4()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_vk_is_derived_from_binary() {
+        let path = write_binary("swap", b"\0asm swap");
+        let app = AppBinary::load("swap", path.clone(), None).unwrap();
+        assert_eq!(app.vk, compute_vk(b"\0asm swap"));
+        assert_eq!(app.vk.len(), 64);
+
+        // A matching configured VK is accepted, a different one is not
+        assert!(AppBinary::load("swap", path.clone(), Some(&app.vk.to_uppercase())).is_ok());
+        let err = AppBinary::load("swap", path.clone(), Some(&"00".repeat(32))).unwrap_err();
+        assert!(err.to_string().contains("VK mismatch"));
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_missing_binary_only_allowed_in_mock_mode() {
+        let path = std::env::temp_dir().join("does-not-exist.wasm");
+        assert!(load_app("escrow", path.clone(), None, false).is_err());
+
+        let app = load_app("escrow", path, None, true).unwrap();
+        assert!(!app.is_loaded());
+        assert_eq!(app.vk, PLACEHOLDER_VK);
+    }
+
+    #[test]
+    fn test_attach_binaries_for_spell_apps() {
+        let swap_path = write_binary("swap", b"swap");
+        let escrow_path = write_binary("escrow", b"escrow");
+        let registry = AppRegistry {
+            swap: AppBinary::load("swap", swap_path.clone(), None).unwrap(),
+            escrow: AppBinary::load("escrow", escrow_path.clone(), None).unwrap(),
+        };
+
+        let spell = format!(
+            "version: 8\napps:\n  $ORDER: n/{}/{}\n  $OFFER: t/toad/{}\n",
+            "11".repeat(32),
+            registry.swap.vk,
+            "22".repeat(32)
+        );
+        let mut binaries = BTreeMap::new();
+        registry.attach_binaries(&spell, &mut binaries);
+        assert_eq!(binaries.len(), 1);
+        assert_eq!(binaries[&registry.swap.vk], b"swap");
+        assert_eq!(registry.binaries().len(), 2);
+
+        std::fs::remove_file(swap_path).ok();
+        std::fs::remove_file(escrow_path).ok();
+    }
+}
@@ -0,0 +1,359 @@
+//! Wallet authentication
+//!
+//! A wallet signs in by signing a one-time challenge for its address with
+//! BIP-322, in the simple (witness only) or full (whole `to_sign`
+//! transaction) format. Native segwit (P2WPKH) and taproot key-path
+//! (P2TR) addresses are supported. A valid signature opens a session whose
+//! bearer token the order and escrow routes require; only a hash of the
+//! token is stored.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use bitcoin::blockdata::opcodes::all::{OP_PUSHBYTES_0, OP_RETURN};
+use bitcoin::consensus;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{Message, Secp256k1, XOnlyPublicKey};
+use bitcoin::sighash::{Prevouts, SighashCache};
+use bitcoin::{
+    absolute, ecdsa, script, taproot, transaction, Address, Amount, CompressedPublicKey, Network, OutPoint, Script,
+    ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
+};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use uuid::Uuid;
+
+use super::address::{self as addresses, AddressError};
+use crate::db::{self, ChallengeRecord, DbPool, SessionRecord};
+
+/// Tag of the BIP-322 message hash
+const MESSAGE_TAG: &[u8] = b"BIP0322-signed-message";
+
+/// Authentication failure
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    /// The address cannot be parsed or is for another network
+    #[error(transparent)]
+    InvalidAddress(#[from] AddressError),
+    /// The address type cannot sign BIP-322 messages here
+    #[error("{0}")]
+    UnsupportedAddress(String),
+    /// The signature is malformed or does not verify
+    #[error("{0}")]
+    InvalidSignature(String),
+    /// The challenge is unknown, used, expired or for another address
+    #[error("{0}")]
+    InvalidChallenge(String),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+/// Session settings
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// How long a challenge can be answered (`AUTH_CHALLENGE_SECS`, default 300)
+    pub challenge_ttl: Duration,
+    /// How long a session lasts (`SESSION_TTL_SECS`, default 86400)
+    pub session_ttl: Duration,
+}
+
+/// A signed-in wallet, as seen by handlers
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub address: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Challenges and sessions, kept in the database
+pub struct SessionStore {
+    db: DbPool,
+    network: Network,
+    config: SessionConfig,
+}
+
+impl SessionStore {
+    /// Sessions for wallets on `network`
+    pub fn new(db: DbPool, network: Network, config: SessionConfig) -> Self {
+        Self { db, network, config }
+    }
+
+    /// Network wallets sign in on
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Issue a challenge for `address` to sign
+    pub async fn challenge(&self, address: &str) -> Result<ChallengeRecord, AuthError> {
+        addresses::parse_address("address", address, self.network)?;
+        let now = chrono::Utc::now();
+        let nonce = Uuid::new_v4().simple().to_string();
+        let expires_at = now + ttl(self.config.challenge_ttl);
+        let challenge = ChallengeRecord {
+            message: challenge_message(address, &nonce, now, expires_at),
+            nonce,
+            address: address.to_string(),
+            expires_at,
+            created_at: now,
+        };
+        db::insert_challenge(&self.db, &challenge).await?;
+        Ok(challenge)
+    }
+
+    /// Check a signed challenge and open a session; returns the token with it
+    pub async fn sign_in(&self, address: &str, message: &str, signature: &str) -> Result<(String, Session), AuthError> {
+        let parsed = addresses::parse_address("address", address, self.network)?;
+        let nonce = challenge_nonce(message)
+            .ok_or_else(|| AuthError::InvalidChallenge("message is not a sign-in challenge".to_string()))?;
+        // Taken before verifying, so a challenge gets one attempt
+        let challenge = db::take_challenge(&self.db, nonce)
+            .await?
+            .ok_or_else(|| AuthError::InvalidChallenge("challenge is unknown, used or expired".to_string()))?;
+        if challenge.address != address || challenge.message != message {
+            return Err(AuthError::InvalidChallenge("challenge was issued for another address or message".to_string()));
+        }
+        verify_bip322(&parsed, message, signature)?;
+
+        let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let now = chrono::Utc::now();
+        let record = SessionRecord {
+            token_hash: token_hash(&token),
+            address: address.to_string(),
+            expires_at: now + ttl(self.config.session_ttl),
+            created_at: now,
+        };
+        db::insert_session(&self.db, &record).await?;
+        Ok((token, Session { address: record.address, expires_at: record.expires_at }))
+    }
+
+    /// The session of a bearer token, if it is live
+    pub async fn session(&self, token: &str) -> Result<Option<Session>, AuthError> {
+        let record = db::get_session(&self.db, &token_hash(token)).await?;
+        Ok(record.map(|record| Session { address: record.address, expires_at: record.expires_at }))
+    }
+
+    /// End the session of a bearer token
+    pub async fn sign_out(&self, token: &str) -> Result<(), AuthError> {
+        db::delete_session(&self.db, &token_hash(token)).await?;
+        Ok(())
+    }
+}
+
+fn ttl(duration: Duration) -> chrono::Duration {
+    chrono::Duration::from_std(duration).unwrap_or_else(|_| chrono::Duration::days(1))
+}
+
+fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Message a wallet signs to sign in
+pub fn challenge_message(
+    address: &str,
+    nonce: &str,
+    issued_at: chrono::DateTime<chrono::Utc>,
+    expires_at: chrono::DateTime<chrono::Utc>,
+) -> String {
+    format!(
+        "Liquid Nation wants you to sign in with your Bitcoin account:\n{}\n\nNonce: {}\nIssued At: {}\nExpiration Time: {}",
+        address,
+        nonce,
+        issued_at.to_rfc3339(),
+        expires_at.to_rfc3339()
+    )
+}
+
+/// Nonce of a challenge message
+pub fn challenge_nonce(message: &str) -> Option<&str> {
+    message.lines().find_map(|line| line.strip_prefix("Nonce: ")).map(str::trim).filter(|nonce| !nonce.is_empty())
+}
+
+/// BIP-322 tagged hash of a message
+pub fn message_hash(message: &[u8]) -> [u8; 32] {
+    let tag = Sha256::digest(MESSAGE_TAG);
+    Sha256::new().chain_update(tag).chain_update(tag).chain_update(message).finalize().into()
+}
+
+/// Virtual transaction whose output the signature spends
+fn to_spend(script_pubkey: &Script, message: &[u8]) -> Transaction {
+    let script_sig = script::Builder::new()
+        .push_opcode(OP_PUSHBYTES_0)
+        .push_slice(message_hash(message))
+        .into_script();
+    Transaction {
+        version: transaction::Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig,
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut { value: Amount::ZERO, script_pubkey: script_pubkey.to_owned() }],
+    }
+}
+
+/// Virtual transaction spending `to_spend` with `witness`
+fn to_sign(to_spend: &Transaction, witness: Witness) -> Transaction {
+    Transaction {
+        version: transaction::Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint { txid: to_spend.compute_txid(), vout: 0 },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ZERO,
+            witness,
+        }],
+        output: vec![op_return()],
+    }
+}
+
+fn op_return() -> TxOut {
+    TxOut { value: Amount::ZERO, script_pubkey: script::Builder::new().push_opcode(OP_RETURN).into_script() }
+}
+
+/// Verify a base64 BIP-322 signature of `message` by `address`
+pub fn verify_bip322(address: &Address, message: &str, signature: &str) -> Result<(), AuthError> {
+    let script_pubkey = address.script_pubkey();
+    if !(script_pubkey.is_p2wpkh() || script_pubkey.is_p2tr()) {
+        return Err(AuthError::UnsupportedAddress(format!(
+            "{} is not a native segwit or taproot address",
+            address
+        )));
+    }
+    let bytes = BASE64
+        .decode(signature.trim())
+        .map_err(|e| AuthError::InvalidSignature(format!("Signature is not base64: {}", e)))?;
+
+    let to_spend = to_spend(&script_pubkey, message.as_bytes());
+    let to_sign = match consensus::deserialize::<Witness>(&bytes) {
+        Ok(witness) => to_sign(&to_spend, witness),
+        Err(_) => {
+            let tx: Transaction = consensus::deserialize(&bytes).map_err(|_| {
+                AuthError::InvalidSignature("Signature is neither a BIP-322 witness nor a transaction".to_string())
+            })?;
+            // Only the challenge input; proofs of funds are not accepted
+            let spends_challenge = tx.input.len() == 1
+                && tx.input[0].previous_output == OutPoint { txid: to_spend.compute_txid(), vout: 0 };
+            if !spends_challenge || tx.output != [op_return()] {
+                return Err(AuthError::InvalidSignature(
+                    "Signature transaction is not a BIP-322 to_sign transaction for the message".to_string(),
+                ));
+            }
+            tx
+        }
+    };
+
+    verify_witness(&to_sign, &script_pubkey)
+}
+
+/// Check the witness of the single `to_sign` input against `script_pubkey`
+fn verify_witness(to_sign: &Transaction, script_pubkey: &Script) -> Result<(), AuthError> {
+    let invalid = |reason: &str| AuthError::InvalidSignature(reason.to_string());
+    let secp = Secp256k1::verification_only();
+    let witness = &to_sign.input[0].witness;
+    let mut cache = SighashCache::new(to_sign);
+
+    if script_pubkey.is_p2wpkh() {
+        let (Some(signature), Some(pubkey), 2) = (witness.nth(0), witness.nth(1), witness.len()) else {
+            return Err(invalid("P2WPKH witness must be a signature and a public key"));
+        };
+        let signature = ecdsa::Signature::from_slice(signature).map_err(|_| invalid("Malformed ECDSA signature"))?;
+        let pubkey = CompressedPublicKey::from_slice(pubkey).map_err(|_| invalid("Malformed public key"))?;
+        if ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash()).as_script() != script_pubkey {
+            return Err(invalid("Public key does not belong to the address"));
+        }
+        let sighash = cache
+            .p2wpkh_signature_hash(0, script_pubkey, Amount::ZERO, signature.sighash_type)
+            .map_err(|e| AuthError::InvalidSignature(e.to_string()))?;
+        secp.verify_ecdsa(&Message::from_digest(sighash.to_byte_array()), &signature.signature, &pubkey.0)
+            .map_err(|_| invalid("Signature does not verify"))
+    } else {
+        let (Some(signature), 1) = (witness.nth(0), witness.len()) else {
+            return Err(invalid("Taproot witness must be a single key-path signature"));
+        };
+        let signature = taproot::Signature::from_slice(signature).map_err(|_| invalid("Malformed Schnorr signature"))?;
+        let output_key =
+            XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..]).map_err(|_| invalid("Invalid taproot output key"))?;
+        let prevouts = [TxOut { value: Amount::ZERO, script_pubkey: script_pubkey.to_owned() }];
+        let sighash = cache
+            .taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), signature.sighash_type)
+            .map_err(|e| AuthError::InvalidSignature(e.to_string()))?;
+        secp.verify_schnorr(&signature.signature, &Message::from_digest(sighash.to_byte_array()), &output_key)
+            .map_err(|_| invalid("Signature does not verify"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::key::{Keypair, TapTweak};
+    use bitcoin::sighash::TapSighashType;
+
+    fn parse_address(address: &str) -> Address {
+        address.parse::<Address<bitcoin::address::NetworkUnchecked>>().unwrap().assume_checked()
+    }
+
+    // Test vectors from BIP-322
+    const P2WPKH_ADDRESS: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    const HELLO_WORLD_SIGNATURE: &str = "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+
+    #[test]
+    fn test_message_hash_vectors() {
+        assert_eq!(
+            hex::encode(message_hash(b"")),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        assert_eq!(
+            hex::encode(message_hash(b"Hello World")),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+    }
+
+    #[test]
+    fn test_verify_p2wpkh_simple_signature() {
+        let address = parse_address(P2WPKH_ADDRESS);
+        assert!(verify_bip322(&address, "Hello World", HELLO_WORLD_SIGNATURE).is_ok());
+        assert!(matches!(
+            verify_bip322(&address, "Hello World!", HELLO_WORLD_SIGNATURE),
+            Err(AuthError::InvalidSignature(_))
+        ));
+
+        let legacy = parse_address("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2");
+        assert!(matches!(
+            verify_bip322(&legacy, "Hello World", HELLO_WORLD_SIGNATURE),
+            Err(AuthError::UnsupportedAddress(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_taproot_simple_and_full_signatures() {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp, &[7u8; 32]).unwrap();
+        let (internal_key, _) = keypair.x_only_public_key();
+        let address = Address::p2tr(&secp, internal_key, None, Network::Testnet);
+        let message = challenge_message(address.to_string().as_str(), "abc", chrono::Utc::now(), chrono::Utc::now());
+
+        let script_pubkey = address.script_pubkey();
+        let mut tx = to_sign(&to_spend(&script_pubkey, message.as_bytes()), Witness::new());
+        let prevouts = [TxOut { value: Amount::ZERO, script_pubkey: script_pubkey.clone() }];
+        let sighash = SighashCache::new(&tx)
+            .taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), TapSighashType::Default)
+            .unwrap();
+        let tweaked = keypair.tap_tweak(&secp, None).to_keypair();
+        let signature = secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash.to_byte_array()), &tweaked);
+        tx.input[0].witness.push(signature.as_ref());
+
+        let simple = BASE64.encode(consensus::serialize(&tx.input[0].witness));
+        let full = BASE64.encode(consensus::serialize(&tx));
+        assert!(verify_bip322(&address, &message, &simple).is_ok());
+        assert!(verify_bip322(&address, &message, &full).is_ok());
+        assert!(verify_bip322(&address, "another message", &simple).is_err());
+        assert!(verify_bip322(&address, "another message", &full).is_err());
+    }
+
+    #[test]
+    fn test_challenge_nonce_round_trips() {
+        let now = chrono::Utc::now();
+        let message = challenge_message(P2WPKH_ADDRESS, "0f1e2d", now, now);
+        assert_eq!(challenge_nonce(&message), Some("0f1e2d"));
+        assert_eq!(challenge_nonce("Hello World"), None);
+    }
+}
@@ -1,16 +1,200 @@
 //! Bitcoin Core RPC client service
+//!
+//! One HTTP client is shared by every call so connections are reused. The
+//! node is reached with a user and password or through its cookie file, and
+//! calls can be scoped to a wallet (`/wallet/<name>`). Transient failures
+//! (unreachable node, timeouts, a full work queue, warmup) are retried with
+//! exponential backoff; anything else is returned as a typed [`RpcError`].
 
-use anyhow::Result;
+use reqwest::{StatusCode, Url};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::time::Duration;
 
 /// Bitcoin service (alias for RPC client)
 pub type BitcoinService = BitcoinRpcClient;
 
+/// Result of an RPC call
+pub type RpcResult<T> = Result<T, RpcError>;
+
+/// Error codes returned by Bitcoin Core (`src/rpc/protocol.h`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcErrorCode {
+    Misc,
+    Type,
+    Wallet,
+    /// Also "No such mempool or blockchain transaction"
+    InvalidAddressOrKey,
+    WalletInsufficientFunds,
+    InvalidParameter,
+    WalletUnlockNeeded,
+    WalletNotFound,
+    WalletNotSpecified,
+    Deserialization,
+    /// The transaction failed a consensus or standardness check
+    Verify,
+    /// The transaction was rejected by the mempool
+    VerifyRejected,
+    /// The transaction's outputs are already in the UTXO set
+    VerifyAlreadyInChain,
+    InWarmup,
+    MethodNotFound,
+    Other(i64),
+}
+
+impl RpcErrorCode {
+    pub fn from_code(code: i64) -> Self {
+        match code {
+            -1 => RpcErrorCode::Misc,
+            -3 => RpcErrorCode::Type,
+            -4 => RpcErrorCode::Wallet,
+            -5 => RpcErrorCode::InvalidAddressOrKey,
+            -6 => RpcErrorCode::WalletInsufficientFunds,
+            -8 => RpcErrorCode::InvalidParameter,
+            -13 => RpcErrorCode::WalletUnlockNeeded,
+            -18 => RpcErrorCode::WalletNotFound,
+            -19 => RpcErrorCode::WalletNotSpecified,
+            -22 => RpcErrorCode::Deserialization,
+            -25 => RpcErrorCode::Verify,
+            -26 => RpcErrorCode::VerifyRejected,
+            -27 => RpcErrorCode::VerifyAlreadyInChain,
+            -28 => RpcErrorCode::InWarmup,
+            -32601 => RpcErrorCode::MethodNotFound,
+            other => RpcErrorCode::Other(other),
+        }
+    }
+
+    pub fn code(&self) -> i64 {
+        match self {
+            RpcErrorCode::Misc => -1,
+            RpcErrorCode::Type => -3,
+            RpcErrorCode::Wallet => -4,
+            RpcErrorCode::InvalidAddressOrKey => -5,
+            RpcErrorCode::WalletInsufficientFunds => -6,
+            RpcErrorCode::InvalidParameter => -8,
+            RpcErrorCode::WalletUnlockNeeded => -13,
+            RpcErrorCode::WalletNotFound => -18,
+            RpcErrorCode::WalletNotSpecified => -19,
+            RpcErrorCode::Deserialization => -22,
+            RpcErrorCode::Verify => -25,
+            RpcErrorCode::VerifyRejected => -26,
+            RpcErrorCode::VerifyAlreadyInChain => -27,
+            RpcErrorCode::InWarmup => -28,
+            RpcErrorCode::MethodNotFound => -32601,
+            RpcErrorCode::Other(code) => *code,
+        }
+    }
+}
+
+/// Failure of a Bitcoin Core RPC call
+#[derive(Debug, thiserror::Error)]
+pub enum RpcError {
+    #[error("Bitcoin node unreachable: {0}")]
+    Transport(String),
+    #[error("Bitcoin node did not answer within {0:?}")]
+    Timeout(Duration),
+    #[error("Bitcoin node rejected the RPC credentials")]
+    Unauthorized,
+    #[error("Cannot read RPC cookie file {path}: {reason}")]
+    Cookie { path: String, reason: String },
+    #[error("Bitcoin node HTTP error ({status}): {body}")]
+    Http { status: u16, body: String },
+    #[error("Bitcoin Core error {}: {message}", code.code())]
+    Core { code: RpcErrorCode, message: String },
+    #[error("Invalid Bitcoin RPC response: {0}")]
+    BadResponse(String),
+}
+
+impl RpcError {
+    /// Core error code, if the node answered with an error
+    pub fn code(&self) -> Option<RpcErrorCode> {
+        match self {
+            RpcError::Core { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// Whether the transaction or object asked about does not exist
+    pub fn is_not_found(&self) -> bool {
+        self.code() == Some(RpcErrorCode::InvalidAddressOrKey)
+    }
+
+    /// Whether the node refused a transaction
+    pub fn is_rejection(&self) -> bool {
+        matches!(
+            self.code(),
+            Some(RpcErrorCode::Verify | RpcErrorCode::VerifyRejected | RpcErrorCode::VerifyAlreadyInChain)
+        )
+    }
+
+    /// Whether the same call may succeed if sent again shortly
+    pub fn is_transient(&self) -> bool {
+        match self {
+            // The node rewrites its cookie when it restarts
+            RpcError::Transport(_) | RpcError::Timeout(_) | RpcError::Cookie { .. } => true,
+            // 503: work queue depth exceeded
+            RpcError::Http { status, .. } => matches!(status, 502..=504),
+            RpcError::Core { code, .. } => *code == RpcErrorCode::InWarmup,
+            RpcError::Unauthorized | RpcError::BadResponse(_) => false,
+        }
+    }
+
+    fn from_json(error: &Value) -> Self {
+        RpcError::Core {
+            code: RpcErrorCode::from_code(error.get("code").and_then(Value::as_i64).unwrap_or(-1)),
+            message: error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+        }
+    }
+}
+
+/// How the client authenticates to the node
+#[derive(Debug, Clone)]
+pub enum RpcAuth {
+    UserPass { user: String, password: String },
+    /// `__cookie__:<secret>` file written by the node; read on every call
+    /// since it changes when the node restarts
+    CookieFile(PathBuf),
+}
+
+/// Connection settings of the RPC client
+#[derive(Debug, Clone)]
+pub struct RpcConfig {
+    /// Node URL (`BITCOIN_RPC_URL`, default `http://127.0.0.1:48332`)
+    pub url: String,
+    /// `BITCOIN_RPC_COOKIE_FILE` if set, otherwise `BITCOIN_RPC_USER` and
+    /// `BITCOIN_RPC_PASSWORD` (required outside mock mode)
+    pub auth: RpcAuth,
+    /// Wallet calls are scoped to (`BITCOIN_RPC_WALLET`)
+    pub wallet: Option<String>,
+    /// Per-request timeout (`BITCOIN_RPC_TIMEOUT_SECS`, default 30)
+    pub timeout: Duration,
+    /// Retries of a transiently failing call (`BITCOIN_RPC_MAX_RETRIES`, default 3)
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on each further one
+    /// (`BITCOIN_RPC_RETRY_BASE_MS`, default 250)
+    pub retry_base: Duration,
+}
+
+impl RpcConfig {
+    /// Delay before retry number `attempt` (starting at 1)
+    fn retry_delay(&self, attempt: u32) -> Duration {
+        self.retry_base.saturating_mul(1 << (attempt - 1).min(10))
+    }
+}
+
 /// Bitcoin Core RPC client
+///
+/// Cheap to clone; clones share the connection pool.
+#[derive(Debug, Clone)]
 pub struct BitcoinRpcClient {
-    url: String,
-    user: String,
-    password: String,
+    client: reqwest::Client,
+    endpoint: Url,
+    config: RpcConfig,
 }
 
 /// UTXO from listunspent
@@ -18,81 +202,221 @@ pub struct BitcoinRpcClient {
 pub struct UnspentOutput {
     pub txid: String,
     pub vout: u32,
+    #[serde(default)]
     pub address: String,
+    #[serde(rename = "scriptPubKey")]
     pub script_pub_key: String,
     pub amount: f64,
     pub confirmations: u32,
     pub spendable: bool,
 }
 
+/// Output found by scantxoutset
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScannedOutput {
+    pub txid: String,
+    pub vout: u32,
+    pub amount: f64,
+    pub height: u64,
+}
+
+/// Result of testmempoolaccept for one transaction
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MempoolAcceptResult {
+    pub txid: String,
+    /// Missing when the package was rejected before this transaction was checked
+    #[serde(default)]
+    pub allowed: bool,
+    #[serde(rename = "reject-reason", default)]
+    pub reject_reason: Option<String>,
+}
+
+/// Mempool entry of an unconfirmed transaction
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MempoolEntry {
+    pub vsize: u64,
+    /// Size of the transaction and its unconfirmed ancestors
+    #[serde(rename = "ancestorsize")]
+    pub ancestor_size: u64,
+    pub fees: MempoolFees,
+    #[serde(rename = "bip125-replaceable", default)]
+    pub bip125_replaceable: bool,
+}
+
+/// Fees of a mempool entry, in BTC
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MempoolFees {
+    pub base: f64,
+    /// Fees of the transaction and its unconfirmed ancestors
+    pub ancestor: f64,
+}
+
+impl MempoolEntry {
+    /// Fee paid by the transaction and its unconfirmed ancestors, in sats
+    pub fn ancestor_fee_sats(&self) -> u64 {
+        (self.fees.ancestor * 100_000_000.0).round() as u64
+    }
+}
+
 /// Block info
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BlockchainInfo {
     pub chain: String,
     pub blocks: u64,
     pub headers: u64,
+    #[serde(rename = "bestblockhash")]
     pub best_block_hash: String,
 }
 
 impl BitcoinRpcClient {
-    /// Create a new Bitcoin RPC client with explicit URL
-    pub fn new(url: &str) -> Self {
-        let user = std::env::var("BITCOIN_RPC_USER")
-            .unwrap_or_else(|_| "charms".to_string());
-        let password = std::env::var("BITCOIN_RPC_PASSWORD")
-            .unwrap_or_else(|_| "charms".to_string());
-
-        Self { url: url.to_string(), user, password }
+    /// Create a client; fails only on a malformed URL
+    pub fn new(config: RpcConfig) -> RpcResult<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .map_err(|e| RpcError::Transport(e.to_string()))?;
+        Ok(Self { client, endpoint: endpoint(&config)?, config })
     }
 
-    /// Create a new Bitcoin RPC client from environment
-    pub fn from_env() -> Result<Self> {
-        let url = std::env::var("BITCOIN_RPC_URL")
-            .unwrap_or_else(|_| "http://127.0.0.1:48332".to_string());
-        Ok(Self::new(&url))
+    /// A client for another wallet on the same node, sharing connections
+    pub fn for_wallet(&self, wallet: &str) -> RpcResult<Self> {
+        let config = RpcConfig { wallet: Some(wallet.to_string()), ..self.config.clone() };
+        Ok(Self { client: self.client.clone(), endpoint: endpoint(&config)?, config })
     }
 
     /// Make an RPC call
-    async fn rpc_call<T: for<'de> Deserialize<'de>>(
+    async fn rpc_call<T: for<'de> Deserialize<'de>>(&self, method: &str, params: Value) -> RpcResult<T> {
+        let body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": "liquid-nation",
+            "method": method,
+            "params": params
+        });
+        let result = self.post_with_retries(method, &body, |response| parse_response(&response)).await?;
+        decode(method, result)
+    }
+
+    /// Send several calls in one request
+    ///
+    /// The outer error is for the request as a whole; each call has its own
+    /// result, in the order given.
+    pub async fn batch(&self, calls: &[(&str, Value)]) -> RpcResult<Vec<RpcResult<Value>>> {
+        if calls.is_empty() {
+            return Ok(Vec::new());
+        }
+        let body: Vec<Value> = calls
+            .iter()
+            .enumerate()
+            .map(|(id, (method, params))| {
+                serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+            })
+            .collect();
+        // A node that refuses the whole batch (e.g. while warming up) answers
+        // with a single error object
+        let responses = self
+            .post_with_retries("batch", &Value::Array(body), |response| match response {
+                Value::Array(responses) => Ok(responses),
+                other => Err(parse_response(&other)
+                    .err()
+                    .unwrap_or_else(|| RpcError::BadResponse("batch response is not an array".to_string()))),
+            })
+            .await?;
+        let mut results: Vec<Option<RpcResult<Value>>> = calls.iter().map(|_| None).collect();
+        for response in &responses {
+            let slot = response
+                .get("id")
+                .and_then(Value::as_u64)
+                .and_then(|id| results.get_mut(id as usize))
+                .ok_or_else(|| RpcError::BadResponse(format!("unexpected batch response id: {}", response)))?;
+            *slot = Some(parse_response(response));
+        }
+        Ok(results
+            .into_iter()
+            .enumerate()
+            .map(|(id, result)| {
+                result.unwrap_or_else(|| Err(RpcError::BadResponse(format!("no response to call {} ({})", id, calls[id].0))))
+            })
+            .collect())
+    }
+
+    /// POST a request and parse the response, retrying transient failures
+    async fn post_with_retries<T>(
         &self,
         method: &str,
-        params: serde_json::Value,
-    ) -> Result<T> {
-        let client = reqwest::Client::new();
-        
-        let response = client
-            .post(&self.url)
-            .basic_auth(&self.user, Some(&self.password))
-            .json(&serde_json::json!({
-                "jsonrpc": "2.0",
-                "id": "liquid-nation",
-                "method": method,
-                "params": params
-            }))
+        body: &Value,
+        parse: impl Fn(Value) -> RpcResult<T>,
+    ) -> RpcResult<T> {
+        let mut attempt = 0;
+        loop {
+            match self.post(body).await.and_then(&parse) {
+                Err(e) if e.is_transient() && attempt < self.config.max_retries => {
+                    attempt += 1;
+                    let delay = self.config.retry_delay(attempt);
+                    tracing::warn!("Bitcoin RPC {} failed ({}), retry {} in {:?}", method, e, attempt, delay);
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// POST a request once and return the JSON body
+    ///
+    /// Core answers RPC errors with HTTP 500 (404 for unknown methods) and a
+    /// JSON body, so the body is parsed whatever the status.
+    async fn post(&self, body: &Value) -> RpcResult<Value> {
+        let (user, password) = self.credentials()?;
+        let response = self
+            .client
+            .post(self.endpoint.clone())
+            .basic_auth(user, Some(password))
+            .json(body)
             .send()
-            .await?;
+            .await
+            .map_err(|e| self.transport_error(e))?;
 
-        let result: serde_json::Value = response.json().await?;
-        
-        if let Some(error) = result.get("error") {
-            if !error.is_null() {
-                anyhow::bail!("RPC error: {}", error);
+        let status = response.status();
+        if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+            return Err(RpcError::Unauthorized);
+        }
+        let text = response.text().await.map_err(|e| self.transport_error(e))?;
+        match serde_json::from_str::<Value>(&text) {
+            Ok(value) if !value.is_null() => Ok(value),
+            _ if !status.is_success() => Err(RpcError::Http { status: status.as_u16(), body: text }),
+            _ => Err(RpcError::BadResponse(format!("not JSON: {}", text))),
+        }
+    }
+
+    fn credentials(&self) -> RpcResult<(String, String)> {
+        match &self.config.auth {
+            RpcAuth::UserPass { user, password } => Ok((user.clone(), password.clone())),
+            RpcAuth::CookieFile(path) => {
+                let cookie_error = |reason: String| RpcError::Cookie { path: path.display().to_string(), reason };
+                let cookie = std::fs::read_to_string(path).map_err(|e| cookie_error(e.to_string()))?;
+                let (user, password) = cookie
+                    .trim()
+                    .split_once(':')
+                    .ok_or_else(|| cookie_error("expected user:password".to_string()))?;
+                Ok((user.to_string(), password.to_string()))
             }
         }
+    }
 
-        let result_value = result.get("result")
-            .ok_or_else(|| anyhow::anyhow!("No result in response"))?;
-        
-        Ok(serde_json::from_value(result_value.clone())?)
+    fn transport_error(&self, e: reqwest::Error) -> RpcError {
+        if e.is_timeout() {
+            RpcError::Timeout(self.config.timeout)
+        } else {
+            RpcError::Transport(e.to_string())
+        }
     }
 
     /// Get blockchain info
-    pub async fn get_blockchain_info(&self) -> Result<BlockchainInfo> {
+    pub async fn get_blockchain_info(&self) -> RpcResult<BlockchainInfo> {
         self.rpc_call("getblockchaininfo", serde_json::json!([])).await
     }
 
     /// Get new address
-    pub async fn get_new_address(&self, label: Option<&str>) -> Result<String> {
+    pub async fn get_new_address(&self, label: Option<&str>) -> RpcResult<String> {
         let params = match label {
             Some(l) => serde_json::json!([l]),
             None => serde_json::json!([]),
@@ -105,7 +429,7 @@ impl BitcoinRpcClient {
         &self,
         min_conf: Option<u32>,
         max_conf: Option<u32>,
-    ) -> Result<Vec<UnspentOutput>> {
+    ) -> RpcResult<Vec<UnspentOutput>> {
         let params = serde_json::json!([
             min_conf.unwrap_or(1),
             max_conf.unwrap_or(9999999)
@@ -114,29 +438,291 @@ impl BitcoinRpcClient {
     }
 
     /// Get wallet balance
-    pub async fn get_balance(&self) -> Result<f64> {
+    pub async fn get_balance(&self) -> RpcResult<f64> {
         self.rpc_call("getbalance", serde_json::json!([])).await
     }
 
+    /// Confirmed unspent outputs of an address, found by scanning the UTXO set
+    pub async fn scan_address_utxos(&self, address: &str) -> RpcResult<Vec<ScannedOutput>> {
+        let scan: Value = self
+            .rpc_call("scantxoutset", serde_json::json!(["start", [format!("addr({})", address)]]))
+            .await?;
+        decode("scantxoutset", scan.get("unspents").cloned().unwrap_or_else(|| Value::Array(Vec::new())))
+    }
+
     /// Send raw transaction
-    pub async fn send_raw_transaction(&self, hex: &str) -> Result<String> {
+    pub async fn send_raw_transaction(&self, hex: &str) -> RpcResult<String> {
         self.rpc_call("sendrawtransaction", serde_json::json!([hex])).await
     }
 
+    /// Check whether transactions would be accepted, without broadcasting
+    ///
+    /// Several transactions are checked as a package, children after parents.
+    pub async fn test_mempool_accept(&self, hexes: &[String]) -> RpcResult<Vec<MempoolAcceptResult>> {
+        self.rpc_call("testmempoolaccept", serde_json::json!([hexes])).await
+    }
+
+    /// Estimated fee rates in sat/vB for each confirmation target, in one request
+    ///
+    /// `None` for targets the node does not have enough data for.
+    pub async fn estimate_smart_fees(&self, conf_targets: &[u16]) -> RpcResult<Vec<Option<f64>>> {
+        let calls: Vec<(&str, Value)> = conf_targets
+            .iter()
+            .map(|target| ("estimatesmartfee", serde_json::json!([target])))
+            .collect();
+        self.batch(&calls)
+            .await?
+            .into_iter()
+            .map(|estimate| {
+                // BTC per kvB to sat/vB
+                Ok(estimate?
+                    .get("feerate")
+                    .and_then(Value::as_f64)
+                    .map(|btc_per_kvb| btc_per_kvb * 100_000.0))
+            })
+            .collect()
+    }
+
+    /// Mempool entry of a transaction, or `None` if it is not in the mempool
+    pub async fn get_mempool_entry(&self, txid: &str) -> RpcResult<Option<MempoolEntry>> {
+        match self.rpc_call("getmempoolentry", serde_json::json!([txid])).await {
+            Ok(entry) => Ok(Some(entry)),
+            Err(e) if e.is_not_found() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Get transaction
-    pub async fn get_transaction(&self, txid: &str) -> Result<serde_json::Value> {
+    pub async fn get_transaction(&self, txid: &str) -> RpcResult<Value> {
         self.rpc_call("gettransaction", serde_json::json!([txid])).await
     }
 
     /// Get raw transaction
-    pub async fn get_raw_transaction(&self, txid: &str, verbose: bool) -> Result<serde_json::Value> {
+    pub async fn get_raw_transaction(&self, txid: &str, verbose: bool) -> RpcResult<Value> {
         self.rpc_call("getrawtransaction", serde_json::json!([txid, verbose])).await
     }
+
+    /// Get a transaction and decode it
+    pub async fn get_decoded_transaction(&self, txid: &str) -> RpcResult<bitcoin::Transaction> {
+        let hex: String = self.rpc_call("getrawtransaction", serde_json::json!([txid, false])).await?;
+        bitcoin::consensus::encode::deserialize_hex(&hex)
+            .map_err(|e| RpcError::BadResponse(format!("transaction {} does not decode: {}", txid, e)))
+    }
 }
 
-impl Default for BitcoinRpcClient {
-    fn default() -> Self {
-        Self::from_env().expect("Failed to create Bitcoin RPC client")
+/// Node URL, with the wallet path if calls are scoped to one
+fn endpoint(config: &RpcConfig) -> RpcResult<Url> {
+    let mut url = Url::parse(&config.url)
+        .map_err(|e| RpcError::Transport(format!("invalid RPC URL '{}': {}", config.url, e)))?;
+    if let Some(wallet) = &config.wallet {
+        url.path_segments_mut()
+            .map_err(|_| RpcError::Transport(format!("RPC URL '{}' cannot have a path", config.url)))?
+            .pop_if_empty()
+            .extend(["wallet", wallet.as_str()]);
     }
+    Ok(url)
 }
 
+/// Result or error of one JSON-RPC response object
+fn parse_response(response: &Value) -> RpcResult<Value> {
+    match response.get("error") {
+        Some(error) if !error.is_null() => Err(RpcError::from_json(error)),
+        _ => response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| RpcError::BadResponse(format!("no result in {}", response))),
+    }
+}
+
+fn decode<T: for<'de> Deserialize<'de>>(method: &str, value: Value) -> RpcResult<T> {
+    serde_json::from_value(value).map_err(|e| RpcError::BadResponse(format!("{}: {}", method, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, http::HeaderMap, routing::post, Json, Router};
+    use std::sync::{Arc, Mutex};
+
+    /// Requests seen by the mock node: path, authorization header and body
+    type Seen = Arc<Mutex<Vec<(String, String, Value)>>>;
+
+    /// Answers one call: HTTP status and body
+    type Handler = Arc<dyn Fn(usize, &Value) -> (u16, Value) + Send + Sync>;
+
+    /// Start a mock JSON-RPC node on a free port
+    async fn mock_node(handler: Handler) -> (String, Seen) {
+        let seen: Seen = Arc::default();
+        let state = (seen.clone(), handler);
+        async fn handle(
+            State((seen, handler)): State<(Seen, Handler)>,
+            uri: axum::http::Uri,
+            headers: HeaderMap,
+            Json(body): Json<Value>,
+        ) -> (axum::http::StatusCode, Json<Value>) {
+            let auth = headers
+                .get("authorization")
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            let n = {
+                let mut seen = seen.lock().unwrap();
+                seen.push((uri.path().to_string(), auth, body.clone()));
+                seen.len()
+            };
+            let (status, response) = handler(n, &body);
+            (axum::http::StatusCode::from_u16(status).unwrap(), Json(response))
+        }
+
+        let app = Router::new()
+            .route("/", post(handle))
+            .route("/wallet/:name", post(handle))
+            .with_state(state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (url, seen)
+    }
+
+    fn config(url: &str) -> RpcConfig {
+        RpcConfig {
+            url: url.to_string(),
+            auth: RpcAuth::UserPass { user: "alice".to_string(), password: "secret".to_string() },
+            wallet: None,
+            timeout: Duration::from_secs(5),
+            max_retries: 3,
+            retry_base: Duration::from_millis(1),
+        }
+    }
+
+    fn ok(result: Value) -> (u16, Value) {
+        (200, serde_json::json!({ "result": result, "error": null, "id": "liquid-nation" }))
+    }
+
+    fn core_error(code: i64, message: &str) -> (u16, Value) {
+        (500, serde_json::json!({ "result": null, "error": { "code": code, "message": message }, "id": "liquid-nation" }))
+    }
+
+    #[tokio::test]
+    async fn test_wallet_path_and_auth() {
+        let (url, seen) = mock_node(Arc::new(|_, _| ok(serde_json::json!(1.5)))).await;
+        let client = BitcoinRpcClient::new(RpcConfig { wallet: Some("maker one".to_string()), ..config(&url) }).unwrap();
+        assert_eq!(client.get_balance().await.unwrap(), 1.5);
+
+        let cookie = std::env::temp_dir().join(format!("cookie-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&cookie, "__cookie__:abc123\n").unwrap();
+        let client = BitcoinRpcClient::new(RpcConfig { auth: RpcAuth::CookieFile(cookie.clone()), ..config(&url) }).unwrap();
+        client.get_balance().await.unwrap();
+        client.for_wallet("taker").unwrap().get_balance().await.unwrap();
+        std::fs::remove_file(cookie).ok();
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].0, "/wallet/maker%20one");
+        assert_eq!(seen[0].1, "Basic YWxpY2U6c2VjcmV0");
+        assert_eq!(seen[0].2["method"], "getbalance");
+        assert_eq!(seen[1].0, "/");
+        assert_eq!(seen[1].1, "Basic X19jb29raWVfXzphYmMxMjM=");
+        assert_eq!(seen[2].0, "/wallet/taker");
+    }
+
+    #[tokio::test]
+    async fn test_core_errors_are_typed() {
+        let (url, seen) = mock_node(Arc::new(|_, body| match body["method"].as_str() {
+            Some("getmempoolentry") => core_error(-5, "Transaction not in mempool"),
+            _ => core_error(-26, "min relay fee not met"),
+        }))
+        .await;
+        let client = BitcoinRpcClient::new(config(&url)).unwrap();
+
+        assert!(client.get_mempool_entry(&"00".repeat(32)).await.unwrap().is_none());
+        let err = client.send_raw_transaction("00").await.unwrap_err();
+        assert_eq!(err.code(), Some(RpcErrorCode::VerifyRejected));
+        assert!(err.is_rejection() && !err.is_transient());
+        assert_eq!(err.to_string(), "Bitcoin Core error -26: min relay fee not met");
+        // Rejections are not retried
+        assert_eq!(seen.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_transient_failures_are_retried() {
+        let (url, seen) = mock_node(Arc::new(|n, _| match n {
+            1 => (503, Value::Null),
+            2 => core_error(-28, "Loading block index..."),
+            _ => ok(serde_json::json!("bcrt1qaddress")),
+        }))
+        .await;
+        let client = BitcoinRpcClient::new(config(&url)).unwrap();
+        assert_eq!(client.get_new_address(None).await.unwrap(), "bcrt1qaddress");
+        assert_eq!(seen.lock().unwrap().len(), 3);
+
+        // Retries run out
+        let client = BitcoinRpcClient::new(RpcConfig { max_retries: 1, ..config(&url) }).unwrap();
+        seen.lock().unwrap().clear();
+        let err = client.get_new_address(None).await.unwrap_err();
+        assert_eq!(err.code(), Some(RpcErrorCode::InWarmup));
+        assert_eq!(seen.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_and_unreachable_node() {
+        // Accepts connections and never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let silent = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+        let client = BitcoinRpcClient::new(RpcConfig {
+            timeout: Duration::from_millis(50),
+            max_retries: 0,
+            ..config(&silent)
+        })
+        .unwrap();
+        assert!(matches!(client.get_balance().await, Err(RpcError::Timeout(_))));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let client = BitcoinRpcClient::new(RpcConfig { max_retries: 0, ..config(&closed) }).unwrap();
+        assert!(matches!(client.get_balance().await, Err(RpcError::Transport(_))));
+    }
+
+    #[tokio::test]
+    async fn test_batch_matches_responses_by_id() {
+        let (url, seen) = mock_node(Arc::new(|_, body| {
+            // Answer in reverse order, with an error for the last call
+            let responses: Vec<Value> = body
+                .as_array()
+                .unwrap()
+                .iter()
+                .rev()
+                .map(|call| match call["params"][0].as_u64() {
+                    Some(144) => serde_json::json!({ "result": null, "error": { "code": -8, "message": "Invalid conf_target" }, "id": call["id"] }),
+                    Some(2) => serde_json::json!({ "result": { "feerate": 0.0002, "blocks": 2 }, "error": null, "id": call["id"] }),
+                    _ => serde_json::json!({ "result": { "errors": ["Insufficient data"], "blocks": 0 }, "error": null, "id": call["id"] }),
+                })
+                .collect();
+            (200, Value::Array(responses))
+        }))
+        .await;
+        let client = BitcoinRpcClient::new(config(&url)).unwrap();
+
+        let results = client
+            .batch(&[
+                ("estimatesmartfee", serde_json::json!([2])),
+                ("estimatesmartfee", serde_json::json!([6])),
+                ("estimatesmartfee", serde_json::json!([144])),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(results[0].as_ref().unwrap()["feerate"], 0.0002);
+        assert!(results[1].as_ref().unwrap().get("feerate").is_none());
+        assert_eq!(results[2].as_ref().unwrap_err().code(), Some(RpcErrorCode::InvalidParameter));
+
+        let rates = client.estimate_smart_fees(&[2, 6]).await.unwrap();
+        assert_eq!(rates, vec![Some(20.0), None]);
+        assert_eq!(seen.lock().unwrap().len(), 2);
+    }
+}
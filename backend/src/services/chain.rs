@@ -0,0 +1,175 @@
+//! Chain data backends
+//!
+//! Routes read chain data and broadcast through [`ChainBackend`], so the
+//! backend can run against Bitcoin Core or an Esplora/electrs HTTP API.
+//! `CHAIN_BACKEND` selects one (`rpc`, the default, or `esplora`). Features
+//! that need a node wallet or its mempool (wallet endpoints, fee bumping)
+//! still talk to Bitcoin Core directly.
+
+use async_trait::async_trait;
+use bitcoin::Transaction;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::bitcoin::{BitcoinService, MempoolAcceptResult, RpcError};
+use super::esplora::EsploraClient;
+
+/// Failure of a chain backend request
+#[derive(Debug, thiserror::Error)]
+pub enum ChainError {
+    #[error("Chain backend unavailable: {0}")]
+    Unavailable(String),
+    #[error("Transaction rejected: {0}")]
+    Rejected(String),
+    #[error("Invalid chain backend response: {0}")]
+    BadResponse(String),
+}
+
+impl From<RpcError> for ChainError {
+    fn from(e: RpcError) -> Self {
+        match e {
+            e if e.is_rejection() => ChainError::Rejected(e.to_string()),
+            RpcError::BadResponse(msg) => ChainError::BadResponse(msg),
+            e => ChainError::Unavailable(e.to_string()),
+        }
+    }
+}
+
+/// Result of a chain backend request
+pub type ChainResult<T> = Result<T, ChainError>;
+
+/// Confirmation status of a transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TxStatus {
+    pub confirmed: bool,
+    pub block_height: Option<u64>,
+    pub block_hash: Option<String>,
+}
+
+/// Unspent output of an address
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AddressUtxo {
+    pub txid: String,
+    pub vout: u32,
+    /// Value in sats
+    pub value: u64,
+    /// Height of the block that confirmed it; `None` while unconfirmed
+    pub height: Option<u64>,
+}
+
+/// Source of chain data and sink for transactions
+#[async_trait]
+pub trait ChainBackend: Send + Sync {
+    /// Short name for logs and health checks
+    fn name(&self) -> &'static str;
+
+    /// Height of the best block
+    async fn tip_height(&self) -> ChainResult<u64>;
+
+    /// A transaction, or `None` if the backend does not know it
+    async fn get_transaction(&self, txid: &str) -> ChainResult<Option<Transaction>>;
+
+    /// Confirmation status of a transaction, or `None` if it is unknown
+    async fn tx_status(&self, txid: &str) -> ChainResult<Option<TxStatus>>;
+
+    /// Unspent outputs paying to an address
+    async fn address_utxos(&self, address: &str) -> ChainResult<Vec<AddressUtxo>>;
+
+    /// Broadcast a raw transaction, returning its txid
+    async fn broadcast(&self, tx_hex: &str) -> ChainResult<String>;
+
+    /// Fee rates in sat/vB for each confirmation target; `None` where the
+    /// backend has no estimate
+    async fn fee_estimates(&self, conf_targets: &[u16]) -> ChainResult<Vec<Option<f64>>>;
+
+    /// Check a package against the mempool without broadcasting
+    ///
+    /// `None` when the backend cannot do this check.
+    async fn test_mempool_accept(&self, _hexes: &[String]) -> ChainResult<Option<Vec<MempoolAcceptResult>>> {
+        Ok(None)
+    }
+}
+
+/// Which backend serves chain data (`CHAIN_BACKEND`, default `rpc`)
+#[derive(Debug, Clone)]
+pub enum ChainBackendConfig {
+    /// The Bitcoin Core node the wallet calls go to
+    Rpc,
+    /// An Esplora API (`ESPLORA_URL`, `ESPLORA_TIMEOUT_SECS` default 30)
+    Esplora { url: String, timeout: Duration },
+}
+
+/// The configured backend
+pub fn from_config(config: &ChainBackendConfig, bitcoin: &BitcoinService) -> anyhow::Result<Arc<dyn ChainBackend>> {
+    match config {
+        ChainBackendConfig::Rpc => Ok(Arc::new(bitcoin.clone())),
+        ChainBackendConfig::Esplora { url, timeout } => Ok(Arc::new(EsploraClient::new(url, *timeout)?)),
+    }
+}
+
+#[async_trait]
+impl ChainBackend for BitcoinService {
+    fn name(&self) -> &'static str {
+        "rpc"
+    }
+
+    async fn tip_height(&self) -> ChainResult<u64> {
+        Ok(self.get_blockchain_info().await?.blocks)
+    }
+
+    async fn get_transaction(&self, txid: &str) -> ChainResult<Option<Transaction>> {
+        match self.get_decoded_transaction(txid).await {
+            Ok(tx) => Ok(Some(tx)),
+            Err(e) if e.is_not_found() => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn tx_status(&self, txid: &str) -> ChainResult<Option<TxStatus>> {
+        let tx = match self.get_raw_transaction(txid, true).await {
+            Ok(tx) => tx,
+            Err(e) if e.is_not_found() => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let confirmations = tx.get("confirmations").and_then(|c| c.as_u64()).unwrap_or(0);
+        let block_height = if confirmations > 0 {
+            Some(self.tip_height().await? + 1 - confirmations)
+        } else {
+            None
+        };
+        Ok(Some(TxStatus {
+            confirmed: confirmations > 0,
+            block_height,
+            block_hash: tx.get("blockhash").and_then(|h| h.as_str()).map(str::to_string),
+        }))
+    }
+
+    async fn address_utxos(&self, address: &str) -> ChainResult<Vec<AddressUtxo>> {
+        // Scans the UTXO set, so it works without a wallet but only sees
+        // confirmed outputs
+        Ok(self
+            .scan_address_utxos(address)
+            .await?
+            .into_iter()
+            .map(|utxo| AddressUtxo {
+                txid: utxo.txid,
+                vout: utxo.vout,
+                value: (utxo.amount * 100_000_000.0).round() as u64,
+                height: Some(utxo.height),
+            })
+            .collect())
+    }
+
+    async fn broadcast(&self, tx_hex: &str) -> ChainResult<String> {
+        Ok(self.send_raw_transaction(tx_hex).await?)
+    }
+
+    async fn fee_estimates(&self, conf_targets: &[u16]) -> ChainResult<Vec<Option<f64>>> {
+        Ok(self.estimate_smart_fees(conf_targets).await?)
+    }
+
+    async fn test_mempool_accept(&self, hexes: &[String]) -> ChainResult<Option<Vec<MempoolAcceptResult>>> {
+        Ok(Some(BitcoinService::test_mempool_accept(self, hexes).await?))
+    }
+}
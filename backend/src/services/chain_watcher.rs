@@ -0,0 +1,328 @@
+//! New-block and new-transaction notifications
+//!
+//! The watcher subscribes to bitcoind's `zmqpubhashblock` and `zmqpubrawtx`
+//! feeds and republishes them as [`ChainEvent`]s on a broadcast channel, so
+//! anything reacting to the chain hears about a block within seconds instead
+//! of polling. The tip is still polled: every `CHAIN_POLL_SECS` when ZMQ is
+//! not configured, unreachable or has dropped, and once a minute as a safety
+//! net while it is up. Lost ZMQ connections are retried in the background.
+
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use zeromq::{Socket, SocketRecv, SubSocket, ZmqMessage};
+
+use super::chain::ChainBackend;
+
+/// Tip poll interval while ZMQ notifications arrive
+const ZMQ_SAFETY_POLL: Duration = Duration::from_secs(60);
+
+/// Events buffered per subscriber before the slowest one starts missing them
+const EVENT_BUFFER: usize = 256;
+
+/// Something happened on chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChainEvent {
+    /// The tip moved; `hash` is known when the block came in over ZMQ
+    NewBlock { height: u64, hash: Option<String> },
+    /// A transaction entered the mempool or a block
+    NewTransaction { txid: String },
+}
+
+/// Where notifications come from
+#[derive(Debug, Clone)]
+pub struct ChainWatcherConfig {
+    /// bitcoind `zmqpubhashblock` endpoint (`BITCOIN_ZMQ_HASHBLOCK`, e.g. `tcp://127.0.0.1:28332`)
+    pub hashblock_endpoint: Option<String>,
+    /// bitcoind `zmqpubrawtx` endpoint (`BITCOIN_ZMQ_RAWTX`)
+    pub rawtx_endpoint: Option<String>,
+    /// Tip poll interval without ZMQ (`CHAIN_POLL_SECS`, default 10)
+    pub poll_interval: Duration,
+    /// Wait before reconnecting a failed ZMQ feed (`BITCOIN_ZMQ_RECONNECT_SECS`, default 30)
+    pub reconnect_delay: Duration,
+}
+
+impl ChainWatcherConfig {
+    fn zmq_enabled(&self) -> bool {
+        self.hashblock_endpoint.is_some() || self.rawtx_endpoint.is_some()
+    }
+}
+
+/// Publishes chain events to any number of subscribers
+pub struct ChainWatcher {
+    chain: Arc<dyn ChainBackend>,
+    config: ChainWatcherConfig,
+    events: broadcast::Sender<ChainEvent>,
+}
+
+/// What woke the watcher up
+enum Wakeup {
+    Block(String),
+    Transaction(String),
+    Poll,
+    ZmqFailed(String),
+}
+
+impl ChainWatcher {
+    pub fn new(chain: Arc<dyn ChainBackend>, config: ChainWatcherConfig) -> Self {
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        Self { chain, config, events }
+    }
+
+    /// Receive every event published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainEvent> {
+        self.events.subscribe()
+    }
+
+    /// Watch the chain until `shutdown` is cancelled
+    pub async fn run(self: Arc<Self>, shutdown: CancellationToken) {
+        let mut tip = self.chain.tip_height().await.ok();
+        let mut feed: Option<SubSocket> = None;
+        let mut next_connect = Instant::now();
+        let mut next_poll = Instant::now();
+
+        loop {
+            if feed.is_none() && self.config.zmq_enabled() && Instant::now() >= next_connect {
+                // zeromq keeps retrying refused connections, so give up
+                // before the attempt holds up the next poll
+                let connected = tokio::time::timeout(self.config.poll_interval, self.connect())
+                    .await
+                    .unwrap_or_else(|_| Err(zeromq::ZmqError::Other("connection timed out")));
+                match connected {
+                    Ok(socket) => {
+                        tracing::info!("Receiving chain notifications over ZMQ");
+                        feed = Some(socket);
+                        // Blocks may have arrived while disconnected
+                        self.check_tip(&mut tip, None).await;
+                    }
+                    Err(e) => {
+                        tracing::warn!("ZMQ unavailable ({}), polling every {:?}", e, self.config.poll_interval);
+                        next_connect = Instant::now() + self.config.reconnect_delay;
+                    }
+                }
+            }
+
+            let wakeup = tokio::select! {
+                _ = shutdown.cancelled() => return,
+                wakeup = next_notification(&mut feed) => wakeup,
+                _ = tokio::time::sleep_until(next_poll) => Wakeup::Poll,
+            };
+
+            match wakeup {
+                Wakeup::Block(hash) => self.check_tip(&mut tip, Some(hash)).await,
+                Wakeup::Transaction(txid) => self.publish(ChainEvent::NewTransaction { txid }),
+                Wakeup::Poll => {
+                    let interval = if feed.is_some() { ZMQ_SAFETY_POLL } else { self.config.poll_interval };
+                    next_poll = Instant::now() + interval;
+                    self.check_tip(&mut tip, None).await;
+                }
+                Wakeup::ZmqFailed(e) => {
+                    tracing::warn!("ZMQ feed failed ({}), falling back to polling", e);
+                    feed = None;
+                    next_connect = Instant::now() + self.config.reconnect_delay;
+                    next_poll = Instant::now();
+                }
+            }
+        }
+    }
+
+    /// Subscribe to the configured feeds on one socket
+    async fn connect(&self) -> zeromq::ZmqResult<SubSocket> {
+        let mut socket = SubSocket::new();
+        let endpoints = [&self.config.hashblock_endpoint, &self.config.rawtx_endpoint];
+        let mut connected: Vec<&str> = Vec::new();
+        for endpoint in endpoints.into_iter().flatten() {
+            // Both feeds are usually published on the same port
+            if !connected.contains(&endpoint.as_str()) {
+                socket.connect(endpoint).await?;
+                connected.push(endpoint);
+            }
+        }
+        if self.config.hashblock_endpoint.is_some() {
+            socket.subscribe("hashblock").await?;
+        }
+        if self.config.rawtx_endpoint.is_some() {
+            socket.subscribe("rawtx").await?;
+        }
+        Ok(socket)
+    }
+
+    /// Publish a block event if the tip moved
+    ///
+    /// A block announced over ZMQ is always published, since it may replace
+    /// the tip at the same height.
+    async fn check_tip(&self, tip: &mut Option<u64>, hash: Option<String>) {
+        let height = match self.chain.tip_height().await {
+            Ok(height) => height,
+            Err(e) => {
+                tracing::warn!("Failed to read the chain tip: {}", e);
+                return;
+            }
+        };
+        if *tip != Some(height) || hash.is_some() {
+            *tip = Some(height);
+            self.publish(ChainEvent::NewBlock { height, hash });
+        }
+    }
+
+    fn publish(&self, event: ChainEvent) {
+        tracing::debug!("Chain event: {:?}", event);
+        // Fails only when nobody is listening
+        let _ = self.events.send(event);
+    }
+}
+
+/// Next notification from the feed; never resolves without one
+async fn next_notification(feed: &mut Option<SubSocket>) -> Wakeup {
+    let Some(socket) = feed else {
+        return std::future::pending().await;
+    };
+    loop {
+        match socket.recv().await {
+            Ok(message) => {
+                if let Some(wakeup) = parse_notification(&message) {
+                    return wakeup;
+                }
+            }
+            Err(e) => return Wakeup::ZmqFailed(e.to_string()),
+        }
+    }
+}
+
+/// Decode a bitcoind notification: topic, body and a sequence number
+fn parse_notification(message: &ZmqMessage) -> Option<Wakeup> {
+    let topic = message.get(0)?;
+    let body = message.get(1)?;
+    match topic.as_ref() {
+        // bitcoind sends the hash in display byte order
+        b"hashblock" if body.len() == 32 => Some(Wakeup::Block(hex::encode(body))),
+        b"rawtx" => {
+            let tx: bitcoin::Transaction = bitcoin::consensus::deserialize(body).ok()?;
+            Some(Wakeup::Transaction(tx.compute_txid().to_string()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::chain::{AddressUtxo, ChainResult, TxStatus};
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use zeromq::{PubSocket, SocketSend};
+
+    /// Chain whose tip the test moves
+    struct FakeChain(AtomicU64);
+
+    #[async_trait]
+    impl ChainBackend for FakeChain {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+        async fn tip_height(&self) -> ChainResult<u64> {
+            Ok(self.0.load(Ordering::SeqCst))
+        }
+        async fn get_transaction(&self, _: &str) -> ChainResult<Option<bitcoin::Transaction>> {
+            Ok(None)
+        }
+        async fn tx_status(&self, _: &str) -> ChainResult<Option<TxStatus>> {
+            Ok(None)
+        }
+        async fn address_utxos(&self, _: &str) -> ChainResult<Vec<AddressUtxo>> {
+            Ok(Vec::new())
+        }
+        async fn broadcast(&self, _: &str) -> ChainResult<String> {
+            Ok(String::new())
+        }
+        async fn fee_estimates(&self, targets: &[u16]) -> ChainResult<Vec<Option<f64>>> {
+            Ok(vec![None; targets.len()])
+        }
+    }
+
+    async fn next_event(events: &mut broadcast::Receiver<ChainEvent>) -> ChainEvent {
+        tokio::time::timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_polls_when_zmq_is_down() {
+        // Nothing listens on the ZMQ endpoint
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("tcp://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let chain = Arc::new(FakeChain(AtomicU64::new(100)));
+        let watcher = Arc::new(ChainWatcher::new(
+            chain.clone(),
+            ChainWatcherConfig {
+                hashblock_endpoint: Some(endpoint),
+                rawtx_endpoint: None,
+                poll_interval: Duration::from_millis(20),
+                reconnect_delay: Duration::from_secs(60),
+            },
+        ));
+        let mut events = watcher.subscribe();
+        tokio::spawn(watcher.clone().run(CancellationToken::new()));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        chain.0.store(101, Ordering::SeqCst);
+        assert_eq!(next_event(&mut events).await, ChainEvent::NewBlock { height: 101, hash: None });
+    }
+
+    #[tokio::test]
+    async fn test_zmq_notifications() {
+        let mut publisher = PubSocket::new();
+        let endpoint = publisher.bind("tcp://127.0.0.1:0").await.unwrap().to_string();
+
+        let chain = Arc::new(FakeChain(AtomicU64::new(200)));
+        let watcher = Arc::new(ChainWatcher::new(
+            chain.clone(),
+            ChainWatcherConfig {
+                hashblock_endpoint: Some(endpoint.clone()),
+                rawtx_endpoint: Some(endpoint),
+                poll_interval: Duration::from_secs(60),
+                reconnect_delay: Duration::from_secs(60),
+            },
+        ));
+        let mut events = watcher.subscribe();
+        tokio::spawn(watcher.clone().run(CancellationToken::new()));
+
+        let tx = bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn::default()],
+            output: vec![],
+        };
+        let raw_tx: Vec<u8> = bitcoin::consensus::serialize(&tx);
+        let notification = |topic: &'static str, body: Vec<u8>| {
+            let mut message = ZmqMessage::from(topic);
+            message.push_back(body.into());
+            message.push_back(0u32.to_le_bytes().to_vec().into());
+            message
+        };
+
+        // Subscriptions take a moment to reach the publisher
+        chain.0.store(201, Ordering::SeqCst);
+        let event = loop {
+            publisher.send(notification("hashblock", vec![0xab; 32])).await.unwrap();
+            match tokio::time::timeout(Duration::from_millis(100), events.recv()).await {
+                Ok(event) => break event.unwrap(),
+                Err(_) => continue,
+            }
+        };
+        assert_eq!(event, ChainEvent::NewBlock { height: 201, hash: Some("ab".repeat(32)) });
+
+        publisher.send(notification("rawtx", raw_tx)).await.unwrap();
+        let txid = loop {
+            // Skip the blocks announced while waiting for the subscription
+            if let ChainEvent::NewTransaction { txid } = next_event(&mut events).await {
+                break txid;
+            }
+        };
+        assert_eq!(txid, tx.compute_txid().to_string());
+    }
+}
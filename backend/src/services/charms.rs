@@ -3,10 +3,12 @@
 //! Handles spell building, proving, and transaction management
 
 use anyhow::Result;
-use serde::{Deserialize, Serialize, Serializer};
+use serde::{Deserialize, Serialize};
 use serde_yaml;
 use std::collections::BTreeMap;
 
+use super::spell_validator::{self, SpellReport};
+
 /// Charms prover service
 pub struct CharmsService {
     api_url: String,
@@ -14,9 +16,9 @@ pub struct CharmsService {
 }
 
 /// Spell prove request - sent to Charms Prover API
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SpellProveRequest {
-    #[serde(serialize_with = "serialize_spell")]
+    #[serde(serialize_with = "serialize_spell", deserialize_with = "deserialize_spell")]
     pub spell: String, // YAML string that will be parsed to JSON object
     pub binaries: BTreeMap<String, Vec<u8>>,
     pub prev_txs: Vec<String>,
@@ -40,6 +42,42 @@ where
     yaml_value.serialize(serializer)
 }
 
+/// Inverse of `serialize_spell`: accepts a spell object (or a YAML string)
+/// and turns it back into YAML, so stored requests can be replayed
+fn deserialize_spell<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match serde_yaml::Value::deserialize(deserializer)? {
+        serde_yaml::Value::String(yaml) => Ok(yaml),
+        value => serde_yaml::to_string(&value).map_err(serde::de::Error::custom),
+    }
+}
+
+/// Errors from proving a spell
+#[derive(Debug, thiserror::Error)]
+pub enum ProverError {
+    #[error("Spell failed validation: {0}")]
+    InvalidSpell(String),
+    #[error("Prover API unreachable: {0}")]
+    Unavailable(String),
+    #[error("Prover API error ({status}): {body}")]
+    Api { status: u16, body: String },
+    #[error("Invalid prover response: {0}")]
+    BadResponse(String),
+}
+
+impl ProverError {
+    /// Whether the same request may succeed if sent again later
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ProverError::Unavailable(_) => true,
+            ProverError::Api { status, .. } => *status == 429 || *status >= 500,
+            ProverError::InvalidSpell(_) | ProverError::BadResponse(_) => false,
+        }
+    }
+}
+
 /// Transaction from prove response
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProvedTransaction {
@@ -56,9 +94,11 @@ pub struct OrderSpellData {
     pub offer_token_vk: String,
     pub offer_amount: String,
     pub want_token_id: String,
+    pub want_token_vk: String,
     pub want_amount: String,
     pub expiry_height: u64,
     pub allow_partial: bool,
+    pub min_fill_amount: String,
     pub funding_utxo: String,
     pub escrow_address: String,
     pub dest_chain: u8,
@@ -78,17 +118,112 @@ pub struct FillSpellData {
     pub fill_amount: Option<String>,
 }
 
-impl CharmsService {
-    /// Create a new Charms service
-    pub fn new() -> Self {
-        let api_url = std::env::var("CHARMS_PROVE_API_URL")
-            .unwrap_or_else(|_| "https://v8.charms.dev/spells/prove".to_string());
-        
-        let mock_mode = std::env::var("MOCK_MODE")
-            .map(|v| v == "true")
-            .unwrap_or(true);
+/// Cancel order data for spell building
+#[derive(Debug, Clone)]
+pub struct CancelSpellData {
+    pub order_utxo: String,
+    pub maker_address: String,
+    pub filled_amount: u64,
+    pub remaining_amount: u64,
+}
+
+/// Expire order data for spell building
+#[derive(Debug, Clone)]
+pub struct ExpireSpellData {
+    pub order_utxo: String,
+    pub maker_address: String,
+    pub filled_amount: u64,
+    pub remaining_amount: u64,
+    /// Tip height, which must be past the order's expiry
+    pub current_height: u64,
+}
+
+/// Partial fill data for spell building
+#[derive(Debug, Clone)]
+pub struct PartialFillSpellData {
+    pub order_utxo: String,
+    pub taker_utxo: String,
+    pub taker_pubkey: String,
+    pub taker_address: String,
+    pub maker_address: String,
+    pub escrow_address: String,
+    pub fill_amount: u64,
+    pub fill_want_amount: u64,
+    pub current_filled: u64,
+    pub current_remaining: u64,
+}
+
+/// One order of a batch fill
+#[derive(Debug, Clone)]
+pub struct BatchFillOrder {
+    pub order_utxo: String,
+    pub order_data: OrderSpellData,
+    pub fill_amount: u64,
+    pub fill_want_amount: u64,
+    pub current_filled: u64,
+    pub current_remaining: u64,
+}
 
-        Self { api_url, mock_mode }
+/// Batch fill data for spell building
+#[derive(Debug, Clone)]
+pub struct BatchFillSpellData {
+    pub taker_utxo: String,
+    pub taker_pubkey: String,
+    pub taker_address: String,
+    pub orders: Vec<BatchFillOrder>,
+}
+
+/// Input of one order in a batch-fill spell
+const BATCH_ORDER_INPUT: &str = "  - utxo_id: ${order_utxo}
+    charms:
+      $ORDER:
+        maker_pubkey: ${maker_pubkey}
+        offer_app_id: ${offer_token_id}
+        offer_amount: ${offer_amount}
+        want_app_id: ${want_token_id}
+        want_amount: ${want_amount}
+        dest_chain: ${dest_chain}
+        dest_address: ${dest_address}
+        expiry_height: ${expiry_height}
+        allow_partial: ${allow_partial}
+        min_fill_amount: ${min_fill_amount}
+        status: ${current_status}
+        filled_amount: ${current_filled}
+        created_at: ${created_at}
+      $OFFER: ${current_remaining}
+";
+
+/// Order left in escrow after a partial fill in a batch-fill spell
+const BATCH_ORDER_REMAINDER: &str = "  - address: ${addr_escrow}
+    charms:
+      $ORDER:
+        maker_pubkey: ${maker_pubkey}
+        offer_app_id: ${offer_token_id}
+        offer_amount: ${offer_amount}
+        want_app_id: ${want_token_id}
+        want_amount: ${want_amount}
+        dest_chain: ${dest_chain}
+        dest_address: ${dest_address}
+        expiry_height: ${expiry_height}
+        allow_partial: ${allow_partial}
+        min_fill_amount: ${min_fill_amount}
+        status: ${current_status}
+        filled_amount: ${new_filled}
+        created_at: ${created_at}
+      $OFFER: ${new_remaining}
+";
+
+/// Maker's payment in a batch-fill spell
+const BATCH_MAKER_PAYMENT: &str = "  - address: ${addr_maker}
+    charms:
+      $WANT: ${fill_want_amount}
+";
+
+impl CharmsService {
+    /// Create a Charms service proving through `api_url`, or faking proofs
+    /// in mock mode
+    pub fn new(api_url: &str, mock_mode: bool) -> Self {
+        Self { api_url: api_url.to_string(), mock_mode }
     }
 
     /// Build a spell from template with variable substitution
@@ -131,6 +266,7 @@ impl CharmsService {
         vars.insert("want_amount".to_string(), data.want_amount.clone());
         vars.insert("expiry_height".to_string(), data.expiry_height.to_string());
         vars.insert("allow_partial".to_string(), data.allow_partial.to_string());
+        vars.insert("min_fill_amount".to_string(), data.min_fill_amount.clone());
         
         // UTXOs and addresses
         vars.insert("in_utxo_0".to_string(), data.funding_utxo.clone());
@@ -141,7 +277,6 @@ impl CharmsService {
         vars.insert("dest_address".to_string(), data.dest_address.clone());
         
         // Defaults
-        vars.insert("min_fill_amount".to_string(), "0".to_string());
         vars.insert("current_height".to_string(), "0".to_string());
 
         self.build_spell(template, &vars)
@@ -164,7 +299,7 @@ impl CharmsService {
         vars.insert("offer_token_id".to_string(), order_data.offer_token_id.clone());
         vars.insert("offer_token_vk".to_string(), order_data.offer_token_vk.clone());
         vars.insert("want_token_id".to_string(), order_data.want_token_id.clone());
-        vars.insert("want_token_vk".to_string(), order_data.offer_token_vk.clone()); // Assuming same VK
+        vars.insert("want_token_vk".to_string(), order_data.want_token_vk.clone());
         
         // Order state
         vars.insert("order_utxo".to_string(), data.order_utxo.clone());
@@ -185,17 +320,155 @@ impl CharmsService {
         vars.insert("dest_address".to_string(), order_data.dest_address.clone());
         vars.insert("expiry_height".to_string(), order_data.expiry_height.to_string());
         vars.insert("allow_partial".to_string(), order_data.allow_partial.to_string());
-        vars.insert("min_fill_amount".to_string(), "0".to_string());
+        vars.insert("min_fill_amount".to_string(), order_data.min_fill_amount.clone());
         vars.insert("created_at".to_string(), "0".to_string());
 
         self.build_spell(template, &vars)
     }
 
+    /// Build cancel-order spell
+    pub fn build_cancel_order_spell(
+        &self,
+        template: &str,
+        data: &CancelSpellData,
+        order_data: &OrderSpellData,
+        app_id: &str,
+        app_vk: &str,
+    ) -> Result<String> {
+        let mut vars = order_state_vars(order_data, app_id, app_vk);
+
+        vars.insert("order_utxo".to_string(), data.order_utxo.clone());
+        vars.insert("addr_maker".to_string(), data.maker_address.clone());
+        vars.insert("filled_amount".to_string(), data.filled_amount.to_string());
+        vars.insert("remaining_amount".to_string(), data.remaining_amount.to_string());
+        // Authorization comes from the maker signing the order input
+        vars.insert("maker_signature".to_string(), "\"\"".to_string());
+
+        self.build_spell(template, &vars)
+    }
+
+    /// Build expire-order spell
+    pub fn build_expire_order_spell(
+        &self,
+        template: &str,
+        data: &ExpireSpellData,
+        order_data: &OrderSpellData,
+        app_id: &str,
+        app_vk: &str,
+    ) -> Result<String> {
+        let mut vars = order_state_vars(order_data, app_id, app_vk);
+
+        vars.insert("order_utxo".to_string(), data.order_utxo.clone());
+        vars.insert("addr_maker".to_string(), data.maker_address.clone());
+        vars.insert("filled_amount".to_string(), data.filled_amount.to_string());
+        vars.insert("remaining_amount".to_string(), data.remaining_amount.to_string());
+        vars.insert("current_height".to_string(), data.current_height.to_string());
+
+        self.build_spell(template, &vars)
+    }
+
+    /// Build partial-fill spell
+    pub fn build_partial_fill_spell(
+        &self,
+        template: &str,
+        data: &PartialFillSpellData,
+        order_data: &OrderSpellData,
+        app_id: &str,
+        app_vk: &str,
+    ) -> Result<String> {
+        let mut vars = order_state_vars(order_data, app_id, app_vk);
+        let new_filled = data.current_filled + data.fill_amount;
+        let new_remaining = data.current_remaining - data.fill_amount;
+        // Status codes of the swap contract: Open = 0, Filled = 1
+        let new_status = if new_remaining == 0 { 1 } else { 0 };
+
+        vars.insert("order_utxo".to_string(), data.order_utxo.clone());
+        vars.insert("taker_utxo".to_string(), data.taker_utxo.clone());
+        vars.insert("taker_pubkey".to_string(), data.taker_pubkey.clone());
+        vars.insert("addr_escrow".to_string(), data.escrow_address.clone());
+        vars.insert("addr_maker".to_string(), data.maker_address.clone());
+        vars.insert("addr_taker".to_string(), data.taker_address.clone());
+        vars.insert("fill_amount".to_string(), data.fill_amount.to_string());
+        vars.insert("fill_want_amount".to_string(), data.fill_want_amount.to_string());
+        vars.insert("current_filled".to_string(), data.current_filled.to_string());
+        vars.insert("current_remaining".to_string(), data.current_remaining.to_string());
+        vars.insert("new_filled".to_string(), new_filled.to_string());
+        vars.insert("new_remaining".to_string(), new_remaining.to_string());
+        vars.insert("new_status".to_string(), new_status.to_string());
+
+        self.build_spell(template, &vars)
+    }
+
+    /// Build batch-fill spell
+    ///
+    /// Every order contributes an input, a payment to its maker and, when it
+    /// is only filled in part, its remainder back in escrow.
+    pub fn build_batch_fill_spell(
+        &self,
+        template: &str,
+        data: &BatchFillSpellData,
+        app_id: &str,
+        app_vk: &str,
+    ) -> Result<String> {
+        let Some(first) = data.orders.first() else {
+            anyhow::bail!("A batch fill needs at least one order");
+        };
+
+        let mut order_inputs = String::new();
+        let mut order_outputs = String::new();
+        for order in &data.orders {
+            let Some(new_remaining) = order.current_remaining.checked_sub(order.fill_amount) else {
+                anyhow::bail!("Fill of {} exceeds the remaining {}", order.fill_amount, order.current_remaining);
+            };
+            let mut vars = order_state_vars(&order.order_data, app_id, app_vk);
+            vars.insert("order_utxo".to_string(), order.order_utxo.clone());
+            vars.insert("addr_escrow".to_string(), order.order_data.escrow_address.clone());
+            vars.insert("addr_maker".to_string(), order.order_data.maker_address.clone());
+            vars.insert("fill_want_amount".to_string(), order.fill_want_amount.to_string());
+            vars.insert("current_filled".to_string(), order.current_filled.to_string());
+            vars.insert("current_remaining".to_string(), order.current_remaining.to_string());
+            vars.insert("new_filled".to_string(), (order.current_filled + order.fill_amount).to_string());
+            vars.insert("new_remaining".to_string(), new_remaining.to_string());
+
+            order_inputs.push_str(&self.build_spell(BATCH_ORDER_INPUT, &vars)?);
+            if new_remaining > 0 {
+                order_outputs.push_str(&self.build_spell(BATCH_ORDER_REMAINDER, &vars)?);
+            }
+            order_outputs.push_str(&self.build_spell(BATCH_MAKER_PAYMENT, &vars)?);
+        }
+
+        let fill_amounts: Vec<String> = data.orders.iter().map(|order| order.fill_amount.to_string()).collect();
+        let total_fill: u64 = data.orders.iter().map(|order| order.fill_amount).sum();
+        let total_want: u64 = data.orders.iter().map(|order| order.fill_want_amount).sum();
+
+        let mut vars = BTreeMap::new();
+        vars.insert("app_id".to_string(), app_id.to_string());
+        vars.insert("app_vk".to_string(), app_vk.to_string());
+        vars.insert("offer_token_id".to_string(), first.order_data.offer_token_id.clone());
+        vars.insert("offer_token_vk".to_string(), first.order_data.offer_token_vk.clone());
+        vars.insert("want_token_id".to_string(), first.order_data.want_token_id.clone());
+        vars.insert("want_token_vk".to_string(), first.order_data.want_token_vk.clone());
+        vars.insert("taker_utxo".to_string(), data.taker_utxo.clone());
+        vars.insert("taker_pubkey".to_string(), data.taker_pubkey.clone());
+        vars.insert("addr_taker".to_string(), data.taker_address.clone());
+        vars.insert("order_count".to_string(), data.orders.len().to_string());
+        vars.insert("fill_amounts".to_string(), format!("[{}]", fill_amounts.join(", ")));
+        vars.insert("total_fill_amount".to_string(), total_fill.to_string());
+        vars.insert("total_want_amount".to_string(), total_want.to_string());
+        // The blocks go in last so nothing inside them is substituted again
+        let spell = self.build_spell(template, &vars)?;
+        let mut blocks = BTreeMap::new();
+        blocks.insert("order_inputs".to_string(), order_inputs.trim_end().to_string());
+        blocks.insert("order_outputs".to_string(), order_outputs.trim_end().to_string());
+
+        self.build_spell(&spell, &blocks)
+    }
+
     /// Prove a spell - calls Charms Prover API
     pub async fn prove_spell(
         &self,
         request: SpellProveRequest,
-    ) -> Result<Vec<ProvedTransaction>> {
+    ) -> Result<Vec<ProvedTransaction>, ProverError> {
         if self.mock_mode {
             tracing::info!("Mock mode: returning simulated transaction");
             // Return mock transactions for development
@@ -207,25 +480,36 @@ impl CharmsService {
             ]);
         }
 
+        // Reject malformed spells locally instead of waiting on the prover
+        let report = self.check_spell(&request.spell);
+        if !report.valid {
+            return Err(ProverError::InvalidSpell(report.error_summary()));
+        }
+
         tracing::info!("Calling Charms Prover API at {}", self.api_url);
         
         let client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(120)) // ZK proofs take time
-            .build()?;
+            .build()
+            .map_err(|e| ProverError::Unavailable(e.to_string()))?;
         
         let response = client
             .post(&self.api_url)
             .json(&request)
             .send()
-            .await?;
+            .await
+            .map_err(|e| ProverError::Unavailable(e.to_string()))?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let error = response.text().await?;
-            anyhow::bail!("Prover API error ({}): {}", status, error);
+            let status = response.status().as_u16();
+            let body = response.text().await.unwrap_or_default();
+            return Err(ProverError::Api { status, body });
         }
 
-        let txs: Vec<ProvedTransaction> = response.json().await?;
+        let txs: Vec<ProvedTransaction> = response
+            .json()
+            .await
+            .map_err(|e| ProverError::BadResponse(e.to_string()))?;
         tracing::info!("Received {} transactions from prover", txs.len());
         Ok(txs)
     }
@@ -247,39 +531,48 @@ impl CharmsService {
 
     /// Validate a spell locally before proving
     pub fn validate_spell(&self, spell_yaml: &str) -> Result<()> {
-        // Parse YAML
-        let spell: serde_yaml::Value = serde_yaml::from_str(spell_yaml)?;
-        
-        // Check version
-        if let Some(version) = spell.get("version") {
-            let v = version.as_u64().unwrap_or(0);
-            if v != 8 {
-                anyhow::bail!("Invalid spell version: expected 8, got {}", v);
-            }
-        } else {
-            anyhow::bail!("Spell missing version field");
-        }
-        
-        // Check required fields
-        if spell.get("apps").is_none() {
-            anyhow::bail!("Spell missing 'apps' field");
+        let report = self.check_spell(spell_yaml);
+        if !report.valid {
+            anyhow::bail!("Invalid spell: {}", report.error_summary());
         }
-        if spell.get("ins").is_none() {
-            anyhow::bail!("Spell missing 'ins' field");
-        }
-        if spell.get("outs").is_none() {
-            anyhow::bail!("Spell missing 'outs' field");
-        }
-        
         Ok(())
     }
 
+    /// Run all local spell checks and return the structured findings
+    pub fn check_spell(&self, spell_yaml: &str) -> SpellReport {
+        spell_validator::validate(spell_yaml)
+    }
+
     /// Check if service is in mock mode
     pub fn is_mock_mode(&self) -> bool {
         self.mock_mode
     }
 }
 
+/// Variables describing an existing order NFT, shared by the spells that spend it
+fn order_state_vars(order_data: &OrderSpellData, app_id: &str, app_vk: &str) -> BTreeMap<String, String> {
+    let mut vars = BTreeMap::new();
+
+    vars.insert("app_id".to_string(), app_id.to_string());
+    vars.insert("app_vk".to_string(), app_vk.to_string());
+    vars.insert("offer_token_id".to_string(), order_data.offer_token_id.clone());
+    vars.insert("offer_token_vk".to_string(), order_data.offer_token_vk.clone());
+    vars.insert("want_token_id".to_string(), order_data.want_token_id.clone());
+    vars.insert("want_token_vk".to_string(), order_data.want_token_vk.clone());
+    vars.insert("maker_pubkey".to_string(), order_data.maker_pubkey.clone());
+    vars.insert("offer_amount".to_string(), order_data.offer_amount.clone());
+    vars.insert("want_amount".to_string(), order_data.want_amount.clone());
+    vars.insert("dest_chain".to_string(), order_data.dest_chain.to_string());
+    vars.insert("dest_address".to_string(), order_data.dest_address.clone());
+    vars.insert("expiry_height".to_string(), order_data.expiry_height.to_string());
+    vars.insert("allow_partial".to_string(), order_data.allow_partial.to_string());
+    vars.insert("min_fill_amount".to_string(), order_data.min_fill_amount.clone());
+    vars.insert("current_status".to_string(), "0".to_string());
+    vars.insert("created_at".to_string(), "0".to_string());
+
+    vars
+}
+
 /// Information about a charm on a UTXO
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CharmInfo {
@@ -289,19 +582,14 @@ pub struct CharmInfo {
     pub data: serde_json::Value,
 }
 
-impl Default for CharmsService {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::DEFAULT_PROVER_API_URL;
 
     #[test]
     fn test_build_spell() {
-        let service = CharmsService::new();
+        let service = CharmsService::new(DEFAULT_PROVER_API_URL, true);
         
         let template = "version: 8\naddress: ${addr}\namount: ${amount}";
         let mut vars = BTreeMap::new();
@@ -315,24 +603,177 @@ mod tests {
 
     #[test]
     fn test_validate_spell() {
-        let service = CharmsService::new();
+        let service = CharmsService::new(DEFAULT_PROVER_API_URL, true);
         
         let valid_spell = r#"
 version: 8
 apps:
-  $TOKEN: t/abc/def
+  $TOKEN: t/0000000000000000000000000000000000000000000000000000000000000abc/0000000000000000000000000000000000000000000000000000000000000def
 ins:
-  - utxo_id: test
+  - utxo_id: 1111111111111111111111111111111111111111111111111111111111111111:0
+    charms:
+      $TOKEN: 10
 outs:
   - address: test
+    charms:
+      $TOKEN: 10
 "#;
         
         assert!(service.validate_spell(valid_spell).is_ok());
     }
 
+    fn order_data() -> OrderSpellData {
+        OrderSpellData {
+            maker_address: "tb1qmaker".to_string(),
+            maker_pubkey: "02aa".to_string(),
+            offer_token_id: "11".repeat(32),
+            offer_token_vk: "22".repeat(32),
+            offer_amount: "1000".to_string(),
+            want_token_id: "33".repeat(32),
+            want_token_vk: "99".repeat(32),
+            want_amount: "500".to_string(),
+            expiry_height: 900,
+            allow_partial: true,
+            min_fill_amount: "100".to_string(),
+            funding_utxo: String::new(),
+            escrow_address: "tb1qescrow".to_string(),
+            dest_chain: 0,
+            dest_address: "tb1qmaker".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_build_cancel_expire_and_partial_fill_spells() {
+        let service = CharmsService::new(DEFAULT_PROVER_API_URL, true);
+        let order_utxo = format!("{}:0", "44".repeat(32));
+        let (app_id, app_vk) = ("55".repeat(32), "66".repeat(32));
+
+        let cancel = service.build_cancel_order_spell(
+            include_str!("../../../apps/swap-app/spells/cancel-order.yaml"),
+            &CancelSpellData {
+                order_utxo: order_utxo.clone(),
+                maker_address: "tb1qmaker".to_string(),
+                filled_amount: 200,
+                remaining_amount: 800,
+            },
+            &order_data(),
+            &app_id,
+            &app_vk,
+        ).unwrap();
+        let report = service.check_spell(&cancel);
+        assert!(report.valid, "{}", report.error_summary());
+
+        let expire = service.build_expire_order_spell(
+            include_str!("../../../apps/swap-app/spells/expire-order.yaml"),
+            &ExpireSpellData {
+                order_utxo: order_utxo.clone(),
+                maker_address: "tb1qmaker".to_string(),
+                filled_amount: 200,
+                remaining_amount: 800,
+                current_height: 901,
+            },
+            &order_data(),
+            &app_id,
+            &app_vk,
+        ).unwrap();
+        let report = service.check_spell(&expire);
+        assert!(report.valid, "{}", report.error_summary());
+        assert!(expire.contains("current_height: 901"));
+        assert!(!expire.contains("${"));
+
+        let partial = service.build_partial_fill_spell(
+            include_str!("../../../apps/swap-app/spells/partial-fill.yaml"),
+            &PartialFillSpellData {
+                order_utxo,
+                taker_utxo: format!("{}:1", "77".repeat(32)),
+                taker_pubkey: "03bb".to_string(),
+                taker_address: "tb1qtaker".to_string(),
+                maker_address: "tb1qmaker".to_string(),
+                escrow_address: "tb1qescrow".to_string(),
+                fill_amount: 300,
+                fill_want_amount: 150,
+                current_filled: 200,
+                current_remaining: 800,
+            },
+            &order_data(),
+            &app_id,
+            &app_vk,
+        ).unwrap();
+        let report = service.check_spell(&partial);
+        assert!(report.valid, "{}", report.error_summary());
+        assert!(partial.contains("filled_amount: 500"));
+        assert!(partial.contains("$OFFER: 500"));
+        assert!(partial.contains(&format!("$WANT: t/{}/{}", "33".repeat(32), "99".repeat(32))));
+    }
+
+    #[test]
+    fn test_build_batch_fill_spell() {
+        let service = CharmsService::new(DEFAULT_PROVER_API_URL, true);
+        let (app_id, app_vk) = ("55".repeat(32), "66".repeat(32));
+        let order = |utxo: &str, fill_amount, current_filled, current_remaining| BatchFillOrder {
+            order_utxo: format!("{}:0", utxo.repeat(32)),
+            order_data: order_data(),
+            fill_amount,
+            fill_want_amount: fill_amount / 2,
+            current_filled,
+            current_remaining,
+        };
+
+        let batch = service.build_batch_fill_spell(
+            include_str!("../../../apps/swap-app/spells/batch-fill.yaml"),
+            &BatchFillSpellData {
+                taker_utxo: format!("{}:1", "77".repeat(32)),
+                taker_pubkey: "03bb".to_string(),
+                taker_address: "tb1qtaker".to_string(),
+                // The first order is used up, the second keeps 600 in escrow
+                orders: vec![order("44", 800, 200, 800), order("88", 400, 0, 1000)],
+            },
+            &app_id,
+            &app_vk,
+        ).unwrap();
+        let report = service.check_spell(&batch);
+        assert!(report.valid, "{}", report.error_summary());
+        assert!(!batch.contains("${"));
+        assert!(batch.contains("fill_amounts: [800, 400]"));
+        assert!(batch.contains("$WANT: 600"));
+        assert!(batch.contains("$OFFER: 1200"));
+        assert_eq!(batch.matches("address: tb1qescrow").count(), 1);
+        assert!(batch.contains("filled_amount: 400"));
+    }
+
+    #[test]
+    fn test_prove_request_round_trip() {
+        let request = SpellProveRequest {
+            spell: "version: 8\napps: {}\nins: []\nouts: []\n".to_string(),
+            binaries: BTreeMap::new(),
+            prev_txs: vec![],
+            funding_utxo: "ab:0".to_string(),
+            funding_utxo_value: 1000,
+            change_address: "tb1q...".to_string(),
+            fee_rate: 2.0,
+            chain: "bitcoin".to_string(),
+        };
+
+        // The spell goes over the wire as an object and comes back as YAML
+        let json = serde_json::to_value(&request).unwrap();
+        assert!(json["spell"].is_object());
+        let back: SpellProveRequest = serde_json::from_value(json).unwrap();
+        let spell: serde_yaml::Value = serde_yaml::from_str(&back.spell).unwrap();
+        assert_eq!(spell["version"], serde_yaml::Value::from(8));
+    }
+
+    #[test]
+    fn test_transient_prover_errors() {
+        assert!(ProverError::Unavailable("timeout".into()).is_transient());
+        assert!(ProverError::Api { status: 503, body: String::new() }.is_transient());
+        assert!(ProverError::Api { status: 429, body: String::new() }.is_transient());
+        assert!(!ProverError::Api { status: 400, body: String::new() }.is_transient());
+        assert!(!ProverError::InvalidSpell("bad".into()).is_transient());
+    }
+
     #[test]
     fn test_validate_spell_invalid() {
-        let service = CharmsService::new();
+        let service = CharmsService::new(DEFAULT_PROVER_API_URL, true);
         
         let invalid_spell = "version: 7\napps: {}";
         assert!(service.validate_spell(invalid_spell).is_err());
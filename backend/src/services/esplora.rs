@@ -0,0 +1,290 @@
+//! Esplora/electrs HTTP API client
+//!
+//! Implements [`ChainBackend`] for deployments without a full node, against
+//! the REST API served by Blockstream's electrs and mempool.space.
+
+use async_trait::async_trait;
+use bitcoin::Transaction;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use super::chain::{AddressUtxo, ChainBackend, ChainError, ChainResult, TxStatus};
+
+/// Default API, matching the default `testnet4` network
+pub const DEFAULT_ESPLORA_URL: &str = "https://mempool.space/testnet4/api";
+
+/// Esplora HTTP API client
+#[derive(Debug, Clone)]
+pub struct EsploraClient {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+/// `status` object of transactions and UTXOs
+#[derive(Debug, Deserialize)]
+struct EsploraStatus {
+    confirmed: bool,
+    #[serde(default)]
+    block_height: Option<u64>,
+    #[serde(default)]
+    block_hash: Option<String>,
+}
+
+/// Entry of `/address/:address/utxo`
+#[derive(Debug, Deserialize)]
+struct EsploraUtxo {
+    txid: String,
+    vout: u32,
+    value: u64,
+    status: EsploraStatus,
+}
+
+impl EsploraClient {
+    pub fn new(base_url: &str, timeout: Duration) -> ChainResult<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| ChainError::Unavailable(e.to_string()))?;
+        Ok(Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// GET a path; `None` on 404
+    async fn get(&self, path: &str) -> ChainResult<Option<reqwest::Response>> {
+        let response = self
+            .client
+            .get(format!("{}{}", self.base_url, path))
+            .send()
+            .await
+            .map_err(|e| ChainError::Unavailable(e.to_string()))?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            // Unknown or malformed txids and addresses
+            StatusCode::BAD_REQUEST => Ok(None),
+            status if status.is_success() => Ok(Some(response)),
+            status => Err(ChainError::Unavailable(format!(
+                "GET {} returned {}: {}",
+                path,
+                status,
+                response.text().await.unwrap_or_default()
+            ))),
+        }
+    }
+
+    async fn get_text(&self, path: &str) -> ChainResult<Option<String>> {
+        match self.get(path).await? {
+            Some(response) => response
+                .text()
+                .await
+                .map(Some)
+                .map_err(|e| ChainError::Unavailable(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_json<T: for<'de> Deserialize<'de>>(&self, path: &str) -> ChainResult<Option<T>> {
+        match self.get(path).await? {
+            Some(response) => response
+                .json()
+                .await
+                .map(Some)
+                .map_err(|e| ChainError::BadResponse(format!("{}: {}", path, e))),
+            None => Ok(None),
+        }
+    }
+}
+
+#[async_trait]
+impl ChainBackend for EsploraClient {
+    fn name(&self) -> &'static str {
+        "esplora"
+    }
+
+    async fn tip_height(&self) -> ChainResult<u64> {
+        let height = self
+            .get_text("/blocks/tip/height")
+            .await?
+            .ok_or_else(|| ChainError::BadResponse("no tip height".to_string()))?;
+        height
+            .trim()
+            .parse()
+            .map_err(|_| ChainError::BadResponse(format!("tip height is not a number: {}", height)))
+    }
+
+    async fn get_transaction(&self, txid: &str) -> ChainResult<Option<Transaction>> {
+        match self.get_text(&format!("/tx/{}/hex", txid)).await? {
+            Some(hex) => bitcoin::consensus::encode::deserialize_hex(hex.trim())
+                .map(Some)
+                .map_err(|e| ChainError::BadResponse(format!("transaction {} does not decode: {}", txid, e))),
+            None => Ok(None),
+        }
+    }
+
+    async fn tx_status(&self, txid: &str) -> ChainResult<Option<TxStatus>> {
+        Ok(self
+            .get_json::<EsploraStatus>(&format!("/tx/{}/status", txid))
+            .await?
+            .map(|status| TxStatus {
+                confirmed: status.confirmed,
+                block_height: status.block_height,
+                block_hash: status.block_hash,
+            }))
+    }
+
+    async fn address_utxos(&self, address: &str) -> ChainResult<Vec<AddressUtxo>> {
+        Ok(self
+            .get_json::<Vec<EsploraUtxo>>(&format!("/address/{}/utxo", address))
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|utxo| AddressUtxo {
+                txid: utxo.txid,
+                vout: utxo.vout,
+                value: utxo.value,
+                height: utxo.status.block_height.filter(|_| utxo.status.confirmed),
+            })
+            .collect())
+    }
+
+    async fn broadcast(&self, tx_hex: &str) -> ChainResult<String> {
+        let response = self
+            .client
+            .post(format!("{}/tx", self.base_url))
+            .body(tx_hex.to_string())
+            .send()
+            .await
+            .map_err(|e| ChainError::Unavailable(e.to_string()))?;
+        let status = response.status();
+        let body = response.text().await.map_err(|e| ChainError::Unavailable(e.to_string()))?;
+        match status {
+            status if status.is_success() => Ok(body.trim().to_string()),
+            // The node behind the API refused the transaction
+            StatusCode::BAD_REQUEST => Err(ChainError::Rejected(body)),
+            status => Err(ChainError::Unavailable(format!("POST /tx returned {}: {}", status, body))),
+        }
+    }
+
+    async fn fee_estimates(&self, conf_targets: &[u16]) -> ChainResult<Vec<Option<f64>>> {
+        let estimates: BTreeMap<String, f64> = self.get_json("/fee-estimates").await?.unwrap_or_default();
+        let estimates: BTreeMap<u16, f64> = estimates
+            .into_iter()
+            .filter_map(|(target, rate)| Some((target.parse().ok()?, rate)))
+            .collect();
+        Ok(conf_targets
+            .iter()
+            .map(|target| estimate_for_target(&estimates, *target))
+            .collect())
+    }
+}
+
+/// Rate for a confirmation target from Esplora's sparse estimate table
+///
+/// Esplora has estimates for 1-25, 144, 504 and 1008 blocks; a target in a
+/// gap uses the nearest shorter one, which never underpays.
+fn estimate_for_target(estimates: &BTreeMap<u16, f64>, target: u16) -> Option<f64> {
+    estimates.range(..=target).next_back().map(|(_, rate)| *rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::Path, http::StatusCode, routing::{get, post}, Json, Router};
+
+    /// Start a mock Esplora API on a free port; returns its URL and the txid it knows
+    async fn mock_esplora() -> (String, String) {
+        let tx = bitcoin::Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn::default()],
+            output: vec![bitcoin::TxOut {
+                value: bitcoin::Amount::from_sat(1_000),
+                script_pubkey: bitcoin::ScriptBuf::new(),
+            }],
+        };
+        let tx_hex = bitcoin::consensus::encode::serialize_hex(&tx);
+        let txid = tx.compute_txid().to_string();
+
+        let known = txid.clone();
+        let app = Router::new()
+            .route("/api/blocks/tip/height", get(|| async { "850123" }))
+            .route(
+                "/api/tx/:txid/hex",
+                get(move |Path(id): Path<String>| async move {
+                    if id == known { Ok(tx_hex) } else { Err(StatusCode::NOT_FOUND) }
+                }),
+            )
+            .route(
+                "/api/tx/:txid/status",
+                get(|| async {
+                    Json(serde_json::json!({ "confirmed": true, "block_height": 850100, "block_hash": "00ab" }))
+                }),
+            )
+            .route(
+                "/api/address/:address/utxo",
+                get(|| async {
+                    Json(serde_json::json!([
+                        { "txid": "aa", "vout": 1, "value": 5000, "status": { "confirmed": true, "block_height": 850000 } },
+                        { "txid": "bb", "vout": 0, "value": 700, "status": { "confirmed": false } }
+                    ]))
+                }),
+            )
+            .route(
+                "/api/tx",
+                post(|body: String| async move {
+                    if body == "00" {
+                        Err((StatusCode::BAD_REQUEST, "sendrawtransaction RPC error: TX decode failed"))
+                    } else {
+                        Ok("f00d")
+                    }
+                }),
+            )
+            .route(
+                "/api/fee-estimates",
+                get(|| async { Json(serde_json::json!({ "1": 30.5, "2": 20.0, "6": 8.25, "144": 1.5 })) }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (url, txid)
+    }
+
+    #[tokio::test]
+    async fn test_esplora_backend() {
+        let (url, txid) = mock_esplora().await;
+        let txid = txid.as_str();
+        let esplora = EsploraClient::new(&url, Duration::from_secs(5)).unwrap();
+
+        assert_eq!(esplora.tip_height().await.unwrap(), 850123);
+
+        let tx = esplora.get_transaction(txid).await.unwrap().unwrap();
+        assert_eq!(tx.compute_txid().to_string(), txid);
+        assert!(esplora.get_transaction(&"00".repeat(32)).await.unwrap().is_none());
+
+        let status = esplora.tx_status(txid).await.unwrap().unwrap();
+        assert_eq!(status.block_height, Some(850100));
+
+        let utxos = esplora.address_utxos("tb1qexample").await.unwrap();
+        assert_eq!(utxos.len(), 2);
+        assert_eq!(utxos[0].height, Some(850000));
+        assert_eq!(utxos[1].height, None);
+
+        assert_eq!(esplora.broadcast("0200").await.unwrap(), "f00d");
+        assert!(matches!(esplora.broadcast("00").await, Err(ChainError::Rejected(_))));
+
+        let rates = esplora.fee_estimates(&[2, 6, 144]).await.unwrap();
+        assert_eq!(rates, vec![Some(20.0), Some(8.25), Some(1.5)]);
+        assert!(esplora.test_mempool_accept(&[]).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_estimate_for_target_uses_shorter_target() {
+        let estimates = BTreeMap::from([(2, 20.0), (6, 8.0), (144, 1.0)]);
+        assert_eq!(estimate_for_target(&estimates, 10), Some(8.0));
+        assert_eq!(estimate_for_target(&estimates, 1008), Some(1.0));
+        assert_eq!(estimate_for_target(&estimates, 1), None);
+    }
+}
@@ -0,0 +1,195 @@
+//! Persisted event log behind the real-time stream
+//!
+//! Every order and escrow change is written to the `events` table, which
+//! hands out increasing ids, and then broadcast to live subscribers. A
+//! client that reconnects with the last id it saw replays what it missed
+//! from the table before following the live feed again.
+
+use serde::Serialize;
+use tokio::sync::{broadcast, Mutex};
+
+use crate::db::{self, DbPool, EventRecord};
+
+/// Live events buffered per subscriber before it lags
+const EVENT_BUFFER: usize = 1024;
+
+/// What changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    OrderCreated,
+    OrderUpdated,
+    OrderFilled,
+    OrderCancelled,
+    OrderExpired,
+    MatchProposed,
+    TradeExecuted,
+    EscrowUpdated,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::OrderCreated => "order_created",
+            EventKind::OrderUpdated => "order_updated",
+            EventKind::OrderFilled => "order_filled",
+            EventKind::OrderCancelled => "order_cancelled",
+            EventKind::OrderExpired => "order_expired",
+            EventKind::MatchProposed => "match_proposed",
+            EventKind::TradeExecuted => "trade_executed",
+            EventKind::EscrowUpdated => "escrow_updated",
+        }
+    }
+}
+
+/// An entry of the event log
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamEvent {
+    pub id: i64,
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Pair of the order, as from [`market_key`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub market: Option<String>,
+    /// Addresses or pubkeys of the parties
+    pub addresses: Vec<String>,
+    /// The order or escrow after the change
+    pub data: serde_json::Value,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<EventRecord> for StreamEvent {
+    fn from(record: EventRecord) -> Self {
+        Self {
+            id: record.id,
+            kind: record.kind,
+            market: record.market,
+            addresses: record.addresses,
+            data: serde_json::from_str(&record.data).unwrap_or(serde_json::Value::Null),
+            created_at: record.created_at,
+        }
+    }
+}
+
+/// Markets and addresses a client follows; an empty filter follows everything
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    pub markets: Vec<String>,
+    pub addresses: Vec<String>,
+}
+
+impl EventFilter {
+    /// Whether an event concerns any followed market or address
+    pub fn matches(&self, event: &StreamEvent) -> bool {
+        if self.markets.is_empty() && self.addresses.is_empty() {
+            return true;
+        }
+        event.market.as_ref().is_some_and(|market| self.markets.contains(market))
+            || event.addresses.iter().any(|address| self.addresses.contains(address))
+    }
+}
+
+/// Key of a pair, the same for both directions (`BTC/TOAD` for TOAD/BTC)
+pub fn market_key(token_a: &str, token_b: &str) -> String {
+    let (a, b) = (token_a.to_uppercase(), token_b.to_uppercase());
+    if a <= b {
+        format!("{}/{}", a, b)
+    } else {
+        format!("{}/{}", b, a)
+    }
+}
+
+/// Parse a market written as `BASE/QUOTE` or `BASE-QUOTE`
+pub fn parse_market(market: &str) -> Option<String> {
+    let (a, b) = market.split_once(['/', '-'])?;
+    (!a.is_empty() && !b.is_empty()).then(|| market_key(a.trim(), b.trim()))
+}
+
+/// Writer of the event log and source of the live feed
+pub struct EventLog {
+    db: DbPool,
+    sender: broadcast::Sender<StreamEvent>,
+    /// Keeps ids in the order events are broadcast
+    publish_lock: Mutex<()>,
+}
+
+impl EventLog {
+    pub fn new(db: DbPool) -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        Self {
+            db,
+            sender,
+            publish_lock: Mutex::new(()),
+        }
+    }
+
+    /// Store an event and send it to live subscribers
+    pub async fn publish(
+        &self,
+        kind: EventKind,
+        market: Option<String>,
+        addresses: Vec<String>,
+        data: &impl Serialize,
+    ) -> anyhow::Result<StreamEvent> {
+        let data = serde_json::to_string(data)?;
+        let _guard = self.publish_lock.lock().await;
+        let record = db::insert_event(&self.db, kind.as_str(), market.as_deref(), &addresses, &data).await?;
+        let event = StreamEvent::from(record);
+        // Nobody listening is fine
+        let _ = self.sender.send(event.clone());
+        Ok(event)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<StreamEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Stored events after `after` that match a filter, oldest first
+    pub async fn since(&self, after: i64, filter: &EventFilter, limit: u32) -> anyhow::Result<Vec<StreamEvent>> {
+        let records = db::get_events_after(&self.db, after, &filter.markets, &filter.addresses, limit).await?;
+        Ok(records.into_iter().map(StreamEvent::from).collect())
+    }
+
+    /// Id of the newest stored event, 0 when there is none
+    pub async fn latest_id(&self) -> anyhow::Result<i64> {
+        db::latest_event_id(&self.db).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(market: Option<&str>, addresses: &[&str]) -> StreamEvent {
+        StreamEvent {
+            id: 1,
+            kind: EventKind::OrderCreated.as_str().to_string(),
+            market: market.map(str::to_string),
+            addresses: addresses.iter().map(|a| a.to_string()).collect(),
+            data: serde_json::Value::Null,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_market_key_ignores_direction() {
+        assert_eq!(market_key("toad", "BTC"), "BTC/TOAD");
+        assert_eq!(parse_market("BTC-toad").as_deref(), Some("BTC/TOAD"));
+        assert_eq!(parse_market("TOAD/BTC").as_deref(), Some("BTC/TOAD"));
+        assert_eq!(parse_market("TOAD"), None);
+        assert_eq!(parse_market("/BTC"), None);
+    }
+
+    #[test]
+    fn test_filter_follows_markets_or_addresses() {
+        let order = event(Some("BTC/TOAD"), &["tb1qmaker"]);
+        let escrow = event(None, &["02aa", "03bb"]);
+
+        assert!(EventFilter::default().matches(&order));
+        let market = EventFilter { markets: vec!["BTC/TOAD".to_string()], addresses: vec![] };
+        assert!(market.matches(&order));
+        assert!(!market.matches(&escrow));
+        let both = EventFilter { markets: vec!["BTC/ETH".to_string()], addresses: vec!["03bb".to_string()] };
+        assert!(!both.matches(&order));
+        assert!(both.matches(&escrow));
+    }
+}
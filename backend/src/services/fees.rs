@@ -0,0 +1,216 @@
+//! Fee rate selection and fee bumping
+//!
+//! Fee rates come from the chain backend's estimates, clamped to a
+//! configured range, with a fixed fallback when it has no estimate
+//! (fresh regtest chains never do). Stuck transactions are bumped either by
+//! re-proving them at a higher rate (RBF) or by a child that pays for the
+//! package (CPFP); the arithmetic for both lives here.
+
+use bitcoin::{Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness};
+use serde::Serialize;
+
+use super::chain::ChainBackend;
+
+/// Fee rate increase Core requires of a replacement, in sat/vB
+pub const INCREMENTAL_RELAY_FEE: f64 = 1.0;
+
+/// Smallest output Core relays for any standard script type, in sats
+const DUST_LIMIT: u64 = 546;
+
+/// How soon a transaction should confirm
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeTarget {
+    Fast,
+    Normal,
+    Slow,
+}
+
+impl FeeTarget {
+    /// Confirmation target passed to `estimatesmartfee`, in blocks
+    pub fn blocks(&self) -> u16 {
+        match self {
+            FeeTarget::Fast => 2,
+            FeeTarget::Normal => 6,
+            FeeTarget::Slow => 144,
+        }
+    }
+}
+
+/// Bounds on the fee rates the backend uses
+#[derive(Debug, Clone)]
+pub struct FeePolicy {
+    /// Lowest rate ever used (`FEE_MIN_RATE`, default 1 sat/vB)
+    pub min_rate: f64,
+    /// Highest rate ever used (`FEE_MAX_RATE`, default 500 sat/vB)
+    pub max_rate: f64,
+    /// Rate used when the node has no estimate (`FEE_FALLBACK_RATE`, default 10 sat/vB)
+    pub fallback_rate: f64,
+}
+
+impl Default for FeePolicy {
+    fn default() -> Self {
+        Self {
+            min_rate: 1.0,
+            max_rate: 500.0,
+            fallback_rate: 10.0,
+        }
+    }
+}
+
+impl FeePolicy {
+    /// Keep a rate within the policy bounds
+    pub fn clamp(&self, rate: f64) -> f64 {
+        rate.clamp(self.min_rate, self.max_rate)
+    }
+}
+
+/// Current fee rates in sat/vB
+#[derive(Debug, Clone, Serialize)]
+pub struct FeeQuote {
+    pub fast: f64,
+    pub normal: f64,
+    pub slow: f64,
+    /// Chain backend the estimates came from, or `fallback` when it had none
+    pub source: &'static str,
+}
+
+impl FeeQuote {
+    pub fn rate(&self, target: FeeTarget) -> f64 {
+        match target {
+            FeeTarget::Fast => self.fast,
+            FeeTarget::Normal => self.normal,
+            FeeTarget::Slow => self.slow,
+        }
+    }
+}
+
+/// Fee rates for all targets
+pub async fn quote(chain: &dyn ChainBackend, policy: &FeePolicy) -> FeeQuote {
+    let targets = [FeeTarget::Fast, FeeTarget::Normal, FeeTarget::Slow];
+    let estimates = match chain.fee_estimates(&targets.map(|target| target.blocks())).await {
+        Ok(estimates) => estimates,
+        Err(e) => {
+            tracing::warn!("Fee estimation via {} failed, using the fallback rate: {}", chain.name(), e);
+            vec![None; targets.len()]
+        }
+    };
+    let estimated = estimates.iter().all(Option::is_some);
+    let mut rates = [0.0; 3];
+    for (rate, estimate) in rates.iter_mut().zip(estimates) {
+        *rate = policy.clamp(estimate.unwrap_or(policy.fallback_rate));
+    }
+
+    // Estimates for longer targets are never higher than shorter ones
+    let [fast, normal, slow] = rates;
+    let normal = normal.min(fast);
+    FeeQuote {
+        fast,
+        normal,
+        slow: slow.min(normal),
+        source: if estimated { chain.name() } else { "fallback" },
+    }
+}
+
+/// Fee rate for a new transaction: the caller's, if given, or the estimate
+pub async fn select_fee_rate(
+    chain: &dyn ChainBackend,
+    policy: &FeePolicy,
+    requested: Option<f64>,
+    target: FeeTarget,
+) -> f64 {
+    match requested.filter(|rate| rate.is_finite() && *rate > 0.0) {
+        Some(rate) => policy.clamp(rate),
+        None => quote(chain, policy).await.rate(target),
+    }
+}
+
+/// Rate for a replacement of a transaction paying `original_rate`
+///
+/// A replacement has to pay at least the incremental relay fee more per
+/// vbyte than what it replaces; the transactions are rebuilt from the same
+/// spell, so their sizes match.
+pub fn rbf_fee_rate(original_rate: f64, target_rate: f64) -> f64 {
+    target_rate.max(original_rate + INCREMENTAL_RELAY_FEE)
+}
+
+/// Fee a child must pay for it and its parent to reach `target_rate`
+pub fn cpfp_child_fee(parent_vsize: u64, parent_fee: u64, child_vsize: u64, target_rate: f64) -> u64 {
+    let package_fee = (target_rate * (parent_vsize + child_vsize) as f64).ceil() as u64;
+    // The child must at least pay for itself at the minimum relay rate
+    package_fee.saturating_sub(parent_fee).max(child_vsize)
+}
+
+/// Unsigned child spending `outpoint` back to its own script
+///
+/// Returns `None` when the output is too small to pay `fee` and stay above
+/// the dust limit.
+pub fn cpfp_child(outpoint: OutPoint, prevout: &TxOut, fee: u64) -> Option<Transaction> {
+    let value = prevout.value.to_sat().checked_sub(fee).filter(|v| *v >= DUST_LIMIT)?;
+    Some(Transaction {
+        version: bitcoin::transaction::Version::TWO,
+        lock_time: bitcoin::absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: outpoint,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: prevout.script_pubkey.clone(),
+        }],
+    })
+}
+
+/// Virtual size of a one-input child once signed
+pub fn cpfp_child_vsize(prevout: &TxOut) -> u64 {
+    // Witness weight: a 64-byte schnorr signature on a taproot key path,
+    // otherwise a P2WPKH signature and public key; plus the segwit marker
+    let witness_weight = if prevout.script_pubkey.is_p2tr() { 1 + 1 + 64 } else { 1 + 1 + 72 + 1 + 33 };
+    let unsigned = cpfp_child(OutPoint::null(), prevout, 0)
+        .map(|tx| tx.weight().to_wu())
+        .unwrap_or(0);
+    (unsigned + 2 + witness_weight).div_ceil(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+
+    #[test]
+    fn test_policy_clamps_rates() {
+        let policy = FeePolicy { min_rate: 2.0, max_rate: 100.0, fallback_rate: 10.0 };
+        assert_eq!(policy.clamp(0.5), 2.0);
+        assert_eq!(policy.clamp(25.0), 25.0);
+        assert_eq!(policy.clamp(1_000.0), 100.0);
+    }
+
+    #[test]
+    fn test_rbf_pays_at_least_the_increment() {
+        assert_eq!(rbf_fee_rate(10.0, 5.0), 11.0);
+        assert_eq!(rbf_fee_rate(10.0, 30.0), 30.0);
+    }
+
+    #[test]
+    fn test_cpfp_child_fee_covers_package() {
+        // Parent: 200 vB paying 200 sats (1 sat/vB); target 10 sat/vB
+        assert_eq!(cpfp_child_fee(200, 200, 111, 10.0), 3110 - 200);
+        // Parent already pays enough: the child still pays for itself
+        assert_eq!(cpfp_child_fee(200, 10_000, 111, 10.0), 111);
+    }
+
+    #[test]
+    fn test_cpfp_child_respects_dust() {
+        let prevout = TxOut {
+            value: Amount::from_sat(5_000),
+            script_pubkey: ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::from_byte_array([1; 20])),
+        };
+        let child = cpfp_child(OutPoint::null(), &prevout, 1_000).unwrap();
+        assert_eq!(child.output[0].value, Amount::from_sat(4_000));
+        assert!(cpfp_child(OutPoint::null(), &prevout, 4_600).is_none());
+
+        let vsize = cpfp_child_vsize(&prevout);
+        assert!((105..=115).contains(&vsize), "{}", vsize);
+    }
+}
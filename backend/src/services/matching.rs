@@ -0,0 +1,232 @@
+//! Order matching
+//!
+//! Finds crossing orders on a pair among those whose makers opted into
+//! automatic matching. An order offering A for B crosses one offering B for
+//! A when each gets at least its asking price, i.e. `want_x * want_y <=
+//! offer_x * offer_y`; token decimals cancel out, so amounts are compared in
+//! smallest units. The older order of a crossing pair is the maker and is
+//! filled at its own price, like a taker filling it by hand would.
+
+use std::collections::BTreeMap;
+
+use super::events::market_key;
+use crate::db::OrderRecord;
+
+/// A proposed fill of one order by another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub market: String,
+    /// Resting order, filled at its own price
+    pub maker_order_id: String,
+    /// Order paying for the fill with its offered tokens
+    pub taker_order_id: String,
+    /// Maker's offered tokens going to the taker
+    pub maker_amount: u64,
+    /// Taker's offered tokens going to the maker
+    pub taker_amount: u64,
+    /// Whether the fill takes the rest of the maker order
+    pub fills_maker: bool,
+}
+
+/// Side of a pair with the amount it still offers
+struct Resting<'a> {
+    order: &'a OrderRecord,
+    offer: u128,
+    want: u128,
+    remaining: u128,
+    min_fill: u128,
+}
+
+impl<'a> Resting<'a> {
+    fn new(order: &'a OrderRecord) -> Option<Self> {
+        let offer: u128 = order.offer_amount.trim().parse().ok()?;
+        let want: u128 = order.want_amount.trim().parse().ok()?;
+        let filled: u128 = order.filled_amount.as_deref().unwrap_or("0").trim().parse().ok()?;
+        let remaining = offer.checked_sub(filled)?;
+        let min_fill: u128 = order.min_fill_amount.trim().parse().ok()?;
+        (offer > 0 && want > 0 && remaining > 0).then_some(Self { order, offer, want, remaining, min_fill })
+    }
+}
+
+/// Whether an order can be matched at all
+pub fn is_matchable(order: &OrderRecord) -> bool {
+    order.auto_match
+        && matches!(order.status.as_str(), "open" | "partiallyfilled")
+        // The order UTXO is what a fill spends
+        && order.tx_id.is_some()
+}
+
+/// Crossing fills among matchable orders, grouped by pair
+///
+/// Cheapest asks meet the highest bids first; each order is used up to its
+/// remaining amount, orders that do not allow partial fills are only
+/// matched whole, fills below an order's minimum only take its last
+/// remainder, and a maker's orders never match each other.
+pub fn find_matches(orders: &[OrderRecord]) -> Vec<Match> {
+    let mut markets: BTreeMap<String, Vec<&OrderRecord>> = BTreeMap::new();
+    for order in orders.iter().filter(|order| is_matchable(order)) {
+        markets.entry(market_key(&order.offer_token, &order.want_token)).or_default().push(order);
+    }
+
+    let mut matches = Vec::new();
+    for (market, orders) in markets {
+        let base = market.split('/').next().unwrap_or_default().to_string();
+        let (mut asks, mut bids): (Vec<Resting>, Vec<Resting>) = orders
+            .into_iter()
+            .filter_map(Resting::new)
+            .partition(|side| side.order.offer_token.eq_ignore_ascii_case(&base));
+
+        // Asks by want/offer ascending, bids by offer/want descending,
+        // older first at the same price
+        asks.sort_by(|a, b| {
+            (a.want * b.offer).cmp(&(b.want * a.offer)).then(a.order.created_at.cmp(&b.order.created_at))
+        });
+        bids.sort_by(|a, b| {
+            (b.offer * a.want).cmp(&(a.offer * b.want)).then(a.order.created_at.cmp(&b.order.created_at))
+        });
+
+        for ask in asks.iter_mut() {
+            for bid in bids.iter_mut() {
+                if ask.remaining == 0 {
+                    break;
+                }
+                if bid.remaining == 0 || ask.order.maker_address == bid.order.maker_address {
+                    continue;
+                }
+                // Bids further down pay even less
+                if ask.want * bid.want > ask.offer * bid.offer {
+                    break;
+                }
+                let (maker, taker) = if ask.order.created_at <= bid.order.created_at {
+                    (&mut *ask, &mut *bid)
+                } else {
+                    (&mut *bid, &mut *ask)
+                };
+                if let Some(fill) = fill(&market, maker, taker) {
+                    maker.remaining -= fill.maker_amount as u128;
+                    taker.remaining -= fill.taker_amount as u128;
+                    matches.push(fill);
+                }
+            }
+        }
+    }
+    matches
+}
+
+/// The largest fill of `maker` that `taker` can pay for within both limits
+fn fill(market: &str, maker: &Resting, taker: &Resting) -> Option<Match> {
+    // What the taker still wants of the maker's token
+    let taker_wants = taker.want * taker.remaining / taker.offer;
+    let maker_amount = maker.remaining.min(taker_wants);
+    // The maker's price, rounded down as the partial-fill spell does
+    let taker_amount = maker_amount * maker.want / maker.offer;
+
+    let within_limits = maker_amount > 0
+        && taker_amount > 0
+        && taker_amount <= taker.remaining
+        && taker_amount * taker.want <= maker_amount * taker.offer;
+    let whole_when_required = (maker.order.allow_partial || maker_amount == maker.offer)
+        && (taker.order.allow_partial || taker_amount == taker.offer);
+    let above_min_fill = (maker_amount >= maker.min_fill || maker_amount == maker.remaining)
+        && (taker_amount >= taker.min_fill || taker_amount == taker.remaining);
+    if !within_limits || !whole_when_required || !above_min_fill {
+        return None;
+    }
+
+    Some(Match {
+        market: market.to_string(),
+        maker_order_id: maker.order.id.clone(),
+        taker_order_id: taker.order.id.clone(),
+        maker_amount: maker_amount as u64,
+        taker_amount: taker_amount as u64,
+        fills_maker: maker_amount == maker.remaining,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(id: &str, maker: &str, offer: (&str, u64), want: (&str, u64), allow_partial: bool, age: i64) -> OrderRecord {
+        let created = chrono::Utc::now() - chrono::Duration::minutes(age);
+        OrderRecord {
+            id: id.to_string(),
+            maker_address: maker.to_string(),
+            offer_token: offer.0.to_string(),
+            offer_amount: offer.1.to_string(),
+            want_token: want.0.to_string(),
+            want_amount: want.1.to_string(),
+            source_chain: "bitcoin".to_string(),
+            dest_chain: "bitcoin".to_string(),
+            status: "open".to_string(),
+            allow_partial,
+            filled_amount: Some("0".to_string()),
+            expiry_height: Some(900),
+            utxo_id: None,
+            tx_id: Some(format!("{}_tx", id)),
+            created_at: created,
+            updated_at: created,
+            auto_match: true,
+            min_fill_amount: "0".to_string(),
+            maker_pubkey: None,
+        }
+    }
+
+    #[test]
+    fn test_crossing_orders_fill_at_maker_price() {
+        let orders = vec![
+            // Sells 1000 TOAD for 100 BTC units (0.1 each)
+            order("ask", "alice", ("TOAD", 1000), ("BTC", 100), true, 10),
+            // Pays 60 BTC units for 500 TOAD (0.12 each), posted later
+            order("bid", "bob", ("BTC", 60), ("TOAD", 500), true, 5),
+        ];
+        let matches = find_matches(&orders);
+        assert_eq!(matches, vec![Match {
+            market: "BTC/TOAD".to_string(),
+            maker_order_id: "ask".to_string(),
+            taker_order_id: "bid".to_string(),
+            maker_amount: 500,
+            taker_amount: 50,
+            fills_maker: false,
+        }]);
+    }
+
+    #[test]
+    fn test_orders_that_do_not_cross_or_opt_out_stay() {
+        let mut opted_out = order("bid2", "carol", ("BTC", 60), ("TOAD", 500), true, 5);
+        opted_out.auto_match = false;
+        let orders = vec![
+            order("ask", "alice", ("TOAD", 1000), ("BTC", 100), true, 10),
+            // 0.08 each, below the ask
+            order("bid", "bob", ("BTC", 40), ("TOAD", 500), true, 5),
+            opted_out,
+            // Same maker as the ask
+            order("self", "alice", ("BTC", 60), ("TOAD", 500), true, 5),
+        ];
+        assert!(find_matches(&orders).is_empty());
+    }
+
+    #[test]
+    fn test_all_or_nothing_orders_match_whole() {
+        let orders = vec![
+            order("ask", "alice", ("TOAD", 1000), ("BTC", 100), false, 10),
+            // Too small for the whole ask
+            order("small", "bob", ("BTC", 60), ("TOAD", 500), true, 5),
+            order("large", "carol", ("BTC", 150), ("TOAD", 1200), true, 4),
+        ];
+        let matches = find_matches(&orders);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].taker_order_id, "large");
+        assert_eq!((matches[0].maker_amount, matches[0].taker_amount), (1000, 100));
+        assert!(matches[0].fills_maker);
+    }
+
+    #[test]
+    fn test_fills_below_min_fill_are_skipped() {
+        // Would pay 20 of the 60 it offers, below its minimum of 55
+        let mut picky = order("picky", "bob", ("BTC", 60), ("TOAD", 500), true, 5);
+        picky.min_fill_amount = "55".to_string();
+        let small = order("small_ask", "alice", ("TOAD", 200), ("BTC", 20), true, 10);
+        assert!(find_matches(&[small, picky]).is_empty());
+    }
+}
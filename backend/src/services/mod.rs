@@ -1,7 +1,24 @@
 //! Backend services
 
+pub mod address;
+pub mod app_registry;
+pub mod auth;
 pub mod bitcoin;
+pub mod chain;
+pub mod chain_watcher;
 pub mod charms;
+pub mod esplora;
+pub mod events;
+pub mod fees;
+pub mod matching;
+pub mod orderbook;
+pub mod prove_queue;
+pub mod psbt;
+pub mod routing;
+pub mod spell_validator;
+pub mod tasks;
+pub mod tokens;
+pub mod trades;
 
 pub use bitcoin::BitcoinService;
 pub use charms::CharmsService;
@@ -0,0 +1,205 @@
+//! Orderbook aggregation
+//!
+//! Live orders of a trading pair are grouped into price levels. An order
+//! offering the base token for the quote token is an ask; one offering the
+//! quote token for the base token is a bid, so orders for both directions
+//! of a pair land on one book. Prices are in quote per base in whole tokens,
+//! kept as integers of the quote token's smallest unit so levels group
+//! exactly.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::db::OrderRecord;
+
+/// A pair as `base`/`quote` with the decimals of both tokens
+#[derive(Debug, Clone)]
+pub struct Pair {
+    pub base: String,
+    pub quote: String,
+    pub base_decimals: u32,
+    pub quote_decimals: u32,
+}
+
+/// Orders resting at one price
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PriceLevel {
+    /// Quote per base, in whole tokens
+    pub price: String,
+    /// Remaining base tokens, in whole tokens
+    pub size: String,
+    pub orders: u32,
+}
+
+/// Aggregated book of a pair
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderBook {
+    pub base: String,
+    pub quote: String,
+    /// Best (highest) first
+    pub bids: Vec<PriceLevel>,
+    /// Best (lowest) first
+    pub asks: Vec<PriceLevel>,
+    pub best_bid: Option<String>,
+    pub best_ask: Option<String>,
+    /// Best ask minus best bid; negative when the book is crossed
+    pub spread: Option<String>,
+}
+
+/// Remaining size and order count at a price, in smallest units
+#[derive(Default)]
+struct Level {
+    size: u128,
+    orders: u32,
+}
+
+/// Build the book of a pair from live orders of either direction
+///
+/// Orders for other pairs or with malformed amounts are skipped; `depth`
+/// caps the levels per side.
+pub fn build_book(pair: &Pair, orders: &[OrderRecord], depth: usize) -> OrderBook {
+    let (base, quote) = (pair.base.as_str(), pair.quote.as_str());
+    let base_scale = 10u128.pow(pair.base_decimals);
+    let quote_decimals = pair.quote_decimals;
+
+    let mut bids: BTreeMap<u128, Level> = BTreeMap::new();
+    let mut asks: BTreeMap<u128, Level> = BTreeMap::new();
+    for order in orders {
+        let Some((offer, want, filled)) = amounts(order) else { continue };
+        let remaining = offer.saturating_sub(filled);
+        if remaining == 0 {
+            continue;
+        }
+        let is_ask = order.offer_token.eq_ignore_ascii_case(base) && order.want_token.eq_ignore_ascii_case(quote);
+        let is_bid = order.offer_token.eq_ignore_ascii_case(quote) && order.want_token.eq_ignore_ascii_case(base);
+        let (side, price, size) = if is_ask {
+            // Sells `remaining` base for quote at want/offer
+            (&mut asks, want * base_scale / offer, remaining)
+        } else if is_bid {
+            // Pays quote for base at offer/want; the base still wanted
+            // shrinks with what has been filled
+            if want == 0 {
+                continue;
+            }
+            (&mut bids, offer * base_scale / want, want * remaining / offer)
+        } else {
+            continue;
+        };
+        let level = side.entry(price).or_default();
+        level.size += size;
+        level.orders += 1;
+    }
+
+    let base_decimals = pair.base_decimals;
+    let level = |(price, level): (&u128, &Level)| PriceLevel {
+        price: format_units(*price as i128, quote_decimals),
+        size: format_units(level.size as i128, base_decimals),
+        orders: level.orders,
+    };
+    let best_bid = bids.keys().next_back().copied();
+    let best_ask = asks.keys().next().copied();
+
+    OrderBook {
+        base: base.to_uppercase(),
+        quote: quote.to_uppercase(),
+        bids: bids.iter().rev().take(depth).map(level).collect(),
+        asks: asks.iter().take(depth).map(level).collect(),
+        best_bid: best_bid.map(|price| format_units(price as i128, quote_decimals)),
+        best_ask: best_ask.map(|price| format_units(price as i128, quote_decimals)),
+        spread: best_bid
+            .zip(best_ask)
+            .map(|(bid, ask)| format_units(ask as i128 - bid as i128, quote_decimals)),
+    }
+}
+
+/// Offer, want and filled amounts of an order, in smallest units
+fn amounts(order: &OrderRecord) -> Option<(u128, u128, u128)> {
+    let offer: u128 = order.offer_amount.trim().parse().ok()?;
+    let want = order.want_amount.trim().parse().ok()?;
+    let filled = order.filled_amount.as_deref().unwrap_or("0").trim().parse().ok()?;
+    (offer > 0).then_some((offer, want, filled))
+}
+
+/// Render smallest units as a decimal amount without trailing zeros
+pub fn format_units(units: i128, decimals: u32) -> String {
+    let sign = if units < 0 { "-" } else { "" };
+    let units = units.unsigned_abs();
+    let scale = 10u128.pow(decimals);
+    let (whole, fraction) = (units / scale, units % scale);
+    if fraction == 0 {
+        return format!("{}{}", sign, whole);
+    }
+    let fraction = format!("{:0width$}", fraction, width = decimals as usize);
+    format!("{}{}.{}", sign, whole, fraction.trim_end_matches('0'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(offer_token: &str, offer: &str, want_token: &str, want: &str, filled: &str) -> OrderRecord {
+        let now = chrono::Utc::now();
+        OrderRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            maker_address: "tb1qmaker".to_string(),
+            offer_token: offer_token.to_string(),
+            offer_amount: offer.to_string(),
+            want_token: want_token.to_string(),
+            want_amount: want.to_string(),
+            source_chain: "bitcoin".to_string(),
+            dest_chain: "bitcoin".to_string(),
+            status: "open".to_string(),
+            allow_partial: true,
+            filled_amount: Some(filled.to_string()),
+            expiry_height: Some(900),
+            utxo_id: None,
+            tx_id: None,
+            created_at: now,
+            updated_at: now,
+            auto_match: false,
+            min_fill_amount: "0".to_string(),
+            maker_pubkey: None,
+        }
+    }
+
+    fn pair(base: &str, quote: &str) -> Pair {
+        Pair { base: base.to_string(), quote: quote.to_string(), base_decimals: 8, quote_decimals: 8 }
+    }
+
+    #[test]
+    fn test_book_merges_both_directions() {
+        let orders = vec![
+            // Asks: 100 TOAD at 0.001 BTC, twice, and 50 TOAD at 0.002 BTC
+            order("TOAD", "10000000000", "BTC", "10000000", "0"),
+            order("toad", "20000000000", "btc", "20000000", "10000000000"),
+            order("TOAD", "5000000000", "BTC", "10000000", "0"),
+            // Bid: 0.05 BTC for 100 TOAD, half filled
+            order("BTC", "5000000", "TOAD", "10000000000", "2500000"),
+            // Other pair
+            order("ETH", "1", "BTC", "1", "0"),
+        ];
+        let book = build_book(&pair("toad", "btc"), &orders, 10);
+
+        assert_eq!(book.base, "TOAD");
+        assert_eq!(book.asks, vec![
+            PriceLevel { price: "0.001".to_string(), size: "200".to_string(), orders: 2 },
+            PriceLevel { price: "0.002".to_string(), size: "50".to_string(), orders: 1 },
+        ]);
+        assert_eq!(book.bids, vec![PriceLevel { price: "0.0005".to_string(), size: "50".to_string(), orders: 1 }]);
+        assert_eq!(book.best_bid.as_deref(), Some("0.0005"));
+        assert_eq!(book.best_ask.as_deref(), Some("0.001"));
+        assert_eq!(book.spread.as_deref(), Some("0.0005"));
+
+        // The inverted pair is the same book seen from the other side
+        let inverted = build_book(&pair("BTC", "TOAD"), &orders, 10);
+        assert_eq!(inverted.asks, vec![PriceLevel { price: "2000".to_string(), size: "0.025".to_string(), orders: 1 }]);
+        assert_eq!(inverted.best_bid.as_deref(), Some("1000"));
+    }
+
+    #[test]
+    fn test_format_units() {
+        assert_eq!(format_units(150_000_000, 8), "1.5");
+        assert_eq!(format_units(-50_000, 8), "-0.0005");
+        assert_eq!(format_units(7, 0), "7");
+    }
+}
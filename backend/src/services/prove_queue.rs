@@ -0,0 +1,347 @@
+//! Asynchronous proving job queue
+//!
+//! Prove requests are stored in the `prove_jobs` table and worked off in the
+//! background, so HTTP handlers never wait on the prover. Jobs move from
+//! `queued` to `running` to `succeeded` or `failed`; transient prover errors
+//! put the job back in the queue with exponential backoff. Submitting a
+//! request identical to one that is pending or has succeeded returns the
+//! existing job instead of proving twice.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Notify, Semaphore};
+use tokio_util::sync::CancellationToken;
+
+use super::app_registry::AppRegistry;
+use super::charms::{CharmsService, ProvedTransaction, SpellProveRequest};
+use crate::db::{self, DbPool, ProveJobRecord};
+
+/// Longest wait between two attempts of the same job
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+/// Prove job status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(status: &str) -> Self {
+        match status {
+            "running" => JobStatus::Running,
+            "succeeded" => JobStatus::Succeeded,
+            "failed" => JobStatus::Failed,
+            _ => JobStatus::Queued,
+        }
+    }
+
+    /// Whether the job will not change any more
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::Succeeded | JobStatus::Failed)
+    }
+}
+
+/// Prove job as returned by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct ProveJob {
+    pub id: String,
+    pub status: JobStatus,
+    pub attempts: i32,
+    pub transactions: Option<Vec<ProvedTransaction>>,
+    pub error: Option<String>,
+    pub next_attempt_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<ProveJobRecord> for ProveJob {
+    fn from(record: ProveJobRecord) -> Self {
+        let status = JobStatus::parse(&record.status);
+        Self {
+            id: record.id,
+            status,
+            attempts: record.attempts,
+            transactions: record
+                .result
+                .as_deref()
+                .and_then(|r| serde_json::from_str(r).ok()),
+            error: record.error,
+            next_attempt_at: (status == JobStatus::Queued)
+                .then(|| record.next_attempt_at.to_rfc3339()),
+            created_at: record.created_at.to_rfc3339(),
+            updated_at: record.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+/// Queue tuning
+#[derive(Debug, Clone)]
+pub struct ProveQueueConfig {
+    /// Jobs proved at the same time (`PROVE_CONCURRENCY`, default 2)
+    pub concurrency: usize,
+    /// Attempts before a transiently failing job is given up (`PROVE_MAX_ATTEMPTS`, default 5)
+    pub max_attempts: i32,
+    /// Delay before the first retry, doubled on each further one (`PROVE_RETRY_BASE_SECS`, default 2)
+    pub retry_base: Duration,
+    /// How often idle workers look for due jobs
+    pub poll_interval: Duration,
+}
+
+impl ProveQueueConfig {
+    /// Delay before retrying a job that has made `attempts` attempts
+    pub fn retry_delay(&self, attempts: i32) -> Duration {
+        let exponent = attempts.saturating_sub(1).clamp(0, 16) as u32;
+        self.retry_base
+            .saturating_mul(2u32.pow(exponent))
+            .min(MAX_RETRY_DELAY)
+    }
+}
+
+/// Persistent proving job queue
+pub struct ProveQueue {
+    db: DbPool,
+    charms: Arc<CharmsService>,
+    apps: Arc<AppRegistry>,
+    config: ProveQueueConfig,
+    wake: Notify,
+}
+
+impl ProveQueue {
+    pub fn new(
+        db: DbPool,
+        charms: Arc<CharmsService>,
+        apps: Arc<AppRegistry>,
+        config: ProveQueueConfig,
+    ) -> Self {
+        Self {
+            db,
+            charms,
+            apps,
+            config,
+            wake: Notify::new(),
+        }
+    }
+
+    /// Queue a prove request, or return the job already handling an identical one
+    pub async fn submit(&self, request: &SpellProveRequest) -> Result<ProveJob> {
+        let now = chrono::Utc::now();
+        let record = ProveJobRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            request_hash: request_hash(request)?,
+            request: serde_json::to_string(request).context("failed to serialize prove request")?,
+            status: JobStatus::Queued.as_str().to_string(),
+            attempts: 0,
+            result: None,
+            error: None,
+            next_attempt_at: now,
+            created_at: now,
+            updated_at: now,
+        };
+        let stored = db::insert_prove_job(&self.db, &record).await?;
+        if stored.id != record.id {
+            tracing::info!("Reusing prove job {} ({})", stored.id, stored.status);
+            return Ok(stored.into());
+        }
+        tracing::info!("Queued prove job {}", stored.id);
+
+        self.wake.notify_one();
+        Ok(stored.into())
+    }
+
+    /// Look up a job
+    pub async fn get(&self, id: &str) -> Result<Option<ProveJob>> {
+        Ok(db::get_prove_job(&self.db, id).await?.map(ProveJob::from))
+    }
+
+    /// Wait up to `timeout` for a job to finish and return its latest state
+    pub async fn wait(&self, id: &str, timeout: Duration) -> Result<Option<ProveJob>> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let job = self.get(id).await?;
+            let finished = job.as_ref().is_none_or(|j| j.status.is_finished());
+            if finished || tokio::time::Instant::now() >= deadline {
+                return Ok(job);
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    /// Run the workers until `shutdown` is cancelled, then wait for the jobs
+    /// being proved
+    ///
+    /// A job cut off by a forced stop is requeued on the next start.
+    pub async fn run(self: Arc<Self>, shutdown: CancellationToken) {
+        match db::requeue_running_prove_jobs(&self.db).await {
+            Ok(0) => {}
+            Ok(n) => tracing::warn!("Requeued {} prove jobs interrupted by a restart", n),
+            Err(e) => tracing::error!("Failed to requeue interrupted prove jobs: {}", e),
+        }
+
+        tracing::info!("Prove queue started with {} workers", self.config.concurrency);
+        let slots = Arc::new(Semaphore::new(self.config.concurrency));
+
+        loop {
+            let slot = tokio::select! {
+                _ = shutdown.cancelled() => break,
+                slot = Arc::clone(&slots).acquire_owned() => slot.expect("prove queue semaphore is never closed"),
+            };
+
+            match db::claim_next_prove_job(&self.db).await {
+                Ok(Some(job)) => {
+                    let queue = Arc::clone(&self);
+                    tokio::spawn(async move {
+                        queue.execute(job).await;
+                        drop(slot);
+                    });
+                }
+                Ok(None) => {
+                    drop(slot);
+                    tokio::select! {
+                        _ = shutdown.cancelled() => break,
+                        _ = self.wake.notified() => {}
+                        _ = tokio::time::sleep(self.config.poll_interval) => {}
+                    }
+                }
+                Err(e) => {
+                    drop(slot);
+                    tracing::error!("Failed to claim prove job: {}", e);
+                    tokio::time::sleep(self.config.poll_interval).await;
+                }
+            }
+        }
+
+        // Every slot is free again once no job is being proved
+        let in_flight = self.config.concurrency - slots.available_permits();
+        if in_flight > 0 {
+            tracing::info!("Waiting for {} prove jobs to finish", in_flight);
+        }
+        let _ = slots.acquire_many(self.config.concurrency as u32).await;
+        tracing::info!("Prove queue stopped");
+    }
+
+    async fn execute(&self, job: ProveJobRecord) {
+        tracing::info!("Proving job {} (attempt {})", job.id, job.attempts);
+
+        // Binaries of registered apps are attached here rather than stored
+        // with every job
+        let outcome = match serde_json::from_str::<SpellProveRequest>(&job.request) {
+            Ok(mut request) => {
+                self.apps.attach_binaries(&request.spell, &mut request.binaries);
+                self.charms.prove_spell(request).await
+            }
+            Err(e) => {
+                self.fail(&job.id, &format!("Stored request is unreadable: {}", e)).await;
+                return;
+            }
+        };
+
+        match outcome {
+            Ok(txs) if txs.is_empty() => {
+                self.fail(&job.id, "Prover returned no transactions").await;
+            }
+            Ok(txs) => {
+                let result = serde_json::to_string(&txs).expect("transactions serialize");
+                let status = JobStatus::Succeeded.as_str();
+                match db::finish_prove_job(&self.db, &job.id, status, Some(&result), None).await {
+                    Ok(()) => tracing::info!("Prove job {} succeeded", job.id),
+                    Err(e) => tracing::error!("Failed to store result of prove job {}: {}", job.id, e),
+                }
+            }
+            Err(e) if e.is_transient() && job.attempts < self.config.max_attempts => {
+                let delay = self.config.retry_delay(job.attempts);
+                tracing::warn!("Prove job {} failed ({}), retrying in {:?}", job.id, e, delay);
+                let next_attempt_at = chrono::Utc::now()
+                    + chrono::Duration::from_std(delay).unwrap_or_else(|_| chrono::Duration::zero());
+                if let Err(e) = db::retry_prove_job(&self.db, &job.id, &e.to_string(), next_attempt_at).await {
+                    tracing::error!("Failed to requeue prove job {}: {}", job.id, e);
+                }
+            }
+            Err(e) => self.fail(&job.id, &e.to_string()).await,
+        }
+    }
+
+    async fn fail(&self, id: &str, error: &str) {
+        tracing::error!("Prove job {} failed: {}", id, error);
+        let status = JobStatus::Failed.as_str();
+        if let Err(e) = db::finish_prove_job(&self.db, id, status, None, Some(error)).await {
+            tracing::error!("Failed to record failure of prove job {}: {}", id, e);
+        }
+    }
+}
+
+/// Hash identifying a prove request, independent of spell key order
+pub fn request_hash(request: &SpellProveRequest) -> Result<String> {
+    // Going through `Value` sorts object keys, so equivalent spells hash alike
+    let canonical = serde_json::to_value(request).context("failed to serialize prove request")?;
+    let digest = Sha256::digest(serde_json::to_vec(&canonical)?);
+    Ok(hex::encode(digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn request(spell: &str) -> SpellProveRequest {
+        SpellProveRequest {
+            spell: spell.to_string(),
+            binaries: BTreeMap::new(),
+            prev_txs: vec![],
+            funding_utxo: "ab:0".to_string(),
+            funding_utxo_value: 1000,
+            change_address: "tb1q...".to_string(),
+            fee_rate: 2.0,
+            chain: "bitcoin".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_request_hash_ignores_key_order() {
+        let a = request("version: 8\nins: []\nouts: []\n");
+        let b = request("outs: []\nversion: 8\nins: []\n");
+        assert_eq!(request_hash(&a).unwrap(), request_hash(&b).unwrap());
+
+        let mut c = a.clone();
+        c.fee_rate = 3.0;
+        assert_ne!(request_hash(&a).unwrap(), request_hash(&c).unwrap());
+    }
+
+    #[test]
+    fn test_retry_delay_backoff() {
+        let config = ProveQueueConfig {
+            concurrency: 1,
+            max_attempts: 5,
+            retry_base: Duration::from_secs(2),
+            poll_interval: Duration::from_secs(1),
+        };
+        assert_eq!(config.retry_delay(1), Duration::from_secs(2));
+        assert_eq!(config.retry_delay(2), Duration::from_secs(4));
+        assert_eq!(config.retry_delay(4), Duration::from_secs(16));
+        assert_eq!(config.retry_delay(30), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_job_status_round_trip() {
+        for status in [JobStatus::Queued, JobStatus::Running, JobStatus::Succeeded, JobStatus::Failed] {
+            assert_eq!(JobStatus::parse(status.as_str()), status);
+        }
+        assert!(JobStatus::Failed.is_finished());
+        assert!(!JobStatus::Running.is_finished());
+    }
+}
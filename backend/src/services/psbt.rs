@@ -0,0 +1,433 @@
+//! BIP-174 PSBTs for proved transactions
+//!
+//! Proved transactions are handed to wallets as PSBTs carrying the witness
+//! UTXO and sighash type of every input the wallet has to sign; inputs the
+//! prover already satisfied (the commit output spent by a spell transaction)
+//! are marked final. Signed PSBTs come back through [`finalize`], which only
+//! accepts them if they are for exactly the proved transaction and every
+//! signature verifies over all of its inputs and outputs.
+
+use ::bitcoin::hashes::Hash;
+use ::bitcoin::key::{TapTweak, XOnlyPublicKey};
+use ::bitcoin::psbt::{self, Psbt, PsbtSighashType};
+use ::bitcoin::secp256k1::{Message, Secp256k1, Verification};
+use ::bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
+use ::bitcoin::{
+    ecdsa, taproot, CompressedPublicKey, ScriptBuf, Transaction, TxOut, Witness,
+};
+use base64::Engine;
+
+/// Why a PSBT could not be built or accepted
+#[derive(Debug, thiserror::Error)]
+pub enum PsbtError {
+    #[error("PSBT is neither base64 nor hex: {0}")]
+    Decode(String),
+    #[error("expected {expected} previous outputs, got {actual}")]
+    PrevoutCount { expected: usize, actual: usize },
+    #[error("signed transaction {txid} differs from the proved one: {reason}")]
+    TxMismatch { txid: String, reason: String },
+    #[error("input {0} is not signed")]
+    Unsigned(usize),
+    #[error("input {index} uses sighash {sighash}, which does not cover the whole transaction")]
+    PartialSighash { index: usize, sighash: String },
+    #[error("input {index} has an invalid signature: {reason}")]
+    BadSignature { index: usize, reason: String },
+    #[error("input {0} spends a script the backend cannot verify")]
+    UnsupportedInput(usize),
+    #[error(transparent)]
+    Psbt(#[from] psbt::Error),
+}
+
+/// An input the wallet has to sign
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningInput {
+    pub index: usize,
+    /// `SIGHASH_DEFAULT` for taproot key paths, `SIGHASH_ALL` otherwise
+    pub sighash_type: String,
+}
+
+/// Build a PSBT for a proved transaction
+///
+/// `prevouts` holds the output spent by each input, in input order.
+/// Inputs that already carry a witness were satisfied by the prover and
+/// are finalized as they are. For taproot inputs whose output key is
+/// `signer` tweaked without a script tree, the internal key is recorded
+/// so wallets know to sign on the key path.
+pub fn build_psbt(
+    tx: &Transaction,
+    prevouts: &[TxOut],
+    signer: Option<XOnlyPublicKey>,
+) -> Result<Psbt, PsbtError> {
+    check_prevouts(tx, prevouts)?;
+    let secp = Secp256k1::verification_only();
+
+    let mut psbt = Psbt::from_unsigned_tx(strip(tx))?;
+    for (index, (txin, prevout)) in tx.input.iter().zip(prevouts).enumerate() {
+        let input = &mut psbt.inputs[index];
+        input.witness_utxo = Some(prevout.clone());
+
+        if is_prover_signed(tx, index) {
+            input.final_script_witness = Some(txin.witness.clone());
+            if !txin.script_sig.is_empty() {
+                input.final_script_sig = Some(txin.script_sig.clone());
+            }
+            continue;
+        }
+
+        if prevout.script_pubkey.is_p2tr() {
+            input.sighash_type = Some(PsbtSighashType::from(TapSighashType::Default));
+            if let Some(key) = signer.filter(|key| key_path_matches(&secp, key, &prevout.script_pubkey)) {
+                input.tap_internal_key = Some(key);
+            }
+        } else {
+            input.sighash_type = Some(PsbtSighashType::from(EcdsaSighashType::All));
+        }
+    }
+
+    Ok(psbt)
+}
+
+/// Inputs of a PSBT that still need a signature
+pub fn inputs_to_sign(psbt: &Psbt) -> Vec<SigningInput> {
+    psbt.inputs
+        .iter()
+        .enumerate()
+        .filter(|(_, input)| input.final_script_witness.is_none())
+        .map(|(index, input)| {
+            let taproot = input
+                .witness_utxo
+                .as_ref()
+                .is_some_and(|utxo| utxo.script_pubkey.is_p2tr());
+            SigningInput {
+                index,
+                sighash_type: if taproot { "SIGHASH_DEFAULT" } else { "SIGHASH_ALL" }.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Base64 encoding of a PSBT, as wallets expect it
+pub fn encode(psbt: &Psbt) -> String {
+    base64::engine::general_purpose::STANDARD.encode(psbt.serialize())
+}
+
+/// Parse a PSBT sent as base64 or hex
+pub fn decode(encoded: &str) -> Result<Psbt, PsbtError> {
+    let encoded = encoded.trim();
+    let bytes = match hex::decode(encoded) {
+        Ok(bytes) => bytes,
+        Err(_) => base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|e| PsbtError::Decode(e.to_string()))?,
+    };
+    Ok(Psbt::deserialize(&bytes)?)
+}
+
+/// Finalize a signed PSBT into the proved transaction
+///
+/// The PSBT must be for `proved` (same inputs, outputs, version and lock
+/// time). Witness UTXOs are taken from `prevouts`, never from the PSBT, and
+/// prover-satisfied inputs keep the proved witness. Every other input must
+/// carry a taproot key-path or P2WPKH signature that verifies and commits
+/// to all inputs and outputs.
+pub fn finalize(signed: &Psbt, proved: &Transaction, prevouts: &[TxOut]) -> Result<Transaction, PsbtError> {
+    check_prevouts(proved, prevouts)?;
+    check_same_transaction(&signed.unsigned_tx, proved)?;
+
+    let mut tx = strip(proved);
+    for (index, input) in signed.inputs.iter().enumerate() {
+        tx.input[index].witness = if is_prover_signed(proved, index) {
+            proved.input[index].witness.clone()
+        } else if let Some(witness) = &input.final_script_witness {
+            witness.clone()
+        } else if let Some(sig) = input.tap_key_sig {
+            Witness::p2tr_key_spend(&sig)
+        } else if let Some((key, sig)) = input.partial_sigs.iter().next() {
+            Witness::p2wpkh(sig, &key.inner)
+        } else {
+            return Err(PsbtError::Unsigned(index));
+        };
+    }
+
+    verify_signatures(&tx, proved, prevouts)?;
+    Ok(tx)
+}
+
+/// Check that a signed transaction is the proved one with signatures added
+///
+/// Everything but the witnesses of wallet-signed inputs must be identical,
+/// including the witnesses the prover supplied.
+pub fn check_signed_transaction(signed: &Transaction, proved: &Transaction) -> Result<(), PsbtError> {
+    check_same_transaction(signed, proved)?;
+    for (index, (signed_in, proved_in)) in signed.input.iter().zip(&proved.input).enumerate() {
+        if is_prover_signed(proved, index)
+            && (signed_in.witness != proved_in.witness || signed_in.script_sig != proved_in.script_sig)
+        {
+            return Err(PsbtError::TxMismatch {
+                txid: signed.compute_txid().to_string(),
+                reason: format!("the proved witness of input {} was replaced", index),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Compare everything a txid commits to, naming the first difference
+fn check_same_transaction(signed: &Transaction, proved: &Transaction) -> Result<(), PsbtError> {
+    let reason = if signed.version != proved.version {
+        Some(format!("version {} instead of {}", signed.version, proved.version))
+    } else if signed.lock_time != proved.lock_time {
+        Some(format!("lock time {} instead of {}", signed.lock_time, proved.lock_time))
+    } else if signed.input.len() != proved.input.len() {
+        Some(format!("{} inputs instead of {}", signed.input.len(), proved.input.len()))
+    } else if signed.output.len() != proved.output.len() {
+        Some(format!("{} outputs instead of {}", signed.output.len(), proved.output.len()))
+    } else if let Some(index) = (0..signed.input.len()).find(|&i| {
+        signed.input[i].previous_output != proved.input[i].previous_output
+            || signed.input[i].sequence != proved.input[i].sequence
+    }) {
+        Some(format!("input {} spends {} instead of {}", index, signed.input[index].previous_output, proved.input[index].previous_output))
+    } else {
+        (0..signed.output.len())
+            .find(|&i| signed.output[i] != proved.output[i])
+            .map(|index| {
+                format!(
+                    "output {} pays {} to {} instead of {} to {}",
+                    index,
+                    signed.output[index].value,
+                    signed.output[index].script_pubkey,
+                    proved.output[index].value,
+                    proved.output[index].script_pubkey
+                )
+            })
+    };
+
+    match reason {
+        Some(reason) => Err(PsbtError::TxMismatch {
+            txid: signed.compute_txid().to_string(),
+            reason,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Check every wallet-signed input of `tx` against the outputs it spends
+///
+/// Inputs the prover satisfied in `proved` are skipped; their witness is
+/// checked by the network against the proof.
+pub fn verify_signatures(tx: &Transaction, proved: &Transaction, prevouts: &[TxOut]) -> Result<(), PsbtError> {
+    check_prevouts(tx, prevouts)?;
+    let secp = Secp256k1::verification_only();
+    let mut cache = SighashCache::new(tx);
+
+    for (index, (txin, prevout)) in tx.input.iter().zip(prevouts).enumerate() {
+        if is_prover_signed(proved, index) {
+            continue;
+        }
+        let bad = |reason: String| PsbtError::BadSignature { index, reason };
+        let witness: Vec<&[u8]> = txin.witness.iter().collect();
+        let script = &prevout.script_pubkey;
+
+        if script.is_p2tr() {
+            let [sig] = witness[..] else {
+                return Err(if witness.is_empty() { PsbtError::Unsigned(index) } else { PsbtError::UnsupportedInput(index) });
+            };
+            let sig = taproot::Signature::from_slice(sig).map_err(|e| bad(e.to_string()))?;
+            if !matches!(sig.sighash_type, TapSighashType::Default | TapSighashType::All) {
+                return Err(PsbtError::PartialSighash { index, sighash: sig.sighash_type.to_string() });
+            }
+            let output_key = XOnlyPublicKey::from_slice(&script.as_bytes()[2..34]).map_err(|e| bad(e.to_string()))?;
+            let sighash = cache
+                .taproot_key_spend_signature_hash(index, &Prevouts::All(prevouts), sig.sighash_type)
+                .map_err(|e| bad(e.to_string()))?;
+            let msg = Message::from_digest(sighash.to_byte_array());
+            secp.verify_schnorr(&sig.signature, &msg, &output_key)
+                .map_err(|e| bad(e.to_string()))?;
+        } else if script.is_p2wpkh() {
+            let [sig, key] = witness[..] else {
+                return Err(if witness.is_empty() { PsbtError::Unsigned(index) } else { PsbtError::UnsupportedInput(index) });
+            };
+            let sig = ecdsa::Signature::from_slice(sig).map_err(|e| bad(e.to_string()))?;
+            if sig.sighash_type != EcdsaSighashType::All {
+                return Err(PsbtError::PartialSighash { index, sighash: sig.sighash_type.to_string() });
+            }
+            let key = CompressedPublicKey::from_slice(key).map_err(|e| bad(e.to_string()))?;
+            if ScriptBuf::new_p2wpkh(&key.wpubkey_hash()) != *script {
+                return Err(bad("public key does not match the spent output".to_string()));
+            }
+            let sighash = cache
+                .p2wpkh_signature_hash(index, script, prevout.value, sig.sighash_type)
+                .map_err(|e| bad(e.to_string()))?;
+            let msg = Message::from_digest(sighash.to_byte_array());
+            secp.verify_ecdsa(&msg, &sig.signature, &key.0)
+                .map_err(|e| bad(e.to_string()))?;
+        } else {
+            return Err(PsbtError::UnsupportedInput(index));
+        }
+    }
+
+    Ok(())
+}
+
+/// The transaction without any script sigs or witnesses
+fn strip(tx: &Transaction) -> Transaction {
+    let mut tx = tx.clone();
+    for input in &mut tx.input {
+        input.script_sig = ScriptBuf::new();
+        input.witness = Witness::new();
+    }
+    tx
+}
+
+/// Whether the prover already satisfied an input of the proved transaction
+fn is_prover_signed(proved: &Transaction, index: usize) -> bool {
+    proved
+        .input
+        .get(index)
+        .is_some_and(|txin| !txin.witness.is_empty() || !txin.script_sig.is_empty())
+}
+
+fn check_prevouts(tx: &Transaction, prevouts: &[TxOut]) -> Result<(), PsbtError> {
+    if tx.input.len() != prevouts.len() {
+        return Err(PsbtError::PrevoutCount {
+            expected: tx.input.len(),
+            actual: prevouts.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Whether `script` pays to `key` on a taproot key path without a script tree
+fn key_path_matches<C: Verification>(secp: &Secp256k1<C>, key: &XOnlyPublicKey, script: &ScriptBuf) -> bool {
+    let (tweaked, _) = key.tap_tweak(secp, None);
+    ScriptBuf::new_p2tr_tweaked(tweaked) == *script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::bitcoin::secp256k1::{Keypair, SecretKey};
+    use ::bitcoin::transaction::Version;
+    use ::bitcoin::{absolute, Amount, OutPoint, Sequence, TxIn, Txid};
+
+    fn keypair(byte: u8) -> Keypair {
+        Keypair::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[byte; 32]).unwrap())
+    }
+
+    fn p2tr_output(keypair: &Keypair, value: u64) -> TxOut {
+        let secp = Secp256k1::new();
+        let (key, _) = keypair.x_only_public_key();
+        TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: ScriptBuf::new_p2tr(&secp, key, None),
+        }
+    }
+
+    /// Two inputs: one for the wallet, one already satisfied by the prover
+    fn proved_tx() -> Transaction {
+        let input = |byte: u8, witness: Witness| TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array([byte; 32]), 0),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness,
+        };
+        Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![input(1, Witness::new()), input(2, Witness::from_slice(&[vec![1u8], vec![2u8]]))],
+            output: vec![p2tr_output(&keypair(9), 1_000)],
+        }
+    }
+
+    fn sign_key_path(psbt: &mut Psbt, keypair: &Keypair, prevouts: &[TxOut], sighash_type: TapSighashType) {
+        let secp = Secp256k1::new();
+        let tweaked = keypair.tap_tweak(&secp, None).to_keypair();
+        let sighash = SighashCache::new(&psbt.unsigned_tx)
+            .taproot_key_spend_signature_hash(0, &Prevouts::All(prevouts), sighash_type)
+            .unwrap();
+        let msg = Message::from_digest(sighash.to_byte_array());
+        psbt.inputs[0].tap_key_sig = Some(taproot::Signature {
+            signature: secp.sign_schnorr_no_aux_rand(&msg, &tweaked),
+            sighash_type,
+        });
+    }
+
+    #[test]
+    fn test_build_and_finalize_round_trip() {
+        let maker = keypair(7);
+        let proved = proved_tx();
+        let prevouts = vec![p2tr_output(&maker, 5_000), p2tr_output(&keypair(8), 1_000)];
+
+        let psbt = build_psbt(&proved, &prevouts, Some(maker.x_only_public_key().0)).unwrap();
+        assert_eq!(psbt.inputs[0].tap_internal_key, Some(maker.x_only_public_key().0));
+        assert!(psbt.inputs[1].final_script_witness.is_some());
+        assert_eq!(
+            inputs_to_sign(&psbt),
+            vec![SigningInput { index: 0, sighash_type: "SIGHASH_DEFAULT".to_string() }]
+        );
+
+        let mut signed = decode(&encode(&psbt)).unwrap();
+        sign_key_path(&mut signed, &maker, &prevouts, TapSighashType::Default);
+
+        let tx = finalize(&signed, &proved, &prevouts).unwrap();
+        assert_eq!(tx.compute_txid(), proved.compute_txid());
+        assert_eq!(tx.input[1].witness, proved.input[1].witness);
+        assert_eq!(tx.input[0].witness.len(), 1);
+    }
+
+    #[test]
+    fn test_finalize_rejects_bad_signatures() {
+        let maker = keypair(7);
+        let proved = proved_tx();
+        let prevouts = vec![p2tr_output(&maker, 5_000), p2tr_output(&keypair(8), 1_000)];
+        let psbt = build_psbt(&proved, &prevouts, None).unwrap();
+
+        assert!(matches!(finalize(&psbt, &proved, &prevouts), Err(PsbtError::Unsigned(0))));
+
+        let mut wrong_key = psbt.clone();
+        sign_key_path(&mut wrong_key, &keypair(6), &prevouts, TapSighashType::Default);
+        assert!(matches!(
+            finalize(&wrong_key, &proved, &prevouts),
+            Err(PsbtError::BadSignature { index: 0, .. })
+        ));
+
+        let mut partial = psbt.clone();
+        sign_key_path(&mut partial, &maker, &prevouts, TapSighashType::SinglePlusAnyoneCanPay);
+        assert!(matches!(
+            finalize(&partial, &proved, &prevouts),
+            Err(PsbtError::PartialSighash { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_finalize_rejects_other_transactions() {
+        let maker = keypair(7);
+        let proved = proved_tx();
+        let prevouts = vec![p2tr_output(&maker, 5_000), p2tr_output(&keypair(8), 1_000)];
+
+        let mut other = proved.clone();
+        other.output[0].value = Amount::from_sat(999);
+        let mut signed = build_psbt(&other, &prevouts, None).unwrap();
+        sign_key_path(&mut signed, &maker, &prevouts, TapSighashType::Default);
+
+        let err = finalize(&signed, &proved, &prevouts).unwrap_err();
+        assert!(err.to_string().contains("output 0 pays"), "{}", err);
+    }
+
+    #[test]
+    fn test_signed_transaction_keeps_prover_witness() {
+        let proved = proved_tx();
+        let mut signed = proved.clone();
+        signed.input[0].witness = Witness::from_slice(&[vec![0u8; 64]]);
+        assert!(check_signed_transaction(&signed, &proved).is_ok());
+
+        signed.input[1].witness = Witness::from_slice(&[vec![3u8]]);
+        let err = check_signed_transaction(&signed, &proved).unwrap_err();
+        assert!(err.to_string().contains("input 1 was replaced"), "{}", err);
+
+        let mut reordered = proved.clone();
+        reordered.input.swap(0, 1);
+        let err = check_signed_transaction(&reordered, &proved).unwrap_err();
+        assert!(err.to_string().contains("input 0 spends"), "{}", err);
+    }
+}
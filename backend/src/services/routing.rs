@@ -0,0 +1,165 @@
+//! Best-execution routing
+//!
+//! Splits a taker's purchase across the cheapest live orders offering the
+//! token. Orders are taken best price first, older first at the same price,
+//! each filled at its own price as its fill spell would. Orders that do not
+//! allow partial fills are only taken whole, and a partial fill below an
+//! order's minimum is only possible for its last remainder, so the route is
+//! greedy: an order that cannot be used is skipped for the next one.
+
+use crate::db::OrderRecord;
+
+/// Part of a route taken from one order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteFill {
+    pub order_id: String,
+    /// Offered tokens bought from the order
+    pub fill_amount: u64,
+    /// Tokens paid to its maker
+    pub cost: u64,
+    /// Remaining offered tokens before the fill
+    pub remaining: u64,
+}
+
+/// Fills of a route with their totals
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Route {
+    pub fills: Vec<RouteFill>,
+    pub filled: u64,
+    pub cost: u64,
+}
+
+/// Whether a taker can fill an order in a transaction mined after `tip_height`
+pub fn is_fillable(order: &OrderRecord, tip_height: u64) -> bool {
+    matches!(order.status.as_str(), "open" | "partiallyfilled")
+        && order.tx_id.is_some()
+        && order.expiry_height.is_none_or(|expiry| expiry > tip_height as i64)
+}
+
+/// Route the purchase of `amount` of `buy_token` paid in `pay_token`
+///
+/// The route may fall short of `amount` when the book is too thin.
+pub fn route(orders: &[OrderRecord], buy_token: &str, pay_token: &str, amount: u64, tip_height: u64) -> Route {
+    let mut candidates: Vec<(&OrderRecord, u128, u128, u128, u128)> = orders
+        .iter()
+        .filter(|order| {
+            order.offer_token.eq_ignore_ascii_case(buy_token)
+                && order.want_token.eq_ignore_ascii_case(pay_token)
+                && is_fillable(order, tip_height)
+        })
+        .filter_map(|order| {
+            let offer: u128 = order.offer_amount.trim().parse().ok()?;
+            let want: u128 = order.want_amount.trim().parse().ok()?;
+            let filled: u128 = order.filled_amount.as_deref().unwrap_or("0").trim().parse().ok()?;
+            let min_fill: u128 = order.min_fill_amount.trim().parse().ok()?;
+            let remaining = offer.checked_sub(filled)?;
+            (offer > 0 && remaining > 0).then_some((order, offer, want, remaining, min_fill))
+        })
+        .collect();
+    // Cheapest want/offer first
+    candidates.sort_by(|a, b| (a.2 * b.1).cmp(&(b.2 * a.1)).then(a.0.created_at.cmp(&b.0.created_at)));
+
+    let mut route = Route::default();
+    let mut left = amount as u128;
+    for (order, offer, want, remaining, min_fill) in candidates {
+        if left == 0 {
+            break;
+        }
+        let (fill_amount, cost) = if order.allow_partial {
+            let fill_amount = remaining.min(left);
+            if fill_amount < min_fill && fill_amount < remaining {
+                continue;
+            }
+            // Rounded down as the partial-fill spell does
+            (fill_amount, fill_amount * want / offer)
+        } else {
+            // Untouched until filled, so the whole order is what remains
+            if remaining > left {
+                continue;
+            }
+            (remaining, want)
+        };
+        if cost == 0 {
+            continue;
+        }
+
+        left -= fill_amount;
+        route.filled += fill_amount as u64;
+        route.cost += cost as u64;
+        route.fills.push(RouteFill {
+            order_id: order.id.clone(),
+            fill_amount: fill_amount as u64,
+            cost: cost as u64,
+            remaining: remaining as u64,
+        });
+    }
+    route
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(id: &str, offer: u64, want: u64, allow_partial: bool, min_fill: u64, age: i64) -> OrderRecord {
+        let created = chrono::Utc::now() - chrono::Duration::minutes(age);
+        OrderRecord {
+            id: id.to_string(),
+            maker_address: "tb1qmaker".to_string(),
+            offer_token: "TOAD".to_string(),
+            offer_amount: offer.to_string(),
+            want_token: "BTC".to_string(),
+            want_amount: want.to_string(),
+            source_chain: "bitcoin".to_string(),
+            dest_chain: "bitcoin".to_string(),
+            status: "open".to_string(),
+            allow_partial,
+            filled_amount: Some("0".to_string()),
+            expiry_height: Some(900),
+            utxo_id: None,
+            tx_id: Some(format!("{}_tx", id)),
+            created_at: created,
+            updated_at: created,
+            auto_match: false,
+            min_fill_amount: min_fill.to_string(),
+            maker_pubkey: None,
+        }
+    }
+
+    #[test]
+    fn test_route_takes_cheapest_orders_first() {
+        let mut expired = order("expired", 1000, 10, true, 0, 30);
+        expired.expiry_height = Some(800);
+        let orders = vec![
+            order("pricey", 1000, 300, true, 0, 20),
+            order("cheap", 300, 30, true, 0, 10),
+            order("mid", 500, 100, true, 0, 15),
+            expired,
+        ];
+        let route = route(&orders, "toad", "btc", 600, 850);
+
+        let taken: Vec<_> = route.fills.iter().map(|fill| (fill.order_id.as_str(), fill.fill_amount, fill.cost)).collect();
+        assert_eq!(taken, vec![("cheap", 300, 30), ("mid", 300, 60)]);
+        assert_eq!((route.filled, route.cost), (600, 90));
+    }
+
+    #[test]
+    fn test_route_respects_whole_orders_and_min_fill() {
+        let orders = vec![
+            // Too large to take whole
+            order("whole", 800, 40, false, 0, 20),
+            // Would only get 500, below its minimum
+            order("min", 1000, 60, true, 600, 10),
+            order("fits", 400, 30, false, 0, 15),
+            order("rest", 1000, 100, true, 0, 5),
+        ];
+        let route = route(&orders, "TOAD", "BTC", 500, 850);
+
+        let taken: Vec<_> = route.fills.iter().map(|fill| (fill.order_id.as_str(), fill.fill_amount)).collect();
+        assert_eq!(taken, vec![("fits", 400), ("rest", 100)]);
+        assert_eq!(route.cost, 40);
+
+        // Short of liquidity, the route takes what there is
+        let short = super::route(&orders[..1], "TOAD", "BTC", 5000, 850);
+        assert_eq!((short.filled, short.cost), (800, 40));
+    }
+}
@@ -0,0 +1,514 @@
+//! Local spell validation
+//!
+//! Checks a built spell for structural mistakes before it is sent to the
+//! prover: unresolved template variables, unknown `$ALIAS` references,
+//! malformed app specs and UTXO ids and non-integer token amounts. Token
+//! amounts that differ between inputs and outputs are only warned about:
+//! whether a token may be minted is up to its contract.
+
+use serde::Serialize;
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+
+/// Spell format version understood by the prover
+pub const SPELL_VERSION: u64 = 8;
+
+/// How serious a finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The prover would reject this spell
+    Error,
+    /// Suspicious, but the spell can still be proved
+    Warning,
+}
+
+/// A single problem found in a spell
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpellFinding {
+    pub severity: Severity,
+    /// Stable machine-readable code, e.g. `unknown_app_alias`
+    pub code: &'static str,
+    /// Location in the spell, e.g. `ins[0].charms.$OFFER`
+    pub path: String,
+    pub message: String,
+}
+
+/// Result of validating a spell
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SpellReport {
+    pub valid: bool,
+    pub findings: Vec<SpellFinding>,
+}
+
+impl SpellReport {
+    fn from_findings(findings: Vec<SpellFinding>) -> Self {
+        let valid = !findings.iter().any(|f| f.severity == Severity::Error);
+        Self { valid, findings }
+    }
+
+    /// Findings with `Severity::Error`
+    pub fn errors(&self) -> impl Iterator<Item = &SpellFinding> {
+        self.findings.iter().filter(|f| f.severity == Severity::Error)
+    }
+
+    /// One-line summary of all errors, for logs and error messages
+    pub fn error_summary(&self) -> String {
+        self.errors()
+            .map(|f| format!("{}: {}", f.path, f.message))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+/// Parsed `tag/identity/vk` app spec
+#[derive(Debug, Clone)]
+struct AppSpec {
+    tag: char,
+}
+
+/// Collects findings while walking the spell
+#[derive(Default)]
+struct Validator {
+    findings: Vec<SpellFinding>,
+}
+
+impl Validator {
+    fn error(&mut self, code: &'static str, path: impl Into<String>, message: impl Into<String>) {
+        self.findings.push(SpellFinding {
+            severity: Severity::Error,
+            code,
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    fn warning(&mut self, code: &'static str, path: impl Into<String>, message: impl Into<String>) {
+        self.findings.push(SpellFinding {
+            severity: Severity::Warning,
+            code,
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+}
+
+/// Validate a built spell and return every finding
+pub fn validate(spell_yaml: &str) -> SpellReport {
+    let mut v = Validator::default();
+
+    for name in unresolved_variables(spell_yaml) {
+        v.error(
+            "unresolved_variable",
+            format!("${{{}}}", name),
+            format!("template variable '{}' was not substituted", name),
+        );
+    }
+
+    let spell: Value = match serde_yaml::from_str(spell_yaml) {
+        Ok(spell) => spell,
+        Err(e) => {
+            v.error("invalid_yaml", "", format!("spell is not valid YAML: {}", e));
+            return SpellReport::from_findings(v.findings);
+        }
+    };
+
+    match spell.get("version") {
+        Some(version) => match version.as_u64() {
+            Some(SPELL_VERSION) => {}
+            _ => v.error(
+                "invalid_version",
+                "version",
+                format!("expected version {}, got {}", SPELL_VERSION, render(version)),
+            ),
+        },
+        None => v.error("missing_field", "version", "spell is missing the 'version' field"),
+    }
+
+    let apps = check_apps(&mut v, spell.get("apps"));
+    let mut used: BTreeMap<String, bool> = apps.keys().map(|k| (k.clone(), false)).collect();
+
+    for section in ["public_inputs", "private_inputs"] {
+        match spell.get(section) {
+            None | Some(Value::Null) => {}
+            Some(Value::Mapping(map)) => {
+                for key in map.keys() {
+                    let path = format!("{}.{}", section, render(key));
+                    resolve_alias(&mut v, &apps, &mut used, key, &path);
+                }
+            }
+            Some(_) => v.error("invalid_field", section, format!("'{}' must be a mapping", section)),
+        }
+    }
+
+    let ins = spell.get("ins");
+    let outs = spell.get("outs");
+    let in_totals = check_utxos(&mut v, &apps, &mut used, ins, "ins");
+    let out_totals = check_utxos(&mut v, &apps, &mut used, outs, "outs");
+
+    // Token conservation; minting is allowed when the token's contract
+    // authorizes it, which only the prover can tell
+    for (alias, spec) in &apps {
+        if spec.tag != 't' {
+            continue;
+        }
+        let amount_in = in_totals.get(alias).copied().unwrap_or(0);
+        let amount_out = out_totals.get(alias).copied().unwrap_or(0);
+        if amount_out > amount_in {
+            v.warning(
+                "tokens_minted",
+                format!("apps.{}", alias),
+                format!(
+                    "outputs hold {} but inputs only provide {}; the token's contract must allow minting",
+                    amount_out, amount_in
+                ),
+            );
+        } else if amount_out < amount_in {
+            v.warning(
+                "tokens_burned",
+                format!("apps.{}", alias),
+                format!("{} tokens in inputs are not assigned to any output", amount_in - amount_out),
+            );
+        }
+    }
+
+    for (alias, was_used) in used {
+        if !was_used {
+            v.warning(
+                "unused_app",
+                format!("apps.{}", alias),
+                format!("app '{}' is declared but never referenced", alias),
+            );
+        }
+    }
+
+    SpellReport::from_findings(v.findings)
+}
+
+/// Validate the `apps` section, returning each declared alias with its tag
+fn check_apps(v: &mut Validator, apps: Option<&Value>) -> BTreeMap<String, AppSpec> {
+    let mut parsed = BTreeMap::new();
+
+    let map = match apps {
+        Some(Value::Mapping(map)) => map,
+        Some(_) => {
+            v.error("invalid_field", "apps", "'apps' must be a mapping");
+            return parsed;
+        }
+        None => {
+            v.error("missing_field", "apps", "spell is missing the 'apps' field");
+            return parsed;
+        }
+    };
+
+    for (key, value) in map {
+        let alias = render(key);
+        let path = format!("apps.{}", alias);
+        if !alias.starts_with('$') {
+            v.error("invalid_app_alias", &path, format!("app alias '{}' must start with '$'", alias));
+            continue;
+        }
+        let Some(spec) = value.as_str() else {
+            v.error("invalid_app_spec", &path, "app spec must be a 'tag/identity/vk' string");
+            continue;
+        };
+
+        let parts: Vec<&str> = spec.split('/').collect();
+        if parts.len() != 3 {
+            v.error(
+                "invalid_app_spec",
+                &path,
+                format!("expected 'tag/identity/vk', got '{}'", spec),
+            );
+            continue;
+        }
+
+        let mut tag_chars = parts[0].chars();
+        let tag = match (tag_chars.next(), tag_chars.next()) {
+            (Some(tag), None) => tag,
+            _ => {
+                v.error(
+                    "invalid_app_tag",
+                    &path,
+                    format!("tag must be a single character, got '{}'", parts[0]),
+                );
+                '?'
+            }
+        };
+        if !is_hex32(parts[1]) {
+            v.error(
+                "invalid_app_identity",
+                &path,
+                format!("identity must be 64 hex characters, got '{}'", parts[1]),
+            );
+        }
+        if !is_hex32(parts[2]) {
+            v.error(
+                "invalid_app_vk",
+                &path,
+                format!("vk must be 64 hex characters, got '{}'", parts[2]),
+            );
+        }
+
+        // Keep malformed apps resolvable so references to them are not
+        // reported a second time as unknown aliases
+        parsed.insert(alias, AppSpec { tag });
+    }
+
+    parsed
+}
+
+/// Validate `ins` or `outs` and return the token totals per alias
+fn check_utxos(
+    v: &mut Validator,
+    apps: &BTreeMap<String, AppSpec>,
+    used: &mut BTreeMap<String, bool>,
+    section: Option<&Value>,
+    name: &str,
+) -> BTreeMap<String, u128> {
+    let mut totals = BTreeMap::new();
+
+    let items = match section {
+        Some(Value::Sequence(items)) => items,
+        Some(_) => {
+            v.error("invalid_field", name, format!("'{}' must be a list", name));
+            return totals;
+        }
+        None => {
+            v.error("missing_field", name, format!("spell is missing the '{}' field", name));
+            return totals;
+        }
+    };
+
+    if items.is_empty() {
+        v.error("missing_field", name, format!("'{}' must not be empty", name));
+    }
+
+    let mut seen_utxos = BTreeMap::new();
+    for (i, item) in items.iter().enumerate() {
+        let item_path = format!("{}[{}]", name, i);
+
+        if name == "ins" {
+            match item.get("utxo_id").and_then(Value::as_str) {
+                Some(utxo_id) if is_valid_utxo_id(utxo_id) => {
+                    if let Some(first) = seen_utxos.insert(utxo_id.to_string(), i) {
+                        v.error(
+                            "duplicate_input",
+                            format!("{}.utxo_id", item_path),
+                            format!("UTXO {} is already spent by ins[{}]", utxo_id, first),
+                        );
+                    }
+                }
+                Some(utxo_id) => v.error(
+                    "invalid_utxo_id",
+                    format!("{}.utxo_id", item_path),
+                    format!("expected 'txid:vout', got '{}'", utxo_id),
+                ),
+                None => v.error(
+                    "missing_field",
+                    format!("{}.utxo_id", item_path),
+                    "input is missing 'utxo_id'",
+                ),
+            }
+        } else if item.get("address").and_then(Value::as_str).is_none_or(str::is_empty) {
+            v.error(
+                "missing_field",
+                format!("{}.address", item_path),
+                "output is missing 'address'",
+            );
+        }
+
+        let charms = match item.get("charms") {
+            None | Some(Value::Null) => continue,
+            Some(Value::Mapping(charms)) => charms,
+            Some(_) => {
+                v.error(
+                    "invalid_field",
+                    format!("{}.charms", item_path),
+                    "'charms' must be a mapping",
+                );
+                continue;
+            }
+        };
+
+        for (key, value) in charms {
+            let path = format!("{}.charms.{}", item_path, render(key));
+            let Some(spec) = resolve_alias(v, apps, used, key, &path) else {
+                continue;
+            };
+            if spec.tag != 't' {
+                continue;
+            }
+            match value.as_u64() {
+                Some(amount) => {
+                    *totals.entry(render(key)).or_insert(0u128) += amount as u128;
+                }
+                None => v.error(
+                    "invalid_token_amount",
+                    &path,
+                    format!("token amount must be a non-negative integer, got {}", render(value)),
+                ),
+            }
+        }
+    }
+
+    totals
+}
+
+/// Look up an alias in `apps`, recording an error if it is unknown
+fn resolve_alias<'a>(
+    v: &mut Validator,
+    apps: &'a BTreeMap<String, AppSpec>,
+    used: &mut BTreeMap<String, bool>,
+    key: &Value,
+    path: &str,
+) -> Option<&'a AppSpec> {
+    let alias = render(key);
+    match apps.get(&alias) {
+        Some(spec) => {
+            used.insert(alias, true);
+            Some(spec)
+        }
+        None => {
+            v.error(
+                "unknown_app_alias",
+                path,
+                format!("'{}' is not declared in 'apps'", alias),
+            );
+            None
+        }
+    }
+}
+
+/// Names of `${var}` placeholders left in the spell
+fn unresolved_variables(spell_yaml: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = spell_yaml;
+    while let Some(start) = rest.find("${") {
+        let after = &rest[start + 2..];
+        match after.find('}') {
+            Some(end) => {
+                let name = after[..end].to_string();
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+                rest = &after[end + 1..];
+            }
+            None => break,
+        }
+    }
+    names
+}
+
+/// Check a `txid:vout` UTXO id
+pub fn is_valid_utxo_id(utxo_id: &str) -> bool {
+    match utxo_id.split_once(':') {
+        Some((txid, vout)) => is_hex32(txid) && vout.parse::<u32>().is_ok(),
+        None => false,
+    }
+}
+
+/// Check a 32-byte hex string (identity, vk or txid)
+pub fn is_hex32(s: &str) -> bool {
+    s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Render a YAML scalar for messages
+fn render(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => "null".to_string(),
+        other => serde_yaml::to_string(other)
+            .map(|s| s.trim_end().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+    const VK: &str = "2222222222222222222222222222222222222222222222222222222222222222";
+    const TXID: &str = "3333333333333333333333333333333333333333333333333333333333333333";
+
+    fn spell(body: &str) -> String {
+        format!(
+            "version: 8\napps:\n  $ORDER: n/{id}/{vk}\n  $OFFER: t/{id}/{vk}\n{body}",
+            id = ID,
+            vk = VK,
+            body = body
+        )
+    }
+
+    fn codes(report: &SpellReport) -> Vec<&'static str> {
+        report.findings.iter().map(|f| f.code).collect()
+    }
+
+    #[test]
+    fn test_valid_spell_has_no_findings() {
+        let yaml = spell(&format!(
+            "public_inputs:\n  $ORDER: create\nins:\n  - utxo_id: {txid}:0\n    charms:\n      $OFFER: 100\nouts:\n  - address: tb1qexample\n    charms:\n      $ORDER:\n        status: 0\n      $OFFER: 100\n",
+            txid = TXID
+        ));
+        let report = validate(&yaml);
+        assert!(report.valid, "{:?}", report.findings);
+        assert!(report.findings.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_alias_and_unresolved_variable() {
+        let yaml = spell(&format!(
+            "public_inputs:\n  $WANT: fill\nins:\n  - utxo_id: {txid}:0\n    charms:\n      $OFFER: ${{offer_amount}}\nouts:\n  - address: tb1qexample\n",
+            txid = TXID
+        ));
+        let report = validate(&yaml);
+        assert!(!report.valid);
+        let codes = codes(&report);
+        assert!(codes.contains(&"unresolved_variable"));
+        assert!(codes.contains(&"unknown_app_alias"));
+        assert!(codes.contains(&"invalid_token_amount"));
+    }
+
+    #[test]
+    fn test_bad_app_spec_and_utxo_id() {
+        let yaml = format!(
+            "version: 8\napps:\n  $TOKEN: tt/abc/{vk}\nins:\n  - utxo_id: abc:0\nouts:\n  - address: tb1qexample\n",
+            vk = VK
+        );
+        let report = validate(&yaml);
+        let codes = codes(&report);
+        assert!(codes.contains(&"invalid_app_tag"));
+        assert!(codes.contains(&"invalid_app_identity"));
+        assert!(codes.contains(&"invalid_utxo_id"));
+    }
+
+    #[test]
+    fn test_token_conservation() {
+        let yaml = spell(&format!(
+            "ins:\n  - utxo_id: {txid}:0\n    charms:\n      $OFFER: 100\nouts:\n  - address: a\n    charms:\n      $ORDER: {{}}\n      $OFFER: 150\n",
+            txid = TXID
+        ));
+        // Mints are left to the token's contract
+        let report = validate(&yaml);
+        assert!(report.valid);
+        assert_eq!(codes(&report), vec!["tokens_minted"]);
+
+        let yaml = spell(&format!(
+            "ins:\n  - utxo_id: {txid}:0\n    charms:\n      $OFFER: 100\nouts:\n  - address: a\n    charms:\n      $ORDER: {{}}\n      $OFFER: 40\n",
+            txid = TXID
+        ));
+        let report = validate(&yaml);
+        assert!(report.valid);
+        assert_eq!(codes(&report), vec!["tokens_burned"]);
+    }
+
+    #[test]
+    fn test_duplicate_inputs() {
+        let yaml = spell(&format!(
+            "ins:\n  - utxo_id: {txid}:1\n  - utxo_id: {txid}:1\nouts:\n  - address: a\n    charms:\n      $ORDER: {{}}\n      $OFFER: 0\n",
+            txid = TXID
+        ));
+        assert!(codes(&validate(&yaml)).contains(&"duplicate_input"));
+    }
+}
@@ -0,0 +1,108 @@
+//! Background workers and their shutdown
+//!
+//! Long-running workers (proving, chain watching, confirmation tracking,
+//! expiry and matching) are spawned through [`BackgroundTasks`]. Shutdown
+//! cancels its token, which workers check between units of work, then waits
+//! a bounded time for them to finish before aborting the rest.
+
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// Handles of the running workers and the token that stops them
+#[derive(Debug, Default)]
+pub struct BackgroundTasks {
+    shutdown: CancellationToken,
+    handles: Mutex<Vec<(&'static str, JoinHandle<()>)>>,
+}
+
+impl BackgroundTasks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Token cancelled when shutdown starts
+    pub fn token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
+
+    /// Run a worker until it returns; it should return once the token is
+    /// cancelled
+    pub fn spawn<F>(&self, name: &'static str, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let handle = tokio::spawn(task);
+        self.handles.lock().expect("task list lock poisoned").push((name, handle));
+    }
+
+    /// Next message of a broadcast channel; shutdown reads as the channel
+    /// closing, which ends every worker loop
+    pub async fn recv<T: Clone>(&self, events: &mut broadcast::Receiver<T>) -> Result<T, RecvError> {
+        tokio::select! {
+            biased;
+            _ = self.shutdown.cancelled() => Err(RecvError::Closed),
+            event = events.recv() => event,
+        }
+    }
+
+    /// Stop the workers, waiting up to `timeout` before aborting stragglers
+    pub async fn shutdown(&self, timeout: Duration) {
+        self.shutdown.cancel();
+        let handles = std::mem::take(&mut *self.handles.lock().expect("task list lock poisoned"));
+        let deadline = tokio::time::Instant::now() + timeout;
+        for (name, mut handle) in handles {
+            match tokio::time::timeout_at(deadline, &mut handle).await {
+                Ok(Ok(())) => tracing::debug!("Stopped {}", name),
+                Ok(Err(e)) => tracing::error!("{} ended abnormally: {}", name, e),
+                Err(_) => {
+                    tracing::warn!("{} did not stop within {:?}, aborting it", name, timeout);
+                    handle.abort();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_shutdown_ends_worker_loops() {
+        let tasks = Arc::new(BackgroundTasks::new());
+        let (sender, mut events) = broadcast::channel::<u32>(4);
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let worker = tasks.clone();
+        let flag = stopped.clone();
+        tasks.spawn("worker", async move {
+            while worker.recv(&mut events).await != Err(RecvError::Closed) {}
+            flag.store(true, Ordering::SeqCst);
+        });
+        sender.send(1).unwrap();
+
+        tasks.shutdown(Duration::from_secs(5)).await;
+        assert!(stopped.load(Ordering::SeqCst));
+        assert!(tasks.is_shutting_down());
+    }
+
+    #[tokio::test]
+    async fn test_stuck_workers_are_aborted() {
+        let tasks = BackgroundTasks::new();
+        tasks.spawn("stuck", std::future::pending());
+
+        let started = tokio::time::Instant::now();
+        tasks.shutdown(Duration::from_millis(50)).await;
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,193 @@
+//! Token registry
+//!
+//! Orders name tokens by ticker while spells need each token's Charms app
+//! id and verification key. The registry maps one to the other and carries
+//! what clients show: name, decimals, chain and icon. It is read from memory
+//! and written through to the `tokens` table.
+
+use anyhow::Result;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use super::spell_validator::is_hex32;
+use crate::db::{self, DbPool, TokenRecord};
+
+/// Most decimals a token may have; amounts scaled by them stay within u128
+pub const MAX_DECIMALS: u32 = 18;
+
+/// VK of the tokens seeded in mock mode
+const PLACEHOLDER_VK: &str = "857ee181813511526321296bb0183b7496e1cdc0801552495464e9ec44c37718";
+
+/// Tokens seeded in mock mode so local flows work without setup
+const MOCK_TOKENS: &[(&str, &str)] = &[("BTC", "Bitcoin"), ("TOAD", "Toad Token")];
+
+/// A token to register
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewToken {
+    pub ticker: String,
+    pub name: String,
+    pub app_id: String,
+    pub vk: String,
+    pub decimals: u32,
+    pub chain: String,
+    #[serde(default)]
+    pub icon_url: Option<String>,
+}
+
+impl NewToken {
+    /// Check the fields and bring them to their stored form
+    pub fn validate(self) -> Result<Self, String> {
+        let ticker = self.ticker.trim().to_uppercase();
+        if ticker.is_empty() || ticker.len() > 20 || !ticker.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(format!("ticker must be 1 to 20 letters or digits, got '{}'", self.ticker));
+        }
+        let name = self.name.trim().to_string();
+        if name.is_empty() || name.len() > 100 {
+            return Err("name must be 1 to 100 characters".to_string());
+        }
+        let (app_id, vk) = (self.app_id.trim().to_lowercase(), self.vk.trim().to_lowercase());
+        if !is_hex32(&app_id) {
+            return Err(format!("app_id must be 32 bytes of hex, got '{}'", self.app_id));
+        }
+        if !is_hex32(&vk) {
+            return Err(format!("vk must be 32 bytes of hex, got '{}'", self.vk));
+        }
+        if self.decimals > MAX_DECIMALS {
+            return Err(format!("decimals must be at most {}, got {}", MAX_DECIMALS, self.decimals));
+        }
+        let chain = self.chain.trim().to_lowercase();
+        if chain.is_empty() {
+            return Err("chain is required".to_string());
+        }
+        let icon_url = self.icon_url.map(|url| url.trim().to_string()).filter(|url| !url.is_empty());
+        if let Some(url) = &icon_url {
+            if !(url.starts_with("https://") || url.starts_with("http://")) {
+                return Err(format!("icon_url must be an http(s) URL, got '{}'", url));
+            }
+        }
+
+        Ok(Self { ticker, name, app_id, vk, decimals: self.decimals, chain, icon_url })
+    }
+}
+
+/// Token registration failure
+#[derive(Debug, thiserror::Error)]
+pub enum TokenError {
+    /// Live orders trade the ticker; their spells need its current app
+    #[error("Token {0} is used by live orders, so its app_id and vk cannot change")]
+    InUse(String),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+/// Registered tokens by ticker
+pub struct TokenRegistry {
+    db: DbPool,
+    tokens: RwLock<BTreeMap<String, TokenRecord>>,
+}
+
+impl TokenRegistry {
+    /// Load the registry, seeding placeholder tokens into an empty one when
+    /// `seed_mock_tokens` is set
+    pub async fn load(db: DbPool, seed_mock_tokens: bool) -> Result<Self> {
+        let tokens = db::get_tokens(&db).await?;
+        let registry = Self {
+            db,
+            tokens: RwLock::new(tokens.into_iter().map(|token| (token.ticker.clone(), token)).collect()),
+        };
+
+        if seed_mock_tokens && registry.list().is_empty() {
+            for (ticker, name) in MOCK_TOKENS {
+                registry
+                    .register(NewToken {
+                        ticker: ticker.to_string(),
+                        name: name.to_string(),
+                        app_id: hex::encode(Sha256::digest(format!("mock-token:{}", ticker))),
+                        vk: PLACEHOLDER_VK.to_string(),
+                        decimals: 8,
+                        chain: "bitcoin".to_string(),
+                        icon_url: None,
+                    })
+                    .await?;
+            }
+            tracing::warn!("Token registry was empty; seeded placeholder BTC and TOAD for mock mode");
+        }
+        Ok(registry)
+    }
+
+    /// A token by ticker, in any case
+    pub fn get(&self, ticker: &str) -> Option<TokenRecord> {
+        self.tokens
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&ticker.trim().to_uppercase())
+            .cloned()
+    }
+
+    /// All tokens, by ticker
+    pub fn list(&self) -> Vec<TokenRecord> {
+        self.tokens.read().unwrap_or_else(|e| e.into_inner()).values().cloned().collect()
+    }
+
+    /// Register a validated token, replacing one with the same ticker
+    ///
+    /// A ticker that live orders use keeps its app id and VK.
+    pub async fn register(&self, token: NewToken) -> Result<TokenRecord, TokenError> {
+        let now = chrono::Utc::now();
+        let record = TokenRecord {
+            ticker: token.ticker,
+            name: token.name,
+            app_id: token.app_id,
+            vk: token.vk,
+            decimals: token.decimals as i32,
+            chain: token.chain,
+            icon_url: token.icon_url,
+            created_at: now,
+            updated_at: now,
+        };
+        let ticker = record.ticker.clone();
+        let record = db::upsert_token(&self.db, &record).await?.ok_or(TokenError::InUse(ticker))?;
+        self.tokens
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(record.ticker.clone(), record.clone());
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token() -> NewToken {
+        NewToken {
+            ticker: " toad ".to_string(),
+            name: "Toad Token".to_string(),
+            app_id: "AB".repeat(32),
+            vk: "cd".repeat(32),
+            decimals: 8,
+            chain: "Bitcoin".to_string(),
+            icon_url: Some(String::new()),
+        }
+    }
+
+    #[test]
+    fn test_validate_normalizes_fields() {
+        let token = token().validate().unwrap();
+        assert_eq!(token.ticker, "TOAD");
+        assert_eq!(token.app_id, "ab".repeat(32));
+        assert_eq!(token.chain, "bitcoin");
+        assert_eq!(token.icon_url, None);
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_fields() {
+        assert!(NewToken { ticker: "TO-AD".to_string(), ..token() }.validate().is_err());
+        assert!(NewToken { app_id: "toad-token".to_string(), ..token() }.validate().is_err());
+        assert!(NewToken { vk: "cd".repeat(31), ..token() }.validate().is_err());
+        assert!(NewToken { decimals: 19, ..token() }.validate().is_err());
+        assert!(NewToken { icon_url: Some("ftp://icons/toad.png".to_string()), ..token() }.validate().is_err());
+    }
+}
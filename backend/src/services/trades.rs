@@ -0,0 +1,247 @@
+//! Trade history and candles
+//!
+//! A trade records what a maker sold and what the taker paid. Seen from a
+//! pair `BASE/QUOTE`, a trade selling the base token is a buy by the taker
+//! and one selling the quote token a sell. Prices are quote per base in
+//! whole tokens, kept as integers of the quote token's smallest unit like
+//! the orderbook's.
+
+use serde::Serialize;
+
+use super::orderbook::{format_units, Pair};
+use crate::db::TradeRecord;
+
+/// Candle intervals and their length in seconds
+pub const INTERVALS: &[(&str, i64)] = &[
+    ("1m", 60),
+    ("5m", 300),
+    ("15m", 900),
+    ("1h", 3_600),
+    ("4h", 14_400),
+    ("1d", 86_400),
+];
+
+/// Length of a named candle interval in seconds
+pub fn interval_seconds(interval: &str) -> Option<i64> {
+    INTERVALS.iter().find(|(name, _)| *name == interval).map(|(_, seconds)| *seconds)
+}
+
+/// A trade as seen from one side of its pair
+#[derive(Debug, Clone, Serialize)]
+pub struct Trade {
+    pub id: String,
+    pub order_id: String,
+    /// `buy` when the taker bought the base token, else `sell`
+    pub side: &'static str,
+    /// Quote per base, in whole tokens
+    pub price: String,
+    /// Base tokens traded, in whole tokens
+    pub size: String,
+    /// Quote tokens traded, in whole tokens
+    pub total: String,
+    pub maker_address: String,
+    pub taker_address: String,
+    pub txid: Option<String>,
+    pub block_height: Option<i64>,
+    pub executed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Open, high, low and close of one interval
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Candle {
+    /// Start of the interval, in seconds since the epoch
+    pub time: i64,
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub close: String,
+    /// Base tokens traded, in whole tokens
+    pub volume: String,
+    /// Quote tokens traded, in whole tokens
+    pub quote_volume: String,
+    pub trades: u32,
+}
+
+/// Base amount, quote amount and whether the taker bought the base token,
+/// in smallest units
+fn oriented(trade: &TradeRecord, pair: &Pair) -> Option<(u128, u128, bool)> {
+    let (base, quote) = (pair.base.as_str(), pair.quote.as_str());
+    let offer: u128 = trade.offer_amount.trim().parse().ok()?;
+    let want: u128 = trade.want_amount.trim().parse().ok()?;
+    let (base_amount, quote_amount, buy) = if trade.offer_token.eq_ignore_ascii_case(base)
+        && trade.want_token.eq_ignore_ascii_case(quote)
+    {
+        (offer, want, true)
+    } else if trade.offer_token.eq_ignore_ascii_case(quote) && trade.want_token.eq_ignore_ascii_case(base) {
+        (want, offer, false)
+    } else {
+        return None;
+    };
+    (base_amount > 0).then_some((base_amount, quote_amount, buy))
+}
+
+/// Quote per whole base, in the quote token's smallest units
+fn price_units(base_amount: u128, quote_amount: u128, pair: &Pair) -> u128 {
+    quote_amount * 10u128.pow(pair.base_decimals) / base_amount
+}
+
+/// A trade seen from a pair; `None` for other pairs
+pub fn view(trade: &TradeRecord, pair: &Pair) -> Option<Trade> {
+    let (base_amount, quote_amount, buy) = oriented(trade, pair)?;
+    let quote_decimals = pair.quote_decimals;
+    Some(Trade {
+        id: trade.id.clone(),
+        order_id: trade.order_id.clone(),
+        side: if buy { "buy" } else { "sell" },
+        price: format_units(price_units(base_amount, quote_amount, pair) as i128, quote_decimals),
+        size: format_units(base_amount as i128, pair.base_decimals),
+        total: format_units(quote_amount as i128, quote_decimals),
+        maker_address: trade.maker_address.clone(),
+        taker_address: trade.taker_address.clone(),
+        txid: trade.txid.clone(),
+        block_height: trade.block_height,
+        executed_at: trade.executed_at,
+    })
+}
+
+/// Candles of `interval` seconds from executed trades, oldest first
+///
+/// Trades must come oldest first; intervals without trades have no candle.
+pub fn build_candles(trades: &[TradeRecord], pair: &Pair, interval: i64) -> Vec<Candle> {
+    struct Bucket {
+        time: i64,
+        open: u128,
+        high: u128,
+        low: u128,
+        close: u128,
+        volume: u128,
+        quote_volume: u128,
+        trades: u32,
+    }
+
+    let mut buckets: Vec<Bucket> = Vec::new();
+    for trade in trades {
+        let Some(executed_at) = trade.executed_at else { continue };
+        let Some((base_amount, quote_amount, _)) = oriented(trade, pair) else { continue };
+        let price = price_units(base_amount, quote_amount, pair);
+        let time = executed_at.timestamp().div_euclid(interval) * interval;
+
+        match buckets.last_mut() {
+            Some(bucket) if bucket.time == time => {
+                bucket.high = bucket.high.max(price);
+                bucket.low = bucket.low.min(price);
+                bucket.close = price;
+                bucket.volume += base_amount;
+                bucket.quote_volume += quote_amount;
+                bucket.trades += 1;
+            }
+            _ => buckets.push(Bucket {
+                time,
+                open: price,
+                high: price,
+                low: price,
+                close: price,
+                volume: base_amount,
+                quote_volume: quote_amount,
+                trades: 1,
+            }),
+        }
+    }
+
+    let (base_decimals, quote_decimals) = (pair.base_decimals, pair.quote_decimals);
+    let price = |units: u128| format_units(units as i128, quote_decimals);
+    buckets
+        .into_iter()
+        .map(|bucket| Candle {
+            time: bucket.time,
+            open: price(bucket.open),
+            high: price(bucket.high),
+            low: price(bucket.low),
+            close: price(bucket.close),
+            volume: format_units(bucket.volume as i128, base_decimals),
+            quote_volume: format_units(bucket.quote_volume as i128, quote_decimals),
+            trades: bucket.trades,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn trade(offer: (&str, u64), want: (&str, u64), seconds: i64) -> TradeRecord {
+        let executed_at = chrono::Utc.timestamp_opt(seconds, 0).unwrap();
+        TradeRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            order_id: "order".to_string(),
+            market: "BTC/TOAD".to_string(),
+            offer_token: offer.0.to_string(),
+            offer_amount: offer.1.to_string(),
+            want_token: want.0.to_string(),
+            want_amount: want.1.to_string(),
+            maker_address: "tb1qmaker".to_string(),
+            taker_address: "tb1qtaker".to_string(),
+            txid: Some("aa".repeat(32)),
+            block_height: Some(850),
+            status: "executed".to_string(),
+            created_at: executed_at,
+            executed_at: Some(executed_at),
+            prove_job_id: None,
+        }
+    }
+
+    fn pair(base: &str, quote: &str) -> Pair {
+        Pair { base: base.to_string(), quote: quote.to_string(), base_decimals: 8, quote_decimals: 8 }
+    }
+
+    #[test]
+    fn test_trade_view_from_either_side() {
+        // 100 TOAD sold for 0.1 BTC
+        let sold = trade(("TOAD", 10_000_000_000), ("BTC", 10_000_000), 0);
+        let view = view(&sold, &pair("toad", "btc")).unwrap();
+        assert_eq!((view.side, view.price.as_str(), view.size.as_str(), view.total.as_str()), ("buy", "0.001", "100", "0.1"));
+
+        let inverted = super::view(&sold, &pair("BTC", "TOAD")).unwrap();
+        assert_eq!((inverted.side, inverted.price.as_str(), inverted.size.as_str()), ("sell", "1000", "0.1"));
+        assert!(super::view(&sold, &pair("BTC", "ETH")).is_none());
+    }
+
+    #[test]
+    fn test_candles_bucket_trades_by_interval() {
+        let trades = vec![
+            // Minute 0: 0.001, 0.002, then 0.0015
+            trade(("TOAD", 10_000_000_000), ("BTC", 10_000_000), 5),
+            trade(("BTC", 2_000_000), ("TOAD", 1_000_000_000), 20),
+            trade(("TOAD", 2_000_000_000), ("BTC", 3_000_000), 59),
+            // Minute 2
+            trade(("TOAD", 1_000_000_000), ("BTC", 500_000), 130),
+        ];
+        let candles = build_candles(&trades, &pair("TOAD", "BTC"), 60);
+
+        assert_eq!(candles, vec![
+            Candle {
+                time: 0,
+                open: "0.001".to_string(),
+                high: "0.002".to_string(),
+                low: "0.001".to_string(),
+                close: "0.0015".to_string(),
+                volume: "130".to_string(),
+                quote_volume: "0.15".to_string(),
+                trades: 3,
+            },
+            Candle {
+                time: 120,
+                open: "0.0005".to_string(),
+                high: "0.0005".to_string(),
+                low: "0.0005".to_string(),
+                close: "0.0005".to_string(),
+                volume: "10".to_string(),
+                quote_volume: "0.005".to_string(),
+                trades: 1,
+            },
+        ]);
+        assert_eq!(interval_seconds("4h"), Some(14_400));
+        assert_eq!(interval_seconds("2h"), None);
+    }
+}
@@ -0,0 +1,136 @@
+//! Application state
+//!
+//! One [`AppState`] holds every service the handlers and background workers
+//! share. It is built once from the [`Config`] at startup, starts the
+//! workers, and stops them again on shutdown.
+
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::config::Config;
+use crate::db::{self, DbPool};
+use crate::routes::escrow::{self, EscrowRecord};
+use crate::routes::{matches, orders};
+use crate::services::address;
+use crate::services::app_registry::AppRegistry;
+use crate::services::auth::SessionStore;
+use crate::services::chain::{self, ChainBackend};
+use crate::services::chain_watcher::ChainWatcher;
+use crate::services::events::EventLog;
+use crate::services::prove_queue::ProveQueue;
+use crate::services::tasks::BackgroundTasks;
+use crate::services::tokens::TokenRegistry;
+use crate::services::{BitcoinService, CharmsService};
+
+/// State shared by every handler and background worker
+pub struct AppState {
+    pub config: Arc<Config>,
+    pub db: DbPool,
+    pub charms: Arc<CharmsService>,
+    /// Bitcoin Core, for its wallet and mempool
+    pub bitcoin: BitcoinService,
+    /// Chain data and broadcasting, from Core or Esplora
+    pub chain: Arc<dyn ChainBackend>,
+    pub chain_watcher: Arc<ChainWatcher>,
+    pub apps: Arc<AppRegistry>,
+    pub tokens: Arc<TokenRegistry>,
+    pub prove_queue: Arc<ProveQueue>,
+    /// Order and escrow changes, streamed to clients
+    pub events: Arc<EventLog>,
+    /// Wallet sign-in; order and escrow changes need a session
+    pub sessions: Arc<SessionStore>,
+    pub escrows: RwLock<Vec<EscrowRecord>>,
+    pub tasks: BackgroundTasks,
+}
+
+impl AppState {
+    /// Connect to the database and node and load the app and token registries
+    pub async fn new(config: Config) -> Result<Arc<Self>> {
+        let config = Arc::new(config);
+
+        let db = db::init_db(&config.database_url).await?;
+        tracing::info!("Database initialized");
+
+        let bitcoin = BitcoinService::new(config.rpc.clone())?;
+        if !config.mock_mode {
+            check_node_network(&bitcoin, &config).await?;
+        }
+        let chain = chain::from_config(&config.chain_backend, &bitcoin)?;
+        tracing::info!("Chain backend: {}", chain.name());
+        let charms = Arc::new(CharmsService::new(&config.prover_api_url, config.mock_mode));
+
+        // A VK that disagrees with its binary is fatal
+        let apps = Arc::new(AppRegistry::load(&config.apps, config.mock_mode)?);
+        let prove_queue = Arc::new(ProveQueue::new(
+            db.clone(),
+            charms.clone(),
+            apps.clone(),
+            config.prove_queue.clone(),
+        ));
+
+        // Tickers to app ids and VKs; mock mode starts with placeholder tokens
+        let tokens = Arc::new(TokenRegistry::load(db.clone(), config.mock_mode).await?);
+        tracing::info!("Token registry loaded: {} tokens", tokens.list().len());
+
+        Ok(Arc::new(Self {
+            chain_watcher: Arc::new(ChainWatcher::new(chain.clone(), config.chain_watcher.clone())),
+            events: Arc::new(EventLog::new(db.clone())),
+            sessions: Arc::new(SessionStore::new(db.clone(), config.network, config.sessions.clone())),
+            escrows: RwLock::new(Vec::new()),
+            tasks: BackgroundTasks::new(),
+            config,
+            db,
+            charms,
+            bitcoin,
+            chain,
+            apps,
+            tokens,
+            prove_queue,
+        }))
+    }
+
+    /// Start proving, watching the chain, and the workers that follow it
+    pub fn start(self: &Arc<Self>) {
+        let tasks = &self.tasks;
+        tasks.spawn("prove queue", self.prove_queue.clone().run(tasks.token()));
+        tasks.spawn(
+            "order confirmations",
+            orders::track_confirmations(self.clone(), self.chain_watcher.subscribe()),
+        );
+        tasks.spawn("order expiry", orders::expire_orders(self.clone(), self.chain_watcher.subscribe()));
+        tasks.spawn("escrow expiry", escrow::expire_escrows(self.clone(), self.chain_watcher.subscribe()));
+        tasks.spawn("matching", matches::run_matching(self.clone(), self.events.subscribe()));
+        // Last, so no worker misses the first events
+        tasks.spawn("chain watcher", self.chain_watcher.clone().run(tasks.token()));
+    }
+
+    /// Stop the workers, then close the database pool
+    pub async fn shutdown(&self) {
+        self.tasks.shutdown(self.config.shutdown_timeout).await;
+        self.db.close().await;
+        tracing::info!("Background workers stopped");
+    }
+}
+
+/// Refuse to start against a node on another network
+///
+/// An unreachable node is only warned about; calls fail until it comes up.
+async fn check_node_network(bitcoin: &BitcoinService, config: &Config) -> Result<()> {
+    match bitcoin.get_blockchain_info().await {
+        Ok(info) if info.chain != config.network.to_core_arg() => anyhow::bail!(
+            "Bitcoin node at {} is on {}, but BITCOIN_NETWORK is {}",
+            config.rpc.url,
+            info.chain,
+            address::network_name(config.network)
+        ),
+        Ok(info) => {
+            tracing::info!("Bitcoin node on {} at height {}", info.chain, info.blocks);
+            Ok(())
+        }
+        Err(e) => {
+            tracing::warn!("Bitcoin node at {} is unreachable: {}", config.rpc.url, e);
+            Ok(())
+        }
+    }
+}
@@ -216,7 +216,7 @@ function FillOrder({ orderId, chainThemes, onNavigate }) {
         await handleBroadcast(`signed_mock_${unsignedTx.txid}`);
       } else if (btcConnected && signPsbt) {
         // Sign with wallet
-        const signed = await signPsbt(unsignedTx.hex, {
+        const signed = await signPsbt(unsignedTx.psbt || unsignedTx.hex, {
           finalize: true,
           broadcast: false,
         });
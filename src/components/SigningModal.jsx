@@ -79,10 +79,10 @@ const SigningModal = ({
       // Always try wallet signing first if connected
       if (connected && typeof signPsbt === 'function') {
         console.log('Attempting wallet signing with signPsbt...');
-        console.log('PSBT hex:', unsignedTx.hex?.substring(0, 50) + '...');
+        console.log('PSBT:', (unsignedTx.psbt || unsignedTx.hex)?.substring(0, 50) + '...');
         try {
           // LaserEyes PSBT signing - this will open the wallet
-          const signedPsbt = await signPsbt(unsignedTx.hex, {
+          const signedPsbt = await signPsbt(unsignedTx.psbt || unsignedTx.hex, {
             finalize: true,
             broadcast: false,
           });
@@ -143,7 +143,7 @@ const SigningModal = ({
     try {
       const result = await broadcastOrder(orderId, signedTxHex);
       
-      if (result.status === 'confirmed' || result.status === 'success') {
+      if (['confirmed', 'broadcast', 'success'].includes(result.status)) {
         setTxResult(result);
         setStep(3);
         if (onSuccess) {
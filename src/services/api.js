@@ -7,6 +7,9 @@
 
 const API_BASE_URL = import.meta.env.VITE_API_URL || 'http://localhost:3001/api';
 
+// Session token from connectWallet, sent with every request
+let sessionToken = null;
+
 /**
  * Generic API request handler
  */
@@ -15,6 +18,7 @@ async function apiRequest(endpoint, options = {}) {
   
   const defaultHeaders = {
     'Content-Type': 'application/json',
+    ...(sessionToken ? { Authorization: `Bearer ${sessionToken}` } : {}),
   };
 
   const config = {
@@ -166,7 +170,7 @@ export async function cancelOrder(orderId) {
 /**
  * Broadcast a signed order transaction
  * @param {string} orderId - Order ID
- * @param {string} signedTxHex - Signed transaction hex
+ * @param {string} signedTxHex - Signed PSBT or transaction hex
  */
 export async function broadcastOrder(orderId, signedTxHex) {
   return apiRequest(`/orders/${orderId}/broadcast`, {
@@ -183,14 +187,26 @@ export async function broadcastOrder(orderId, signedTxHex) {
 // ============================================
 
 /**
- * Connect wallet
+ * Get the sign-in message for an address
+ * @param {string} address - Wallet address
+ * @returns {Promise<{address: string, message: string, expires_at: string}>}
+ */
+export async function getWalletChallenge(address) {
+  return apiRequest('/wallet/challenge', {
+    method: 'POST',
+    body: JSON.stringify({ address }),
+  });
+}
+
+/**
+ * Connect wallet with a signed challenge; later requests use the session
  * @param {Object} walletData - Wallet connection data
  * @param {string} walletData.address - Wallet address
- * @param {string} walletData.signature - Optional signature for verification
- * @param {string} walletData.message - Optional message that was signed
+ * @param {string} walletData.signature - Base64 BIP-322 signature of the message
+ * @param {string} walletData.message - Challenge message that was signed
  */
 export async function connectWallet(walletData) {
-  return apiRequest('/wallet/connect', {
+  const response = await apiRequest('/wallet/connect', {
     method: 'POST',
     body: JSON.stringify({
       address: walletData.address,
@@ -198,6 +214,18 @@ export async function connectWallet(walletData) {
       message: walletData.message,
     }),
   });
+  sessionToken = response.session_token;
+  return response;
+}
+
+/**
+ * End the wallet session
+ */
+export async function disconnectWallet() {
+  if (!sessionToken) return { connected: false };
+  const response = await apiRequest('/wallet/disconnect', { method: 'POST' });
+  sessionToken = null;
+  return response;
 }
 
 /**
@@ -335,7 +363,6 @@ export async function createEscrow(escrowData) {
  * @param {Object} releaseData - Release data
  * @param {string} releaseData.preimage - Preimage for hash-locked release
  * @param {string} releaseData.signature - Release signature
- * @param {string} releaseData.signerPubkey - Signer's public key
  */
 export async function releaseEscrow(escrowId, releaseData) {
   return apiRequest(`/escrows/${escrowId}/release`, {
@@ -343,7 +370,6 @@ export async function releaseEscrow(escrowId, releaseData) {
     body: JSON.stringify({
       preimage: releaseData.preimage,
       signature: releaseData.signature,
-      signer_pubkey: releaseData.signerPubkey,
     }),
   });
 }
@@ -371,7 +397,6 @@ export async function refundEscrow(escrowId, refundData) {
  * @param {Object} disputeData - Dispute data
  * @param {string} disputeData.reason - Dispute reason
  * @param {string} disputeData.evidenceHash - Optional evidence hash
- * @param {string} disputeData.initiatorPubkey - Initiator's public key
  */
 export async function disputeEscrow(escrowId, disputeData) {
   return apiRequest(`/escrows/${escrowId}/dispute`, {
@@ -379,7 +404,6 @@ export async function disputeEscrow(escrowId, disputeData) {
     body: JSON.stringify({
       reason: disputeData.reason,
       evidence_hash: disputeData.evidenceHash,
-      initiator_pubkey: disputeData.initiatorPubkey,
     }),
   });
 }
@@ -464,7 +488,9 @@ export default {
   broadcastOrder,
   
   // Wallet
+  getWalletChallenge,
   connectWallet,
+  disconnectWallet,
   getWalletBalance,
   getWalletUtxos,
   getNewAddress,
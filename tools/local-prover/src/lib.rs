@@ -0,0 +1,467 @@
+//! Liquid Nation Local Prover
+//!
+//! A stand-in for the Charms prover API. It accepts the same
+//! `SpellProveRequest` the backend sends, runs the app contracts natively
+//! through the spell simulator, and answers with a commit/spell transaction
+//! pair that is structurally valid but carries no proof.
+//!
+//! The commit output is a taproot output whose only script leaf embeds the
+//! spell in an `OP_FALSE OP_IF ... OP_ENDIF` envelope followed by `OP_TRUE`,
+//! so the spell transaction can spend it without a signature. That is fine
+//! on regtest and nowhere else.
+//!
+//! Contracts are not run from the request's binaries: every binary must be
+//! for a VK the simulator has a native contract for, and `chain` must be
+//! `bitcoin` or the prover's own network.
+
+use anyhow::{anyhow, Context, Result};
+use bitcoin::absolute::LockTime;
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::opcodes::all::{OP_ENDIF, OP_IF, OP_PUSHNUM_1};
+use bitcoin::opcodes::OP_FALSE;
+use bitcoin::script::{Builder, PushBytesBuf};
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::taproot::{LeafVersion, TaprootBuilder};
+use bitcoin::transaction::Version;
+use charms_sdk::data::B32;
+use bitcoin::{
+    Address, Amount, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid,
+    Witness,
+};
+use liquid_spell_simulator::{parse_spell, SimulationReport, Simulator};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// Value given to spell outputs that do not set `sats`
+pub const DEFAULT_OUTPUT_SATS: u64 = 1000;
+
+/// Outputs below this are dropped or rejected
+pub const DUST_LIMIT_SATS: u64 = 330;
+
+/// BIP-341 "nothing up my sleeve" point, used as the commit internal key
+const NUMS_KEY: &str = "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+/// Witness bytes added per unsigned key-path input when estimating fees
+const KEY_PATH_WITNESS_VBYTES: u64 = 17;
+
+/// Request body of `POST /spells/prove` (mirrors the backend's `SpellProveRequest`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProveRequest {
+    pub spell: serde_json::Value,
+    #[serde(default)]
+    pub binaries: BTreeMap<String, Vec<u8>>,
+    #[serde(default)]
+    pub prev_txs: Vec<String>,
+    pub funding_utxo: String,
+    pub funding_utxo_value: u64,
+    pub change_address: String,
+    pub fee_rate: f64,
+    pub chain: String,
+}
+
+/// One transaction of the response, as the backend expects it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProvedTransaction {
+    pub hex: String,
+    pub txid: String,
+}
+
+/// Why a request could not be proved
+#[derive(Debug)]
+pub enum ProveError {
+    /// The request or spell is malformed
+    BadRequest(String),
+    /// An app contract rejected the spell
+    Rejected(SimulationReport),
+}
+
+impl fmt::Display for ProveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProveError::BadRequest(msg) => write!(f, "{}", msg),
+            ProveError::Rejected(report) => {
+                let failures: Vec<String> = report
+                    .apps
+                    .iter()
+                    .filter(|a| !a.passed)
+                    .map(|a| format!("{}: {}", a.alias, a.failed_checks.join(" <- ")))
+                    .collect();
+                write!(f, "spell rejected by app contracts: {}", failures.join("; "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProveError {}
+
+impl From<anyhow::Error> for ProveError {
+    fn from(e: anyhow::Error) -> Self {
+        ProveError::BadRequest(format!("{:#}", e))
+    }
+}
+
+/// Local prover
+pub struct LocalProver {
+    network: Network,
+    simulator: Simulator,
+}
+
+impl LocalProver {
+    /// Create a prover for `network` that runs contracts with `simulator`
+    pub fn new(network: Network, simulator: Simulator) -> Self {
+        Self { network, simulator }
+    }
+
+    /// Check the spell and build the unproven commit and spell transactions
+    pub fn prove(&self, req: &ProveRequest) -> Result<Vec<ProvedTransaction>, ProveError> {
+        if !(req.fee_rate.is_finite() && req.fee_rate > 0.0) {
+            return Err(ProveError::BadRequest(format!("invalid fee_rate {}", req.fee_rate)));
+        }
+        if !self.supports_chain(&req.chain) {
+            return Err(ProveError::BadRequest(format!(
+                "unsupported chain '{}'; this prover handles bitcoin on {}",
+                req.chain, self.network
+            )));
+        }
+        for vk in req.binaries.keys() {
+            let known = B32::from_str(vk).map(|vk| self.simulator.has_contract(&vk)).unwrap_or(false);
+            if !known {
+                return Err(ProveError::BadRequest(format!(
+                    "no native contract for the binary with vk {}; set SWAP_APP_VK or ESCROW_APP_VK",
+                    vk
+                )));
+            }
+        }
+
+        // JSON is valid YAML, so the simulator can read the spell as sent
+        let spell_json = serde_json::to_string(&req.spell).context("spell is not serializable")?;
+        let view = parse_spell(&spell_json, &req.prev_txs)?;
+        let report = self.simulator.run(&view);
+        if !report.passed {
+            return Err(ProveError::Rejected(report));
+        }
+        for warning in &report.warnings {
+            tracing::warn!("{}", warning);
+        }
+
+        let (commit_tx, spell_tx) = self.build_transactions(req, spell_json.as_bytes())?;
+        Ok(vec![
+            ProvedTransaction {
+                hex: serialize_hex(&commit_tx),
+                txid: commit_tx.compute_txid().to_string(),
+            },
+            ProvedTransaction {
+                hex: serialize_hex(&spell_tx),
+                txid: spell_tx.compute_txid().to_string(),
+            },
+        ])
+    }
+
+    /// `bitcoin`, or the name of the prover's network
+    fn supports_chain(&self, chain: &str) -> bool {
+        let chain = chain.trim().to_lowercase();
+        chain == "bitcoin"
+            || chain == self.network.to_string()
+            || (chain == "mainnet" && self.network == Network::Bitcoin)
+    }
+
+    fn build_transactions(&self, req: &ProveRequest, spell: &[u8]) -> Result<(Transaction, Transaction)> {
+        let secp = Secp256k1::verification_only();
+        let spell_value = &req.spell;
+
+        let funding = OutPoint::from_str(&req.funding_utxo)
+            .map_err(|e| anyhow!("invalid funding_utxo '{}': {}", req.funding_utxo, e))?;
+        let change_script = self.script_for(&req.change_address)?;
+
+        // Commit output: taproot with a single leaf carrying the spell
+        let leaf = envelope_script(spell)?;
+        let internal_key = XOnlyPublicKey::from_str(NUMS_KEY).expect("NUMS key is valid");
+        let spend_info = TaprootBuilder::new()
+            .add_leaf(0, leaf.clone())
+            .map_err(|e| anyhow!("taproot builder: {:?}", e))?
+            .finalize(&secp, internal_key)
+            .map_err(|_| anyhow!("failed to finalize taproot tree"))?;
+        let control_block = spend_info
+            .control_block(&(leaf.clone(), LeafVersion::TapScript))
+            .ok_or_else(|| anyhow!("missing control block for spell leaf"))?;
+        let commit_script = ScriptBuf::new_p2tr_tweaked(spend_info.output_key());
+
+        // Spell transaction: spell inputs plus the commit output
+        let prev_txs = decode_prev_txs(&req.prev_txs)?;
+        let mut spell_inputs = Vec::new();
+        let mut known_input_value = 0u64;
+        for (i, item) in sequence(spell_value, "ins")?.iter().enumerate() {
+            let utxo_id = item
+                .get("utxo_id")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("ins[{}] is missing 'utxo_id'", i))?;
+            let outpoint = OutPoint::from_str(utxo_id)
+                .map_err(|e| anyhow!("ins[{}].utxo_id '{}': {}", i, utxo_id, e))?;
+            if let Some(out) = prev_txs.get(&outpoint.txid).and_then(|tx| tx.output.get(outpoint.vout as usize)) {
+                known_input_value += out.value.to_sat();
+            }
+            spell_inputs.push(unsigned_input(outpoint));
+        }
+
+        let mut spell_outputs = Vec::new();
+        for (i, item) in sequence(spell_value, "outs")?.iter().enumerate() {
+            let address = item
+                .get("address")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("outs[{}] is missing 'address'", i))?;
+            let sats = item.get("sats").and_then(|v| v.as_u64()).unwrap_or(DEFAULT_OUTPUT_SATS);
+            spell_outputs.push(TxOut {
+                value: Amount::from_sat(sats),
+                script_pubkey: self
+                    .script_for(address)
+                    .with_context(|| format!("outs[{}].address", i))?,
+            });
+        }
+        let outputs_value: u64 = spell_outputs.iter().map(|o| o.value.to_sat()).sum();
+        // Change for inputs worth more than the outputs and fee; dropped below dust
+        spell_outputs.push(TxOut { value: Amount::ZERO, script_pubkey: change_script.clone() });
+
+        let mut commit_input = unsigned_input(OutPoint::null());
+        let mut witness = Witness::new();
+        witness.push(leaf.as_bytes());
+        witness.push(control_block.serialize());
+        commit_input.witness = witness;
+
+        let unsigned_spell_inputs = spell_inputs.len() as u64;
+        spell_inputs.push(commit_input);
+        let mut spell_tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: spell_inputs,
+            output: spell_outputs,
+        };
+        let spell_fee = fee_for(&spell_tx, unsigned_spell_inputs, req.fee_rate);
+        let commit_value = (outputs_value + spell_fee)
+            .saturating_sub(known_input_value)
+            .max(DUST_LIMIT_SATS);
+        let spell_change = (known_input_value + commit_value).saturating_sub(outputs_value + spell_fee);
+        if spell_change >= DUST_LIMIT_SATS {
+            let last = spell_tx.output.len() - 1;
+            spell_tx.output[last].value = Amount::from_sat(spell_change);
+        } else {
+            spell_tx.output.pop();
+        }
+
+        // Commit transaction: funding UTXO -> commit output (+ change)
+        let mut commit_tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![unsigned_input(funding)],
+            output: vec![
+                TxOut { value: Amount::from_sat(commit_value), script_pubkey: commit_script },
+                TxOut { value: Amount::ZERO, script_pubkey: change_script },
+            ],
+        };
+        let commit_fee = fee_for(&commit_tx, 1, req.fee_rate);
+        let change = req
+            .funding_utxo_value
+            .checked_sub(commit_value + commit_fee)
+            .ok_or_else(|| {
+                anyhow!(
+                    "funding UTXO holds {} sats but {} are needed",
+                    req.funding_utxo_value,
+                    commit_value + commit_fee
+                )
+            })?;
+        if change >= DUST_LIMIT_SATS {
+            commit_tx.output[1].value = Amount::from_sat(change);
+        } else {
+            commit_tx.output.pop();
+        }
+
+        let last = spell_tx.input.len() - 1;
+        spell_tx.input[last].previous_output = OutPoint::new(commit_tx.compute_txid(), 0);
+
+        Ok((commit_tx, spell_tx))
+    }
+
+    fn script_for(&self, address: &str) -> Result<ScriptBuf> {
+        let address = Address::from_str(address)
+            .map_err(|e| anyhow!("invalid address '{}': {}", address, e))?
+            .require_network(self.network)
+            .map_err(|e| anyhow!("address '{}': {}", address, e))?;
+        Ok(address.script_pubkey())
+    }
+}
+
+/// `OP_FALSE OP_IF "spell" <data...> OP_ENDIF OP_TRUE`
+fn envelope_script(spell: &[u8]) -> Result<ScriptBuf> {
+    let mut builder = Builder::new()
+        .push_opcode(OP_FALSE)
+        .push_opcode(OP_IF)
+        .push_slice(b"spell");
+    for chunk in spell.chunks(520) {
+        let push = PushBytesBuf::try_from(chunk.to_vec()).map_err(|e| anyhow!("{:?}", e))?;
+        builder = builder.push_slice(push);
+    }
+    Ok(builder.push_opcode(OP_ENDIF).push_opcode(OP_PUSHNUM_1).into_script())
+}
+
+fn unsigned_input(previous_output: OutPoint) -> TxIn {
+    TxIn {
+        previous_output,
+        script_sig: ScriptBuf::new(),
+        sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+        witness: Witness::new(),
+    }
+}
+
+/// Fee for `tx` at `fee_rate` sat/vB, assuming key-path witnesses for
+/// `unsigned_inputs` inputs that are not signed yet
+fn fee_for(tx: &Transaction, unsigned_inputs: u64, fee_rate: f64) -> u64 {
+    let vsize = tx.vsize() as u64 + unsigned_inputs * KEY_PATH_WITNESS_VBYTES;
+    (vsize as f64 * fee_rate).ceil() as u64
+}
+
+fn decode_prev_txs(prev_txs: &[String]) -> Result<HashMap<Txid, Transaction>> {
+    prev_txs
+        .iter()
+        .enumerate()
+        .map(|(i, hex)| {
+            let tx: Transaction = deserialize_hex(hex.trim())
+                .map_err(|e| anyhow!("prev_txs[{}] is not a valid transaction: {}", i, e))?;
+            Ok((tx.compute_txid(), tx))
+        })
+        .collect()
+}
+
+fn sequence<'a>(spell: &'a serde_json::Value, section: &str) -> Result<&'a Vec<serde_json::Value>> {
+    spell
+        .get(section)
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| anyhow!("spell is missing the '{}' list", section))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::consensus::encode::deserialize_hex;
+
+    const ADDR: &str = "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080";
+    const FUNDING: &str = "4444444444444444444444444444444444444444444444444444444444444444:1";
+
+    fn request(outs: serde_json::Value) -> ProveRequest {
+        ProveRequest {
+            spell: serde_json::json!({
+                "version": 8,
+                "apps": {
+                    "$TOKEN": "t/1111111111111111111111111111111111111111111111111111111111111111/2222222222222222222222222222222222222222222222222222222222222222"
+                },
+                "ins": [{
+                    "utxo_id": "3333333333333333333333333333333333333333333333333333333333333333:0",
+                    "charms": { "$TOKEN": 100 }
+                }],
+                "outs": outs,
+            }),
+            binaries: BTreeMap::new(),
+            prev_txs: vec![],
+            funding_utxo: FUNDING.to_string(),
+            funding_utxo_value: 50_000,
+            change_address: ADDR.to_string(),
+            fee_rate: 2.0,
+            chain: "bitcoin".to_string(),
+        }
+    }
+
+    fn prover() -> LocalProver {
+        LocalProver::new(Network::Regtest, Simulator::new())
+    }
+
+    #[test]
+    fn test_builds_commit_and_spell_pair() {
+        let req = request(serde_json::json!([{ "address": ADDR, "charms": { "$TOKEN": 100 } }]));
+        let txs = prover().prove(&req).unwrap();
+        assert_eq!(txs.len(), 2);
+
+        let commit: Transaction = deserialize_hex(&txs[0].hex).unwrap();
+        let spell: Transaction = deserialize_hex(&txs[1].hex).unwrap();
+        assert_eq!(commit.compute_txid().to_string(), txs[0].txid);
+        assert_eq!(commit.input[0].previous_output, OutPoint::from_str(FUNDING).unwrap());
+        assert!(commit.output[0].script_pubkey.is_p2tr());
+
+        // Spell tx spends the charm input and the commit output
+        assert_eq!(spell.input.len(), 2);
+        assert_eq!(spell.input[1].previous_output, OutPoint::new(commit.compute_txid(), 0));
+        assert_eq!(spell.input[1].witness.len(), 2);
+        assert_eq!(spell.output[0].value, Amount::from_sat(DEFAULT_OUTPUT_SATS));
+
+        // Funding covers commit value, fee and change exactly
+        assert_eq!(commit.output.len(), 2);
+        let commit_out: u64 = commit.output.iter().map(|o| o.value.to_sat()).sum();
+        let commit_fee = fee_for(&commit, 1, 2.0);
+        assert!(commit_fee > 0);
+        assert_eq!(commit_out + commit_fee, 50_000);
+    }
+
+    #[test]
+    fn test_spell_change_goes_to_change_address() {
+        let prev = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![unsigned_input(OutPoint::from_str(FUNDING).unwrap())],
+            output: vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: prover().script_for(ADDR).unwrap(),
+            }],
+        };
+        let mut req = request(serde_json::json!([{ "address": ADDR, "charms": { "$TOKEN": 100 } }]));
+        req.spell["ins"][0]["utxo_id"] = format!("{}:0", prev.compute_txid()).into();
+        req.prev_txs = vec![serialize_hex(&prev)];
+        let txs = prover().prove(&req).unwrap();
+
+        let commit: Transaction = deserialize_hex(&txs[0].hex).unwrap();
+        let spell: Transaction = deserialize_hex(&txs[1].hex).unwrap();
+        assert_eq!(spell.output.len(), 2);
+        assert_eq!(spell.output[1].script_pubkey, prover().script_for(ADDR).unwrap());
+
+        // Inputs cover outputs, change and fee exactly
+        let spell_out: u64 = spell.output.iter().map(|o| o.value.to_sat()).sum();
+        let spell_in = 100_000 + commit.output[0].value.to_sat();
+        assert_eq!(spell_out + fee_for(&spell, 1, 2.0), spell_in);
+    }
+
+    #[test]
+    fn test_rejects_unsupported_chain_and_unknown_binaries() {
+        let outs = serde_json::json!([{ "address": ADDR, "charms": { "$TOKEN": 100 } }]);
+        assert!(prover().prove(&ProveRequest { chain: "regtest".to_string(), ..request(outs.clone()) }).is_ok());
+        let req = ProveRequest { chain: "cardano".to_string(), ..request(outs.clone()) };
+        assert!(matches!(prover().prove(&req), Err(ProveError::BadRequest(_))));
+
+        let mut req = request(outs);
+        req.binaries.insert("22".repeat(32), vec![0, 97, 115, 109]);
+        assert!(matches!(prover().prove(&req), Err(ProveError::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_rejects_unbalanced_spell() {
+        let req = request(serde_json::json!([{ "address": ADDR, "charms": { "$TOKEN": 150 } }]));
+        match prover().prove(&req) {
+            Err(ProveError::Rejected(report)) => assert!(!report.passed),
+            other => panic!("expected rejection, got {:?}", other.map(|t| t.len())),
+        }
+    }
+
+    #[test]
+    fn test_rejects_wrong_network_address() {
+        let req = request(serde_json::json!([{
+            "address": "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx",
+            "charms": { "$TOKEN": 100 }
+        }]));
+        assert!(matches!(prover().prove(&req), Err(ProveError::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_insufficient_funding() {
+        let mut req = request(serde_json::json!([{ "address": ADDR, "charms": { "$TOKEN": 100 } }]));
+        req.funding_utxo_value = 500;
+        assert!(matches!(prover().prove(&req), Err(ProveError::BadRequest(_))));
+    }
+}
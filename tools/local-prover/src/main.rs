@@ -0,0 +1,80 @@
+//! Liquid Nation Local Prover server
+//!
+//! Serves `POST /spells/prove` on `LOCAL_PROVER_ADDR` (default 127.0.0.1:3002).
+//! Point the backend at it with
+//! `CHARMS_PROVE_API_URL=http://127.0.0.1:3002/spells/prove` and `MOCK_MODE=false`.
+//!
+//! Environment:
+//!   BITCOIN_NETWORK  network for output addresses (default regtest)
+//!   SWAP_APP_VK      vk whose apps run the swap contract
+//!   ESCROW_APP_VK    vk whose apps run the escrow contract
+
+use anyhow::{Context, Result};
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use bitcoin::Network;
+use charms_sdk::data::B32;
+use liquid_local_prover::{LocalProver, ProveError, ProveRequest, ProvedTransaction};
+use liquid_spell_simulator::{Contract, Simulator};
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "liquid_local_prover=info,local_prover=info".into()),
+        )
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+
+    let network = std::env::var("BITCOIN_NETWORK").unwrap_or_else(|_| "regtest".to_string());
+    let network = Network::from_str(&network)
+        .with_context(|| format!("invalid BITCOIN_NETWORK '{}'", network))?;
+
+    let mut simulator = Simulator::new();
+    for (var, contract) in [("SWAP_APP_VK", Contract::Swap), ("ESCROW_APP_VK", Contract::Escrow)] {
+        if let Ok(vk) = std::env::var(var) {
+            let vk = B32::from_str(&vk).with_context(|| format!("invalid {} '{}'", var, vk))?;
+            simulator = simulator.with_contract(vk, contract);
+        } else {
+            tracing::warn!("{} not set; those apps will be checked as simple transfers", var);
+        }
+    }
+
+    let prover = Arc::new(LocalProver::new(network, simulator));
+    let app = Router::new()
+        .route("/spells/prove", post(prove))
+        .with_state(prover);
+
+    let addr = std::env::var("LOCAL_PROVER_ADDR").unwrap_or_else(|_| "127.0.0.1:3002".to_string());
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    tracing::info!("🧪 Local prover listening on http://{} ({})", addr, network);
+    tracing::warn!("Transactions from this prover carry no proofs; use on regtest only");
+
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn prove(
+    State(prover): State<Arc<LocalProver>>,
+    Json(req): Json<ProveRequest>,
+) -> Result<Json<Vec<ProvedTransaction>>, (StatusCode, String)> {
+    tracing::info!("Prove request for funding UTXO {}", req.funding_utxo);
+
+    match prover.prove(&req) {
+        Ok(txs) => {
+            tracing::info!("Built commit {} and spell {}", txs[0].txid, txs[1].txid);
+            Ok(Json(txs))
+        }
+        Err(e) => {
+            tracing::warn!("Rejected prove request: {}", e);
+            let status = match e {
+                ProveError::BadRequest(_) => StatusCode::BAD_REQUEST,
+                ProveError::Rejected(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            };
+            Err((status, e.to_string()))
+        }
+    }
+}
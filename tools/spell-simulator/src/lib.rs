@@ -0,0 +1,398 @@
+//! Liquid Nation Spell Simulator
+//!
+//! Builds the charms `Transaction` view of a spell and runs the swap and
+//! escrow `app_contract`s natively, so spells can be checked offline
+//! instead of paying for a remote proof.
+//!
+//! Apps are matched to contracts by verification key. Token and NFT apps
+//! without a registered contract are checked as simple transfers, the same
+//! way the prover treats apps that come without a binary.
+
+use anyhow::{anyhow, bail, Context, Result};
+use bitcoin::consensus::encode::deserialize_hex;
+use bitcoin::hashes::Hash;
+use charms_sdk::data::{
+    is_simple_transfer, App, Charms, Data, NativeOutput, Transaction, TxId, UtxoId, B32, NFT,
+    TOKEN,
+};
+use serde::Serialize;
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+
+/// Native contract implementations known to the simulator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Contract {
+    Swap,
+    Escrow,
+}
+
+impl Contract {
+    /// Run the contract and return whether it passed plus the failed checks
+    fn run(&self, app: &App, tx: &Transaction, x: &Data, w: &Data) -> (bool, Vec<&'static str>) {
+        match self {
+            Contract::Swap => {
+                liquid_check_trace::take();
+                let ok = liquid_swap_app::app_contract(app, tx, x, w);
+                (ok, liquid_check_trace::take())
+            }
+            Contract::Escrow => {
+                liquid_check_trace::take();
+                let ok = liquid_escrow_app::app_contract(app, tx, x, w);
+                (ok, liquid_check_trace::take())
+            }
+        }
+    }
+}
+
+/// A spell parsed into what the contracts see
+#[derive(Debug, Clone)]
+pub struct SpellView {
+    /// `$ALIAS` -> app
+    pub apps: BTreeMap<String, App>,
+    pub tx: Transaction,
+    pub private_inputs: BTreeMap<App, Data>,
+    /// Problems that do not stop the simulation (e.g. missing prev txs)
+    pub warnings: Vec<String>,
+}
+
+/// Outcome of running one app's contract
+#[derive(Debug, Clone, Serialize)]
+pub struct AppOutcome {
+    pub alias: String,
+    pub app: String,
+    /// Contract that ran, or `None` for a simple transfer check
+    pub contract: Option<Contract>,
+    pub passed: bool,
+    /// Failed `check!` conditions, innermost first
+    pub failed_checks: Vec<String>,
+}
+
+/// Result of simulating a whole spell
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulationReport {
+    pub passed: bool,
+    pub apps: Vec<AppOutcome>,
+    pub warnings: Vec<String>,
+}
+
+/// Runs app contracts against spells
+#[derive(Debug, Clone, Default)]
+pub struct Simulator {
+    contracts: BTreeMap<B32, Contract>,
+}
+
+impl Simulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `contract` for every app with verification key `vk`
+    pub fn with_contract(mut self, vk: B32, contract: Contract) -> Self {
+        self.contracts.insert(vk, contract);
+        self
+    }
+
+    /// Whether apps with verification key `vk` run a native contract
+    pub fn has_contract(&self, vk: &B32) -> bool {
+        self.contracts.contains_key(vk)
+    }
+
+    /// Simulate a built spell against its previous transactions (raw hex)
+    pub fn simulate(&self, spell_yaml: &str, prev_txs: &[String]) -> Result<SimulationReport> {
+        let view = parse_spell(spell_yaml, prev_txs)?;
+        Ok(self.run(&view))
+    }
+
+    /// Run every app in an already parsed spell
+    pub fn run(&self, view: &SpellView) -> SimulationReport {
+        let empty = Data::empty();
+        let mut apps = Vec::new();
+
+        for (alias, app) in &view.apps {
+            let x = view.tx.app_public_inputs.get(app).unwrap_or(&empty);
+            let w = view.private_inputs.get(app).unwrap_or(&empty);
+            let contract = self.contracts.get(&app.vk).copied();
+
+            let (passed, failed_checks) = match contract {
+                Some(contract) => {
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        contract.run(app, &view.tx, x, w)
+                    }));
+                    match result {
+                        Ok((passed, checks)) => {
+                            (passed, checks.into_iter().map(str::to_string).collect())
+                        }
+                        Err(payload) => (false, vec![format!("panicked: {}", panic_message(&payload))]),
+                    }
+                }
+                None if app.tag == TOKEN || app.tag == NFT => {
+                    let passed = is_simple_transfer(app, &view.tx);
+                    let checks = if passed {
+                        vec![]
+                    } else {
+                        vec!["is_simple_transfer(app, tx)".to_string()]
+                    };
+                    (passed, checks)
+                }
+                None => (false, vec![format!("no contract registered for vk {}", app.vk)]),
+            };
+
+            apps.push(AppOutcome {
+                alias: alias.clone(),
+                app: app.to_string(),
+                contract,
+                passed,
+                failed_checks,
+            });
+        }
+
+        SimulationReport {
+            passed: apps.iter().all(|a| a.passed),
+            apps,
+            warnings: view.warnings.clone(),
+        }
+    }
+}
+
+/// Parse a spell and its previous transactions into the contract view
+pub fn parse_spell(spell_yaml: &str, prev_txs: &[String]) -> Result<SpellView> {
+    let spell: Value = serde_yaml::from_str(spell_yaml).context("spell is not valid YAML")?;
+    let mut warnings = Vec::new();
+
+    let mut apps = BTreeMap::new();
+    let app_map = spell
+        .get("apps")
+        .and_then(Value::as_mapping)
+        .ok_or_else(|| anyhow!("spell is missing the 'apps' mapping"))?;
+    for (alias, spec) in app_map {
+        let alias = alias_name(alias)?;
+        let spec = spec
+            .as_str()
+            .ok_or_else(|| anyhow!("apps.{}: expected 'tag/identity/vk'", alias))?;
+        let app = App::from_str(spec).with_context(|| format!("apps.{}", alias))?;
+        apps.insert(alias, app);
+    }
+
+    let public_inputs = app_data(&spell, "public_inputs", &apps)?;
+    let private_inputs = app_data(&spell, "private_inputs", &apps)?;
+
+    // Every app is visible to the contracts, with or without a public input
+    let app_public_inputs = apps
+        .values()
+        .map(|app| (app.clone(), public_inputs.get(app).cloned().unwrap_or_default()))
+        .collect();
+
+    let mut prev = BTreeMap::new();
+    let mut prev_data = BTreeMap::new();
+    for (i, tx_hex) in prev_txs.iter().enumerate() {
+        let tx: bitcoin::Transaction = deserialize_hex(tx_hex.trim())
+            .map_err(|e| anyhow!("prev_txs[{}] is not a valid transaction: {}", i, e))?;
+        let txid = TxId(tx.compute_txid().to_byte_array());
+        prev_data.insert(txid, Data::from(&tx_hex.trim().to_string()));
+        prev.insert(txid, tx);
+    }
+
+    let mut ins = Vec::new();
+    let mut coin_ins = Some(Vec::new());
+    for (i, item) in sequence(&spell, "ins")?.iter().enumerate() {
+        let utxo_id = item
+            .get("utxo_id")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("ins[{}] is missing 'utxo_id'", i))?;
+        let utxo_id = UtxoId::from_str(utxo_id).with_context(|| format!("ins[{}].utxo_id", i))?;
+
+        match prev.get(&utxo_id.0) {
+            Some(tx) => {
+                let out = tx.output.get(utxo_id.1 as usize).ok_or_else(|| {
+                    anyhow!("ins[{}] spends output {} but {} has only {}", i, utxo_id.1, utxo_id.0, tx.output.len())
+                })?;
+                if let Some(coins) = coin_ins.as_mut() {
+                    coins.push(NativeOutput {
+                        amount: out.value.to_sat(),
+                        dest: out.script_pubkey.to_bytes(),
+                    });
+                }
+            }
+            None => {
+                warnings.push(format!("ins[{}]: previous transaction {} not provided", i, utxo_id.0));
+                coin_ins = None;
+            }
+        }
+
+        ins.push((utxo_id, charms(item, &apps, &format!("ins[{}]", i))?));
+    }
+
+    let mut outs = Vec::new();
+    for (i, item) in sequence(&spell, "outs")?.iter().enumerate() {
+        outs.push(charms(item, &apps, &format!("outs[{}]", i))?);
+    }
+
+    let tx = Transaction {
+        ins,
+        refs: vec![],
+        outs,
+        coin_ins,
+        coin_outs: None,
+        prev_txs: prev_data,
+        app_public_inputs,
+    };
+
+    Ok(SpellView { apps, tx, private_inputs, warnings })
+}
+
+/// Read `public_inputs` / `private_inputs` into app -> data
+fn app_data(
+    spell: &Value,
+    section: &str,
+    apps: &BTreeMap<String, App>,
+) -> Result<BTreeMap<App, Data>> {
+    let mut result = BTreeMap::new();
+    let Some(map) = spell.get(section).and_then(Value::as_mapping) else {
+        return Ok(result);
+    };
+    for (alias, value) in map {
+        let alias = alias_name(alias)?;
+        let app = apps
+            .get(&alias)
+            .ok_or_else(|| anyhow!("{}.{}: unknown app alias", section, alias))?;
+        result.insert(app.clone(), Data::from(value));
+    }
+    Ok(result)
+}
+
+/// Read the `charms` of one input or output
+fn charms(item: &Value, apps: &BTreeMap<String, App>, path: &str) -> Result<Charms> {
+    let mut result = Charms::new();
+    let Some(map) = item.get("charms").and_then(Value::as_mapping) else {
+        return Ok(result);
+    };
+    for (alias, value) in map {
+        let alias = alias_name(alias)?;
+        let app = apps
+            .get(&alias)
+            .ok_or_else(|| anyhow!("{}.charms.{}: unknown app alias", path, alias))?;
+        result.insert(app.clone(), Data::from(value));
+    }
+    Ok(result)
+}
+
+fn sequence<'a>(spell: &'a Value, section: &str) -> Result<&'a Vec<Value>> {
+    spell
+        .get(section)
+        .and_then(Value::as_sequence)
+        .ok_or_else(|| anyhow!("spell is missing the '{}' list", section))
+}
+
+fn alias_name(key: &Value) -> Result<String> {
+    match key.as_str() {
+        Some(alias) if alias.starts_with('$') => Ok(alias.to_string()),
+        _ => bail!("app alias must be a '$NAME' string, got {:?}", key),
+    }
+}
+
+fn panic_message(payload: &Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    const VK: &str = "857ee181813511526321296bb0183b7496e1cdc0801552495464e9ec44c37718";
+    const TOKEN_ID: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+    const UTXO: &str = "3333333333333333333333333333333333333333333333333333333333333333:0";
+
+    fn b32_yaml(byte: u8) -> String {
+        format!("[{}]", vec![byte.to_string(); 32].join(", "))
+    }
+
+    fn create_order_spell(status: u8) -> String {
+        let identity: [u8; 32] = Sha256::digest(UTXO).into();
+        format!(
+            r#"
+version: 8
+apps:
+  $ORDER: n/{identity}/{vk}
+  $OFFER: t/{token}/{vk}
+public_inputs:
+  $ORDER: "create"
+private_inputs:
+  $ORDER: "{utxo}"
+ins:
+  - utxo_id: {utxo}
+    charms:
+      $OFFER: 1000
+outs:
+  - address: tb1qescrow
+    charms:
+      $ORDER:
+        maker_pubkey: [2, 3, 4]
+        offer_app_id: {offer}
+        offer_amount: 1000
+        want_app_id: {want}
+        want_amount: 500
+        dest_chain: 0
+        dest_address: []
+        expiry_height: 900000
+        allow_partial: false
+        status: {status}
+        filled_amount: 0
+      $OFFER: 1000
+"#,
+            identity = B32(identity),
+            vk = VK,
+            token = TOKEN_ID,
+            utxo = UTXO,
+            offer = b32_yaml(1),
+            want = b32_yaml(2),
+            status = status,
+        )
+    }
+
+    fn simulator() -> Simulator {
+        Simulator::new().with_contract(B32::from_str(VK).unwrap(), Contract::Swap)
+    }
+
+    #[test]
+    fn test_create_order_passes() {
+        let report = simulator().simulate(&create_order_spell(0), &[]).unwrap();
+        assert!(report.passed, "{:#?}", report.apps);
+        assert_eq!(report.apps.len(), 2);
+        assert_eq!(report.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_reports_failed_check() {
+        let report = simulator().simulate(&create_order_spell(1), &[]).unwrap();
+        assert!(!report.passed);
+
+        let order = report.apps.iter().find(|a| a.alias == "$ORDER").unwrap();
+        assert_eq!(order.contract, Some(Contract::Swap));
+        assert_eq!(order.failed_checks[0], "order.status == OrderStatus::Open");
+        assert!(order.failed_checks.len() > 1);
+    }
+
+    #[test]
+    fn test_unregistered_token_is_simple_transfer() {
+        let report = Simulator::new().simulate(&create_order_spell(0), &[]).unwrap();
+        let offer = report.apps.iter().find(|a| a.alias == "$OFFER").unwrap();
+        assert_eq!(offer.contract, None);
+        assert!(offer.passed);
+    }
+
+    #[test]
+    fn test_unknown_alias_is_an_error() {
+        let spell = create_order_spell(0).replace("public_inputs:\n  $ORDER", "public_inputs:\n  $NOPE");
+        assert!(parse_spell(&spell, &[]).is_err());
+    }
+}
@@ -0,0 +1,91 @@
+//! Liquid Nation Spell Simulator CLI
+//!
+//! Usage:
+//!   spell-sim <spell.yaml> [--prev-tx <hex|@file>]... [--swap-vk <hex>] [--escrow-vk <hex>] [--json]
+//!
+//! Exits with status 1 if any app contract rejects the spell.
+
+use anyhow::{bail, Context, Result};
+use charms_sdk::data::B32;
+use liquid_spell_simulator::{Contract, Simulator};
+use std::str::FromStr;
+
+const USAGE: &str = "usage: spell-sim <spell.yaml> [--prev-tx <hex|@file>]... \
+                     [--swap-vk <hex>] [--escrow-vk <hex>] [--json]";
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let mut spell_path = None;
+    let mut prev_txs = Vec::new();
+    let mut simulator = Simulator::new();
+    let mut json = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--prev-tx" => prev_txs.push(read_arg(args.next())?),
+            "--swap-vk" => simulator = simulator.with_contract(vk_arg(args.next())?, Contract::Swap),
+            "--escrow-vk" => {
+                simulator = simulator.with_contract(vk_arg(args.next())?, Contract::Escrow)
+            }
+            "--json" => json = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ if spell_path.is_none() && !arg.starts_with("--") => spell_path = Some(arg),
+            _ => bail!("unexpected argument '{}'\n{}", arg, USAGE),
+        }
+    }
+
+    let Some(spell_path) = spell_path else {
+        bail!("{}", USAGE);
+    };
+    let spell = std::fs::read_to_string(&spell_path)
+        .with_context(|| format!("failed to read {}", spell_path))?;
+
+    let report = simulator.simulate(&spell, &prev_txs)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for warning in &report.warnings {
+            println!("warning: {}", warning);
+        }
+        for app in &report.apps {
+            let runner = match app.contract {
+                Some(Contract::Swap) => "swap contract",
+                Some(Contract::Escrow) => "escrow contract",
+                None => "simple transfer",
+            };
+            let verdict = if app.passed { "ok" } else { "FAILED" };
+            println!("{} {} ({}): {}", app.alias, app.app, runner, verdict);
+            for check in &app.failed_checks {
+                println!("    check failed: {}", check);
+            }
+        }
+    }
+
+    if !report.passed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Read a literal value, or a file's contents for `@path`
+fn read_arg(arg: Option<String>) -> Result<String> {
+    let Some(arg) = arg else {
+        bail!("missing value\n{}", USAGE);
+    };
+    match arg.strip_prefix('@') {
+        Some(path) => Ok(std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path))?
+            .trim()
+            .to_string()),
+        None => Ok(arg),
+    }
+}
+
+fn vk_arg(arg: Option<String>) -> Result<B32> {
+    let vk = read_arg(arg)?;
+    B32::from_str(&vk).with_context(|| format!("invalid vk '{}'", vk))
+}